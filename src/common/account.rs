@@ -0,0 +1,488 @@
+use std::fmt;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU8, Ordering};
+use dashmap::DashMap;
+use log::info;
+use sonic_rs::{from_str, json, Deserialize, Serialize};
+use crate::common::rest_api::OkxResponse;
+use crate::common::utils::{str_to_f64, HttpClientSimulation};
+
+/// positions 频道 / GET /api/v5/account/positions 的单个持仓
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PositionData {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub mgn_mode: String,              // cross / isolated
+    pub pos_id: String,
+    pub pos_side: String,              // net / long / short
+    pub pos: String,                   // 持仓数量，net 模式下有正负
+    pub pos_ccy: String,
+    pub avail_pos: String,
+    pub avg_px: String,
+    pub mark_px: String,
+    pub upl: String,
+    pub upl_ratio: String,
+    pub lever: String,
+    pub liq_px: String,
+    pub imr: String,
+    pub margin: String,
+    pub mgn_ratio: String,
+    pub mmr: String,
+    pub notional_usd: String,
+    pub ccy: String,
+    pub realized_pnl: String,
+    pub u_time: String,
+    pub c_time: String,
+}
+
+/// account 频道 / GET /api/v5/account/balance 的账户信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AccountData {
+    pub u_time: String,
+    pub total_eq: String,
+    pub iso_eq: String,
+    pub adj_eq: String,
+    pub imr: String,
+    pub mmr: String,
+    pub mgn_ratio: String,
+    pub notional_usd: String,
+    pub upl: String,
+    pub details: Vec<BalanceDetail>,
+}
+
+/// 单币种资产明细
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BalanceDetail {
+    pub ccy: String,
+    pub eq: String,
+    pub cash_bal: String,
+    pub avail_eq: String,
+    pub avail_bal: String,
+    pub frozen_bal: String,
+    pub ord_frozen: String,
+    pub upl: String,
+    pub eq_usd: String,
+    pub u_time: String,
+}
+
+/// balance_and_position 频道推送
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BalanceAndPositionData {
+    pub p_time: String,
+    pub event_type: String,
+    pub bal_data: Vec<BalData>,
+    pub pos_data: Vec<PosData>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BalData {
+    pub ccy: String,
+    pub cash_bal: String,
+    pub u_time: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PosData {
+    pub pos_id: String,
+    pub inst_id: String,
+    pub inst_type: String,
+    pub mgn_mode: String,
+    pub pos_side: String,
+    pub pos: String,
+    pub ccy: String,
+    pub avg_px: String,
+    pub u_time: String,
+}
+
+/// 本地持仓
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Position {
+    pub inst_id: String,
+    pub inst_type: String,
+    pub mgn_mode: String,
+    pub pos_side: String,
+    pub pos: f64,
+    pub avg_px: f64,
+    pub mark_px: f64,
+    pub upl: f64,
+    pub mgn_ratio: f64,
+    /// 强平价，OKX 返回空字符串时为 None
+    pub liq_px: Option<f64>,
+    pub lever: f64,
+    pub u_time: u64,
+}
+
+/// 本地单币种余额
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Balance {
+    pub ccy: String,
+    pub eq: f64,
+    pub cash_bal: f64,
+    pub avail_bal: f64,
+    pub avail_eq: f64,
+    pub frozen_bal: f64,
+    pub upl: f64,
+    pub u_time: u64,
+}
+
+/// 账户汇总
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub total_eq: f64,
+    pub adj_eq: f64,
+    pub imr: f64,
+    pub mmr: f64,
+    pub mgn_ratio: f64,
+    pub upl: f64,
+    pub u_time: u64,
+}
+
+/// 单个账户的持仓和余额缓存，由 positions / account / balance_and_position 频道维护
+#[derive(Default)]
+pub struct AccountState {
+    /// (instId, posSide) -> 持仓
+    positions: DashMap<(String, String), Position>,
+    /// ccy -> 余额
+    balances: DashMap<String, Balance>,
+    summary: RwLock<AccountSummary>,
+}
+
+fn parse_ms(value: &str) -> u64 {
+    value.parse::<u64>().unwrap_or(0)
+}
+
+impl AccountState {
+    pub fn new() -> AccountState {
+        AccountState::default()
+    }
+
+    /// 处理 positions 频道推送或 REST 持仓查询结果，持仓为 0 的移除
+    pub fn on_positions(&self, data: &[PositionData]) {
+        for p in data.iter() {
+            let key = (p.inst_id.clone(), p.pos_side.clone());
+            let u_time = parse_ms(&p.u_time);
+            if let Some(old) = self.positions.get(&key)
+                && old.u_time > u_time
+            {
+                continue;
+            }
+            let pos = str_to_f64(&p.pos);
+            if pos == 0.0 {
+                self.positions.remove(&key);
+                continue;
+            }
+            let position = Position {
+                inst_id: p.inst_id.clone(),
+                inst_type: p.inst_type.clone(),
+                mgn_mode: p.mgn_mode.clone(),
+                pos_side: p.pos_side.clone(),
+                pos,
+                avg_px: str_to_f64(&p.avg_px),
+                mark_px: str_to_f64(&p.mark_px),
+                upl: str_to_f64(&p.upl),
+                mgn_ratio: str_to_f64(&p.mgn_ratio),
+                liq_px: p.liq_px.parse::<f64>().ok(),
+                lever: str_to_f64(&p.lever),
+                u_time,
+            };
+            self.positions.insert(key, position);
+        }
+    }
+
+    /// 用 REST 持仓快照替换本地持仓：快照里没有、且在 snapshot_ms 之前更新的持仓视为已平掉
+    pub fn replace_positions(&self, data: &[PositionData], snapshot_ms: u64) {
+        self.positions.retain(|key, p| {
+            p.u_time >= snapshot_ms || data.iter().any(|d| d.inst_id == key.0 && d.pos_side == key.1)
+        });
+        self.on_positions(data);
+    }
+
+    /// 处理 account 频道推送或 REST 余额查询结果
+    pub fn on_account(&self, data: &[AccountData]) {
+        for a in data.iter() {
+            {
+                let mut summary = self.summary.write().unwrap();
+                let u_time = parse_ms(&a.u_time);
+                if u_time >= summary.u_time {
+                    *summary = AccountSummary {
+                        total_eq: str_to_f64(&a.total_eq),
+                        adj_eq: str_to_f64(&a.adj_eq),
+                        imr: str_to_f64(&a.imr),
+                        mmr: str_to_f64(&a.mmr),
+                        mgn_ratio: str_to_f64(&a.mgn_ratio),
+                        upl: str_to_f64(&a.upl),
+                        u_time,
+                    };
+                }
+            }
+            for d in a.details.iter() {
+                let u_time = parse_ms(&d.u_time);
+                if let Some(old) = self.balances.get(&d.ccy)
+                    && old.u_time > u_time
+                {
+                    continue;
+                }
+                let balance = Balance {
+                    ccy: d.ccy.clone(),
+                    eq: str_to_f64(&d.eq),
+                    cash_bal: str_to_f64(&d.cash_bal),
+                    avail_bal: str_to_f64(&d.avail_bal),
+                    avail_eq: str_to_f64(&d.avail_eq),
+                    frozen_bal: str_to_f64(&d.frozen_bal),
+                    upl: str_to_f64(&d.upl),
+                    u_time,
+                };
+                self.balances.insert(d.ccy.clone(), balance);
+            }
+        }
+    }
+
+    /// 处理 balance_and_position 频道推送，只更新其中携带的字段
+    pub fn on_balance_and_position(&self, data: &[BalanceAndPositionData]) {
+        for bp in data.iter() {
+            for b in bp.bal_data.iter() {
+                let u_time = parse_ms(&b.u_time);
+                let mut balance = self.balances.entry(b.ccy.clone()).or_insert_with(|| Balance {
+                    ccy: b.ccy.clone(),
+                    ..Balance::default()
+                });
+                if balance.u_time > u_time {
+                    continue;
+                }
+                balance.cash_bal = str_to_f64(&b.cash_bal);
+                balance.u_time = u_time;
+            }
+            for p in bp.pos_data.iter() {
+                let key = (p.inst_id.clone(), p.pos_side.clone());
+                let pos = str_to_f64(&p.pos);
+                if pos == 0.0 {
+                    self.positions.remove(&key);
+                    continue;
+                }
+                let u_time = parse_ms(&p.u_time);
+                let mut position = self.positions.entry(key).or_insert_with(|| Position {
+                    inst_id: p.inst_id.clone(),
+                    inst_type: p.inst_type.clone(),
+                    mgn_mode: p.mgn_mode.clone(),
+                    pos_side: p.pos_side.clone(),
+                    ..Position::default()
+                });
+                if position.u_time > u_time {
+                    continue;
+                }
+                position.pos = pos;
+                position.avg_px = str_to_f64(&p.avg_px);
+                position.u_time = u_time;
+            }
+        }
+    }
+
+    pub fn position(&self, inst_id: &str, pos_side: &str) -> Option<Position> {
+        self.positions
+            .get(&(inst_id.to_string(), pos_side.to_string()))
+            .map(|p| p.clone())
+    }
+
+    /// 某个产品的净持仓（long 为正，short 为负，net 模式本身带符号）
+    pub fn net_position(&self, inst_id: &str) -> f64 {
+        self.positions
+            .iter()
+            .filter(|p| p.inst_id == inst_id)
+            .map(|p| if p.pos_side == "short" { -p.pos.abs() } else { p.pos })
+            .sum()
+    }
+
+    pub fn positions(&self) -> Vec<Position> {
+        self.positions.iter().map(|p| p.clone()).collect()
+    }
+
+    pub fn balance(&self, ccy: &str) -> Option<Balance> {
+        self.balances.get(ccy).map(|b| b.clone())
+    }
+
+    pub fn balances(&self) -> Vec<Balance> {
+        self.balances.iter().map(|b| b.clone()).collect()
+    }
+
+    pub fn summary(&self) -> AccountSummary {
+        self.summary.read().unwrap().clone()
+    }
+}
+
+/// GET /api/v5/account/positions
+pub async fn fetch_positions(inst_type: Option<&str>) -> Result<Vec<PositionData>, Box<dyn std::error::Error>> {
+    let params = inst_type.map(|t| vec![("instType", t)]);
+    let response = HttpClientSimulation::get("/api/v5/account/positions", params.as_deref()).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<PositionData>>(&text)?.into_result()
+}
+
+/// GET /api/v5/account/balance
+pub async fn fetch_balance(ccy: Option<&str>) -> Result<Vec<AccountData>, Box<dyn std::error::Error>> {
+    let params = ccy.map(|c| vec![("ccy", c)]);
+    let response = HttpClientSimulation::get("/api/v5/account/balance", params.as_deref()).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AccountData>>(&text)?.into_result()
+}
+
+/// 持仓模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PosMode {
+    /// 单向持仓（买卖模式），posSide 固定为 net
+    Net,
+    /// 双向持仓（开平仓模式），posSide 为 long / short
+    LongShort,
+}
+
+impl PosMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PosMode::Net => "net_mode",
+            PosMode::LongShort => "long_short_mode",
+        }
+    }
+
+    pub fn from_okx(value: &str) -> Option<PosMode> {
+        match value {
+            "net_mode" => Some(PosMode::Net),
+            "long_short_mode" => Some(PosMode::LongShort),
+            _ => None,
+        }
+    }
+
+    /// 下单时的 posSide：双向持仓下买开多、卖开空、卖平多、买平空
+    pub fn pos_side(&self, side: &str, close: bool) -> &'static str {
+        match (self, side, close) {
+            (PosMode::Net, _, _) => "net",
+            (PosMode::LongShort, "buy", false) | (PosMode::LongShort, "sell", true) => "long",
+            (PosMode::LongShort, _, _) => "short",
+        }
+    }
+}
+
+impl fmt::Display for PosMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// 当前账户的持仓模式，启动时从 /account/config 获取，默认单向持仓
+static POS_MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn get_pos_mode() -> PosMode {
+    match POS_MODE.load(Ordering::Relaxed) {
+        1 => PosMode::LongShort,
+        _ => PosMode::Net,
+    }
+}
+
+pub fn set_pos_mode(mode: PosMode) {
+    let value = match mode {
+        PosMode::Net => 0,
+        PosMode::LongShort => 1,
+    };
+    POS_MODE.store(value, Ordering::Relaxed);
+}
+
+/// GET /api/v5/account/config 的账户配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AccountConfig {
+    pub uid: String,
+    pub main_uid: String,
+    /// 账户模式 1: 现货 2: 合约 3: 跨币种保证金 4: 组合保证金
+    pub acct_lv: String,
+    pub pos_mode: String,
+    pub auto_loan: bool,
+    pub greeks_type: String,
+    pub level: String,
+    pub ct_iso_mode: String,
+    pub mgn_iso_mode: String,
+    pub label: String,
+    pub perm: String,
+}
+
+/// GET /api/v5/account/config
+pub async fn fetch_account_config() -> Result<AccountConfig, Box<dyn std::error::Error>> {
+    let response = HttpClientSimulation::get("/api/v5/account/config", None).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AccountConfig>>(&text)?
+        .into_result()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "account config empty".into())
+}
+
+/// 获取账户配置并记录持仓模式，启动时调用
+pub async fn load_pos_mode() -> Result<PosMode, Box<dyn std::error::Error>> {
+    let config = fetch_account_config().await?;
+    let mode = PosMode::from_okx(&config.pos_mode).ok_or_else(|| format!("unknown posMode {}", config.pos_mode))?;
+    set_pos_mode(mode);
+    info!("账户 {} 持仓模式 {}", config.uid, mode);
+    Ok(mode)
+}
+
+/// POST /api/v5/account/set-position-mode，有持仓或挂单时 OKX 会拒绝
+pub async fn set_position_mode(mode: PosMode) -> Result<(), Box<dyn std::error::Error>> {
+    let response = HttpClientSimulation::post("/api/v5/account/set-position-mode", json!({"posMode": mode.as_str()})).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<sonic_rs::Value>>(&text)?.into_result()?;
+    set_pos_mode(mode);
+    info!("持仓模式已切换为 {}", mode);
+    Ok(())
+}
+
+#[cfg(test)]
+mod account_test {
+    use sonic_rs::from_str;
+    use crate::common::ws_api::ChannelData;
+    use super::*;
+
+    #[test]
+    fn test_positions_and_balance() {
+        let state = AccountState::new();
+        let push = from_str::<ChannelData<PositionData>>(r#"{"arg":{"channel":"positions","instType":"ANY"},"data":[{"instType":"SWAP","instId":"BTC-USDT-SWAP","mgnMode":"cross","posSide":"net","pos":"-2","avgPx":"50000","upl":"1.5","lever":"10","liqPx":"","mgnRatio":"12.3","uTime":"1700000000000"}]}"#).unwrap();
+        state.on_positions(&push.data);
+        let p = state.position("BTC-USDT-SWAP", "net").unwrap();
+        assert_eq!(p.pos, -2.0);
+        assert_eq!(p.liq_px, None);
+        assert_eq!(state.net_position("BTC-USDT-SWAP"), -2.0);
+
+        let push = from_str::<ChannelData<BalanceAndPositionData>>(r#"{"arg":{"channel":"balance_and_position"},"data":[{"pTime":"1700000000001","eventType":"filled","balData":[{"ccy":"USDT","cashBal":"1000","uTime":"1700000000001"}],"posData":[{"instId":"BTC-USDT-SWAP","posSide":"net","pos":"0","avgPx":"","uTime":"1700000000001"}]}]}"#).unwrap();
+        state.on_balance_and_position(&push.data);
+        assert!(state.position("BTC-USDT-SWAP", "net").is_none());
+        assert_eq!(state.balance("USDT").unwrap().cash_bal, 1000.0);
+
+        let push = from_str::<ChannelData<AccountData>>(r#"{"arg":{"channel":"account"},"data":[{"uTime":"1700000000002","totalEq":"1200","mgnRatio":"","details":[{"ccy":"USDT","eq":"1200","cashBal":"1000","availBal":"900","uTime":"1700000000002"}]}]}"#).unwrap();
+        state.on_account(&push.data);
+        assert_eq!(state.summary().total_eq, 1200.0);
+        assert_eq!(state.balance("USDT").unwrap().avail_bal, 900.0);
+    }
+
+    #[test]
+    fn test_replace_positions() {
+        let state = AccountState::new();
+        let data = from_str::<Vec<PositionData>>(r#"[{"instId":"BTC-USDT-SWAP","posSide":"net","pos":"2","uTime":"1000"},{"instId":"ETH-USDT-SWAP","posSide":"net","pos":"1","uTime":"1000"}]"#).unwrap();
+        state.on_positions(&data);
+        // 快照里只剩 BTC，ETH 在断线期间被平掉
+        state.replace_positions(&data[..1], 2000);
+        assert!(state.position("ETH-USDT-SWAP", "net").is_none());
+        assert_eq!(state.position("BTC-USDT-SWAP", "net").unwrap().pos, 2.0);
+    }
+
+    #[test]
+    fn test_pos_side() {
+        assert_eq!(PosMode::Net.pos_side("buy", true), "net");
+        assert_eq!(PosMode::LongShort.pos_side("buy", false), "long");
+        assert_eq!(PosMode::LongShort.pos_side("sell", false), "short");
+        assert_eq!(PosMode::LongShort.pos_side("sell", true), "long");
+        assert_eq!(PosMode::LongShort.pos_side("buy", true), "short");
+        assert_eq!(PosMode::from_okx("long_short_mode"), Some(PosMode::LongShort));
+    }
+}
@@ -0,0 +1,575 @@
+use std::fmt;
+use dashmap::DashMap;
+use sonic_rs::{from_str, json, to_value, Deserialize, Serialize};
+use crate::common::account::AccountState;
+use crate::common::instrument::{Instrument, INSTRUMENT_REGISTRY};
+use crate::common::order_store::OrderStore;
+use crate::common::risk::{OrderIntent, RiskGate};
+use crate::common::rest_api::OkxResponse;
+use crate::common::utils::{str_to_f64, HttpClientSimulation};
+use crate::common::ws_api::OrderType;
+
+/// 私有频道：策略委托订单
+pub const CHANNEL_ORDERS_ALGO: &str = "orders-algo";
+/// 私有频道：高级策略委托订单（冰山、时间加权、移动止盈止损）
+pub const CHANNEL_ALGO_ADVANCE: &str = "algo-advance";
+
+pub struct AlgoOrdType;
+impl AlgoOrdType {
+    /// 单向止盈止损
+    pub const CONDITIONAL: &'static str = "conditional";
+    /// 双向止盈止损
+    pub const OCO: &'static str = "oco";
+    /// 计划委托
+    pub const TRIGGER: &'static str = "trigger";
+    /// 移动止盈止损
+    pub const MOVE_ORDER_STOP: &'static str = "move_order_stop";
+    /// 冰山委托
+    pub const ICEBERG: &'static str = "iceberg";
+    /// 时间加权委托
+    pub const TWAP: &'static str = "twap";
+}
+
+/// 触发价类型
+pub struct TriggerPxType;
+impl TriggerPxType {
+    pub const LAST: &'static str = "last";
+    pub const INDEX: &'static str = "index";
+    pub const MARK: &'static str = "mark";
+}
+
+/// POST /api/v5/trade/order-algo 的请求体
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlgoOrderRequest {
+    pub inst_id: String,
+    pub td_mode: String,
+    pub side: String,
+    pub ord_type: String,
+    pub sz: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos_side: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algo_cl_ord_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduce_only: Option<bool>,
+    // 止盈止损（conditional / oco）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tp_trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tp_ord_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tp_trigger_px_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sl_trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sl_ord_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sl_trigger_px_type: Option<String>,
+    // 计划委托（trigger）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_px_type: Option<String>,
+    // 移动止盈止损（move_order_stop）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_ratio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_spread: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_px: Option<String>,
+    // 冰山 / 时间加权（iceberg / twap）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub px_var: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub px_spread: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sz_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub px_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_interval: Option<String>,
+}
+
+impl AlgoOrderRequest {
+    fn base(ord_type: &str, inst_id: &str, td_mode: &str, side: &str, sz: &str) -> AlgoOrderRequest {
+        AlgoOrderRequest {
+            inst_id: inst_id.to_string(),
+            td_mode: td_mode.to_string(),
+            side: side.to_string(),
+            ord_type: ord_type.to_string(),
+            sz: sz.to_string(),
+            ..AlgoOrderRequest::default()
+        }
+    }
+
+    /// 单向止盈止损，需再调用 take_profit 或 stop_loss
+    pub fn conditional(inst_id: &str, td_mode: &str, side: &str, sz: &str) -> AlgoOrderRequest {
+        Self::base(AlgoOrdType::CONDITIONAL, inst_id, td_mode, side, sz)
+    }
+
+    /// 双向止盈止损，需同时调用 take_profit 和 stop_loss
+    pub fn oco(inst_id: &str, td_mode: &str, side: &str, sz: &str) -> AlgoOrderRequest {
+        Self::base(AlgoOrdType::OCO, inst_id, td_mode, side, sz)
+    }
+
+    /// 计划委托，order_px 为 "-1" 时触发后以市价成交
+    pub fn trigger(inst_id: &str, td_mode: &str, side: &str, sz: &str, trigger_px: &str, order_px: &str) -> AlgoOrderRequest {
+        let mut req = Self::base(AlgoOrdType::TRIGGER, inst_id, td_mode, side, sz);
+        req.trigger_px = Some(trigger_px.to_string());
+        req.order_px = Some(order_px.to_string());
+        req
+    }
+
+    /// 移动止盈止损，回调比例（如 "0.01"）
+    pub fn move_order_stop(inst_id: &str, td_mode: &str, side: &str, sz: &str, callback_ratio: &str) -> AlgoOrderRequest {
+        let mut req = Self::base(AlgoOrdType::MOVE_ORDER_STOP, inst_id, td_mode, side, sz);
+        req.callback_ratio = Some(callback_ratio.to_string());
+        req
+    }
+
+    /// 冰山委托，px_var 为距离买一卖一的比例
+    pub fn iceberg(inst_id: &str, td_mode: &str, side: &str, sz: &str, px_var: &str, sz_limit: &str, px_limit: &str) -> AlgoOrderRequest {
+        let mut req = Self::base(AlgoOrdType::ICEBERG, inst_id, td_mode, side, sz);
+        req.px_var = Some(px_var.to_string());
+        req.sz_limit = Some(sz_limit.to_string());
+        req.px_limit = Some(px_limit.to_string());
+        req
+    }
+
+    /// 时间加权委托，需再调用 time_interval 设置下单间隔
+    pub fn twap(inst_id: &str, td_mode: &str, side: &str, sz: &str, px_var: &str, sz_limit: &str, px_limit: &str) -> AlgoOrderRequest {
+        let mut req = Self::iceberg(inst_id, td_mode, side, sz, px_var, sz_limit, px_limit);
+        req.ord_type = AlgoOrdType::TWAP.to_string();
+        req
+    }
+
+    /// 时间加权委托的下单间隔（秒）
+    pub fn time_interval(mut self, seconds: &str) -> AlgoOrderRequest {
+        self.time_interval = Some(seconds.to_string());
+        self
+    }
+
+    /// 止盈，ord_px 为 "-1" 时市价
+    pub fn take_profit(mut self, trigger_px: &str, ord_px: &str) -> AlgoOrderRequest {
+        self.tp_trigger_px = Some(trigger_px.to_string());
+        self.tp_ord_px = Some(ord_px.to_string());
+        self
+    }
+
+    /// 止损，ord_px 为 "-1" 时市价
+    pub fn stop_loss(mut self, trigger_px: &str, ord_px: &str) -> AlgoOrderRequest {
+        self.sl_trigger_px = Some(trigger_px.to_string());
+        self.sl_ord_px = Some(ord_px.to_string());
+        self
+    }
+
+    /// 移动止盈止损改用回调价距
+    pub fn callback_spread(mut self, callback_spread: &str) -> AlgoOrderRequest {
+        self.callback_ratio = None;
+        self.callback_spread = Some(callback_spread.to_string());
+        self
+    }
+
+    /// 移动止盈止损的激活价格
+    pub fn active_px(mut self, active_px: &str) -> AlgoOrderRequest {
+        self.active_px = Some(active_px.to_string());
+        self
+    }
+
+    /// 冰山 / 时间加权改用价距
+    pub fn px_spread(mut self, px_spread: &str) -> AlgoOrderRequest {
+        self.px_var = None;
+        self.px_spread = Some(px_spread.to_string());
+        self
+    }
+
+    pub fn pos_side(mut self, pos_side: &str) -> AlgoOrderRequest {
+        self.pos_side = Some(pos_side.to_string());
+        self
+    }
+
+    pub fn algo_cl_ord_id(mut self, algo_cl_ord_id: &str) -> AlgoOrderRequest {
+        self.algo_cl_ord_id = Some(algo_cl_ord_id.to_string());
+        self
+    }
+
+    pub fn reduce_only(mut self, reduce_only: bool) -> AlgoOrderRequest {
+        self.reduce_only = Some(reduce_only);
+        self
+    }
+
+    /// 风控检查用的下单意图：触发价与当前价格可以相差很远，按市价单处理不做价格偏离检查
+    pub fn intent(&self) -> OrderIntent {
+        OrderIntent {
+            inst_id: self.inst_id.clone(),
+            side: self.side.clone(),
+            td_mode: self.td_mode.clone(),
+            ord_type: OrderType::MARKET.to_string(),
+            px: None,
+            sz: self.sz.clone(),
+            reduce_only: self.reduce_only.unwrap_or(false),
+            pos_side: self.pos_side.clone().unwrap_or_default(),
+        }
+    }
+
+    /// 按 INSTRUMENT_REGISTRY 中的产品校验，产品不存在时返回错误
+    pub fn validate(&self) -> Result<(), AlgoOrderError> {
+        match INSTRUMENT_REGISTRY.get(&self.inst_id) {
+            Some(instrument) => self.validate_with(&instrument),
+            None => Err(AlgoOrderError::UnknownInstrument(self.inst_id.clone())),
+        }
+    }
+
+    /// 按产品限制校验：必填字段、最小下单量和各类策略委托的最大数量
+    pub fn validate_with(&self, instrument: &Instrument) -> Result<(), AlgoOrderError> {
+        if self.inst_id != instrument.inst_id {
+            return Err(AlgoOrderError::InstrumentMismatch(self.inst_id.clone(), instrument.inst_id.clone()));
+        }
+        let sz = str_to_f64(&self.sz);
+        if sz <= 0.0 || sz < str_to_f64(&instrument.min_sz) {
+            return Err(AlgoOrderError::BelowMinSize(self.sz.clone(), instrument.min_sz.clone()));
+        }
+        let max_sz = match self.ord_type.as_str() {
+            AlgoOrdType::CONDITIONAL => {
+                if self.tp_trigger_px.is_none() && self.sl_trigger_px.is_none() {
+                    return Err(AlgoOrderError::MissingField("tpTriggerPx/slTriggerPx"));
+                }
+                &instrument.max_stop_sz
+            }
+            AlgoOrdType::OCO => {
+                if self.tp_trigger_px.is_none() {
+                    return Err(AlgoOrderError::MissingField("tpTriggerPx"));
+                }
+                if self.sl_trigger_px.is_none() {
+                    return Err(AlgoOrderError::MissingField("slTriggerPx"));
+                }
+                &instrument.max_stop_sz
+            }
+            AlgoOrdType::TRIGGER => {
+                if self.trigger_px.is_none() {
+                    return Err(AlgoOrderError::MissingField("triggerPx"));
+                }
+                if self.order_px.is_none() {
+                    return Err(AlgoOrderError::MissingField("orderPx"));
+                }
+                &instrument.max_trigger_sz
+            }
+            AlgoOrdType::MOVE_ORDER_STOP => {
+                if self.callback_ratio.is_none() && self.callback_spread.is_none() {
+                    return Err(AlgoOrderError::MissingField("callbackRatio/callbackSpread"));
+                }
+                &instrument.max_stop_sz
+            }
+            AlgoOrdType::ICEBERG | AlgoOrdType::TWAP => {
+                if self.px_var.is_none() && self.px_spread.is_none() {
+                    return Err(AlgoOrderError::MissingField("pxVar/pxSpread"));
+                }
+                if self.sz_limit.is_none() {
+                    return Err(AlgoOrderError::MissingField("szLimit"));
+                }
+                if self.px_limit.is_none() {
+                    return Err(AlgoOrderError::MissingField("pxLimit"));
+                }
+                if self.ord_type == AlgoOrdType::TWAP {
+                    if self.time_interval.is_none() {
+                        return Err(AlgoOrderError::MissingField("timeInterval"));
+                    }
+                    &instrument.max_twap_sz
+                } else {
+                    &instrument.max_iceberg_sz
+                }
+            }
+            other => return Err(AlgoOrderError::UnknownOrdType(other.to_string())),
+        };
+        if !max_sz.is_empty() && sz > str_to_f64(max_sz) {
+            return Err(AlgoOrderError::AboveMaxSize(self.sz.clone(), max_sz.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// 策略委托本地校验失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlgoOrderError {
+    InstrumentMismatch(String, String),
+    UnknownInstrument(String),
+    UnknownOrdType(String),
+    MissingField(&'static str),
+    BelowMinSize(String, String),
+    AboveMaxSize(String, String),
+}
+
+impl fmt::Display for AlgoOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlgoOrderError::InstrumentMismatch(req, inst) => write!(f, "instId {} 与产品 {} 不一致", req, inst),
+            AlgoOrderError::UnknownInstrument(inst_id) => write!(f, "产品 {} 不存在", inst_id),
+            AlgoOrderError::UnknownOrdType(t) => write!(f, "未知的策略委托类型 {}", t),
+            AlgoOrderError::MissingField(field) => write!(f, "缺少字段 {}", field),
+            AlgoOrderError::BelowMinSize(sz, min) => write!(f, "数量 {} 小于最小下单量 {}", sz, min),
+            AlgoOrderError::AboveMaxSize(sz, max) => write!(f, "数量 {} 超过最大数量 {}", sz, max),
+        }
+    }
+}
+
+impl std::error::Error for AlgoOrderError {}
+
+/// order-algo / cancel-algos / amend-algos 的返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AlgoAck {
+    pub algo_id: String,
+    pub algo_cl_ord_id: String,
+    pub req_id: String,
+    pub s_code: String,
+    pub s_msg: String,
+}
+
+/// POST /api/v5/trade/amend-algos 的请求体，只支持 conditional / oco / trigger
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmendAlgoRequest {
+    pub inst_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algo_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algo_cl_ord_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_sz: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_tp_trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_tp_ord_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_sl_trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_sl_ord_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_trigger_px: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_ord_px: Option<String>,
+}
+
+/// 策略委托订单：orders-algo / algo-advance 推送，以及 orders-algo-pending / history 查询结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AlgoOrderData {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub algo_id: String,
+    pub algo_cl_ord_id: String,
+    pub ord_id: String,
+    pub ord_type: String,
+    pub side: String,
+    pub pos_side: String,
+    pub td_mode: String,
+    pub sz: String,
+    /// live / pause / partially_effective / effective / canceled / order_failed
+    pub state: String,
+    pub trigger_px: String,
+    pub ord_px: String,
+    pub tp_trigger_px: String,
+    pub tp_ord_px: String,
+    pub sl_trigger_px: String,
+    pub sl_ord_px: String,
+    pub actual_sz: String,
+    pub actual_px: String,
+    pub actual_side: String,
+    pub trigger_time: String,
+    pub callback_ratio: String,
+    pub callback_spread: String,
+    pub active_px: String,
+    pub move_trigger_px: String,
+    pub px_var: String,
+    pub px_spread: String,
+    pub sz_limit: String,
+    pub px_limit: String,
+    pub time_interval: String,
+    pub reduce_only: String,
+    pub c_time: String,
+    pub u_time: String,
+}
+
+impl AlgoOrderData {
+    /// 仍在等待触发或执行中
+    pub fn is_open(&self) -> bool {
+        matches!(self.state.as_str(), "live" | "pause" | "partially_effective")
+    }
+}
+
+/// 策略委托订单的本地存储，按 algoId 维护
+#[derive(Default)]
+pub struct AlgoOrderStore {
+    orders: DashMap<String, AlgoOrderData>,
+}
+
+impl AlgoOrderStore {
+    pub fn new() -> AlgoOrderStore {
+        AlgoOrderStore::default()
+    }
+
+    /// 处理 orders-algo / algo-advance 推送或查询结果
+    pub fn on_update(&self, data: &[AlgoOrderData]) {
+        for algo in data.iter() {
+            let u_time = algo.u_time.parse::<u64>().unwrap_or(0);
+            if let Some(old) = self.orders.get(&algo.algo_id)
+                && old.u_time.parse::<u64>().unwrap_or(0) > u_time
+            {
+                continue;
+            }
+            self.orders.insert(algo.algo_id.clone(), algo.clone());
+        }
+    }
+
+    pub fn get(&self, algo_id: &str) -> Option<AlgoOrderData> {
+        self.orders.get(algo_id).map(|a| a.clone())
+    }
+
+    pub fn open_orders(&self, inst_id: Option<&str>) -> Vec<AlgoOrderData> {
+        self.orders
+            .iter()
+            .filter(|a| a.is_open())
+            .filter(|a| inst_id.is_none_or(|id| a.inst_id == id))
+            .map(|a| a.clone())
+            .collect()
+    }
+
+    /// 清理已终结（已触发生效、已撤销、委托失败）的策略委托，返回清理数量
+    pub fn purge_terminal(&self) -> usize {
+        let before = self.orders.len();
+        self.orders.retain(|_, a| a.is_open());
+        before.saturating_sub(self.orders.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+}
+
+/// POST /api/v5/trade/order-algo，只能经 place_algo_order_checked 调用
+async fn place_algo_order(req: &AlgoOrderRequest) -> Result<Vec<AlgoAck>, Box<dyn std::error::Error>> {
+    let response = HttpClientSimulation::post("/api/v5/trade/order-algo", to_value(req)?).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AlgoAck>>(&text)?.into_result()
+}
+
+/// 按产品注册表校验并经过风控后下策略委托单，停止交易或产品暂停时拒绝
+pub async fn place_algo_order_checked(req: &AlgoOrderRequest, risk: &RiskGate, account: &AccountState, orders: &OrderStore) -> Result<Vec<AlgoAck>, Box<dyn std::error::Error>> {
+    req.validate()?;
+    risk.check(&req.intent(), account, orders)?;
+    place_algo_order(req).await
+}
+
+/// POST /api/v5/trade/cancel-algos，参数为 (instId, algoId) 列表，单次最多 10 个
+pub async fn cancel_algos(algos: &[(&str, &str)]) -> Result<Vec<AlgoAck>, Box<dyn std::error::Error>> {
+    let body = algos
+        .iter()
+        .map(|(inst_id, algo_id)| json!({"instId": inst_id, "algoId": algo_id}))
+        .collect::<Vec<_>>();
+    let response = HttpClientSimulation::post("/api/v5/trade/cancel-algos", json!(body)).await?;
+    let text = response.text().await?;
+    let result = from_str::<OkxResponse<AlgoAck>>(&text)?;
+    // 部分失败时 code 为 1 / 2，逐条结果在 data 的 sCode 中
+    if result.code != "0" && result.data.is_empty() {
+        return Err(format!("okx error code {} msg {}", result.code, result.msg).into());
+    }
+    Ok(result.data)
+}
+
+/// POST /api/v5/trade/amend-algos
+pub async fn amend_algos(req: &AmendAlgoRequest) -> Result<Vec<AlgoAck>, Box<dyn std::error::Error>> {
+    let response = HttpClientSimulation::post("/api/v5/trade/amend-algos", to_value(req)?).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AlgoAck>>(&text)?.into_result()
+}
+
+/// GET /api/v5/trade/orders-algo-pending，ord_type 可用逗号组合 conditional,oco
+pub async fn orders_algo_pending(ord_type: &str, inst_id: Option<&str>) -> Result<Vec<AlgoOrderData>, Box<dyn std::error::Error>> {
+    let mut params = vec![("ordType", ord_type)];
+    if let Some(inst_id) = inst_id {
+        params.push(("instId", inst_id));
+    }
+    let response = HttpClientSimulation::get("/api/v5/trade/orders-algo-pending", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AlgoOrderData>>(&text)?.into_result()
+}
+
+/// GET /api/v5/trade/orders-algo-history，state 为 effective / canceled / order_failed
+pub async fn orders_algo_history(ord_type: &str, state: &str, inst_id: Option<&str>) -> Result<Vec<AlgoOrderData>, Box<dyn std::error::Error>> {
+    let mut params = vec![("ordType", ord_type), ("state", state)];
+    if let Some(inst_id) = inst_id {
+        params.push(("instId", inst_id));
+    }
+    let response = HttpClientSimulation::get("/api/v5/trade/orders-algo-history", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AlgoOrderData>>(&text)?.into_result()
+}
+
+#[cfg(test)]
+mod algo_test {
+    use sonic_rs::to_string;
+    use crate::common::instrument::{InstrumentRegistry, INSTRUMENTS_PATH};
+    use crate::common::ws_api::{Side, TdMode};
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        let req = AlgoOrderRequest::oco("BTC-USDT-SWAP", TdMode::CROSS, Side::SELL, "1")
+            .take_profit("60000", "-1")
+            .stop_loss("40000", "-1");
+        let text = to_string(&req).unwrap();
+        assert!(text.contains(r#""ordType":"oco""#));
+        assert!(text.contains(r#""tpTriggerPx":"60000""#));
+        assert!(!text.contains("triggerPx\":null"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let registry = InstrumentRegistry::load(INSTRUMENTS_PATH).unwrap();
+        let instrument = registry.get("BTC-USDT-SWAP").unwrap();
+        let ok = AlgoOrderRequest::conditional("BTC-USDT-SWAP", TdMode::CROSS, Side::SELL, "1").stop_loss("40000", "-1");
+        assert_eq!(ok.validate_with(&instrument), Ok(()));
+        let missing = AlgoOrderRequest::oco("BTC-USDT-SWAP", TdMode::CROSS, Side::SELL, "1").stop_loss("40000", "-1");
+        assert_eq!(missing.validate_with(&instrument), Err(AlgoOrderError::MissingField("tpTriggerPx")));
+        let too_big = AlgoOrderRequest::trigger("BTC-USDT-SWAP", TdMode::CROSS, Side::BUY, "1000000000000", "50000", "-1");
+        assert!(matches!(too_big.validate_with(&instrument), Err(AlgoOrderError::AboveMaxSize(_, _))));
+        let unknown = AlgoOrderRequest::conditional("NOPE-USDT-SWAP", TdMode::CROSS, Side::SELL, "1").stop_loss("40000", "-1");
+        assert_eq!(unknown.validate(), Err(AlgoOrderError::UnknownInstrument("NOPE-USDT-SWAP".to_string())));
+    }
+
+    #[test]
+    fn test_risk_intent() {
+        use crate::common::risk::{PauseReason, RiskLimits, RiskReject};
+        let req = AlgoOrderRequest::trigger("BTC-USDT-SWAP", TdMode::CROSS, Side::BUY, "3", "50000", "-1");
+        let intent = req.intent();
+        assert_eq!(intent.ord_type, OrderType::MARKET);
+        assert!(intent.px.is_none() && !intent.reduce_only);
+        let gate = RiskGate::new(RiskLimits { max_order_size: Some(2.0), ..RiskLimits::default() });
+        let (account, orders) = (AccountState::new(), OrderStore::new());
+        assert!(matches!(gate.check(&intent, &account, &orders), Err(RiskReject::MaxOrderSize { .. })));
+        gate.pause("BTC-USDT-SWAP", PauseReason::Maintenance, "");
+        assert!(matches!(gate.check(&intent, &account, &orders), Err(RiskReject::TradingPaused { .. })));
+    }
+
+    #[test]
+    fn test_purge_terminal() {
+        let store = AlgoOrderStore::new();
+        let algo = |algo_id: &str, state: &str| AlgoOrderData {
+            algo_id: algo_id.to_string(),
+            inst_id: "BTC-USDT-SWAP".to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        };
+        store.on_update(&[algo("1", "live"), algo("2", "effective"), algo("3", "canceled"), algo("4", "partially_effective")]);
+        assert_eq!(store.purge_terminal(), 2);
+        assert_eq!(store.len(), 2);
+        assert!(store.get("1").is_some() && store.get("2").is_none());
+    }
+}
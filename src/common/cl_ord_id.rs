@@ -0,0 +1,193 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::Utc;
+
+/// 本程序生成的 clOrdId 统一前缀，用于区分外部（网页、其他程序）下的单
+pub const CL_ORD_ID_PREFIX: &str = "ox";
+/// clOrdId 最长 32 位
+pub const CL_ORD_ID_MAX_LEN: usize = 32;
+/// 策略标识最长 8 位
+pub const STRATEGY_TAG_MAX_LEN: usize = 8;
+/// 会话段：进程启动毫秒时间戳的 36 进制，固定 8 位
+const SESSION_LEN: usize = 8;
+/// 序号段：36 进制，固定 6 位
+const SEQ_LEN: usize = 6;
+const SEQ_MAX: u64 = 36u64.pow(SEQ_LEN as u32);
+const BASE36: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+fn to_base36(mut value: u64, width: usize) -> String {
+    let mut buf = vec![b'0'; width];
+    for i in (0..width).rev() {
+        buf[i] = BASE36[(value % 36) as usize];
+        value /= 36;
+    }
+    String::from_utf8(buf).unwrap()
+}
+
+fn from_base36(value: &str) -> Option<u64> {
+    u64::from_str_radix(value, 36).ok()
+}
+
+fn is_alphanumeric(value: &str) -> bool {
+    value.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// 策略标识不合法：为空、超过 8 位或包含字母数字以外的字符
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTag(pub String);
+
+impl fmt::Display for InvalidTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "策略标识 {:?} 只能是 1..={} 位字母数字", self.0, STRATEGY_TAG_MAX_LEN)
+    }
+}
+
+impl std::error::Error for InvalidTag {}
+
+/// 检查策略标识，可在注册策略时提前调用
+pub fn validate_tag(tag: &str) -> Result<(), InvalidTag> {
+    if tag.is_empty() || tag.len() > STRATEGY_TAG_MAX_LEN || !is_alphanumeric(tag) {
+        return Err(InvalidTag(tag.to_string()));
+    }
+    Ok(())
+}
+
+/// 解析出的 clOrdId
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedClOrdId {
+    pub tag: String,
+    /// 生成该 id 的进程启动时间（毫秒）
+    pub session_ms: u64,
+    pub seq: u64,
+}
+
+/// clOrdId 生成器
+///
+/// 格式为 `{prefix}{tag}{session}{seq}`，只包含字母数字，最长 4 + 8 + 8 + 6 = 26 位。
+/// session 取进程启动的毫秒时间戳，重启后不会与之前的 id 重复；seq 在进程内递增。
+pub struct ClOrdIdGenerator {
+    prefix: String,
+    session: String,
+    session_ms: u64,
+    seq: AtomicU64,
+}
+
+impl Default for ClOrdIdGenerator {
+    fn default() -> Self {
+        ClOrdIdGenerator::new(CL_ORD_ID_PREFIX)
+    }
+}
+
+impl ClOrdIdGenerator {
+    /// prefix 只能是字母数字，最长 4 位
+    pub fn new(prefix: &str) -> ClOrdIdGenerator {
+        Self::with_session(prefix, Utc::now().timestamp_millis() as u64)
+    }
+
+    pub fn with_session(prefix: &str, session_ms: u64) -> ClOrdIdGenerator {
+        assert!(prefix.len() <= 4 && is_alphanumeric(prefix), "invalid clOrdId prefix {}", prefix);
+        ClOrdIdGenerator {
+            prefix: prefix.to_string(),
+            session: to_base36(session_ms, SESSION_LEN),
+            session_ms,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn session_ms(&self) -> u64 {
+        self.session_ms
+    }
+
+    /// 当前序号，用于状态快照
+    pub fn seq(&self) -> u64 {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    /// 从快照恢复序号，只会向前推进
+    pub fn restore_seq(&self, seq: u64) {
+        self.seq.fetch_max(seq, Ordering::Relaxed);
+    }
+
+    /// 生成下一个 clOrdId，tag 为策略标识（字母数字，最长 8 位），不合法时不消耗序号
+    pub fn next(&self, tag: &str) -> Result<String, InvalidTag> {
+        validate_tag(tag)?;
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) % SEQ_MAX;
+        Ok(format!("{}{}{}{}", self.prefix, tag, self.session, to_base36(seq, SEQ_LEN)))
+    }
+
+    /// 是否为本程序（相同前缀）生成的 id
+    pub fn is_ours(&self, cl_ord_id: &str) -> bool {
+        self.parse(cl_ord_id).is_some()
+    }
+
+    /// 解析 clOrdId，不是本前缀或格式不对时返回 None
+    pub fn parse(&self, cl_ord_id: &str) -> Option<ParsedClOrdId> {
+        parse_cl_ord_id(&self.prefix, cl_ord_id)
+    }
+}
+
+/// 按指定前缀解析 clOrdId，用于把成交归属到下单的策略
+pub fn parse_cl_ord_id(prefix: &str, cl_ord_id: &str) -> Option<ParsedClOrdId> {
+    if cl_ord_id.len() > CL_ORD_ID_MAX_LEN || !is_alphanumeric(cl_ord_id) {
+        return None;
+    }
+    let rest = cl_ord_id.strip_prefix(prefix)?;
+    if rest.len() <= SESSION_LEN + SEQ_LEN {
+        return None;
+    }
+    let tag_len = rest.len() - SESSION_LEN - SEQ_LEN;
+    if tag_len > STRATEGY_TAG_MAX_LEN {
+        return None;
+    }
+    let (tag, rest) = rest.split_at(tag_len);
+    let (session, seq) = rest.split_at(SESSION_LEN);
+    Some(ParsedClOrdId {
+        tag: tag.to_string(),
+        session_ms: from_base36(session)?,
+        seq: from_base36(seq)?,
+    })
+}
+
+#[cfg(test)]
+mod cl_ord_id_test {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_parse() {
+        let generator = ClOrdIdGenerator::with_session("ox", 1_764_000_000_000);
+        let id1 = generator.next("scalp").unwrap();
+        let id2 = generator.next("scalp").unwrap();
+        assert_ne!(id1, id2);
+        assert!(id1.len() <= CL_ORD_ID_MAX_LEN);
+        assert!(id1.bytes().all(|b| b.is_ascii_alphanumeric()));
+        let parsed = generator.parse(&id2).unwrap();
+        assert_eq!(parsed.tag, "scalp");
+        assert_eq!(parsed.session_ms, 1_764_000_000_000);
+        assert_eq!(parsed.seq, 1);
+        assert!(!generator.is_ours("abc"));
+        assert!(!generator.is_ours("BTC-USDT-SWAP-buy-123.4-limit"));
+    }
+
+    #[test]
+    fn test_restart_unique() {
+        let first = ClOrdIdGenerator::with_session("ox", 1_764_000_000_000);
+        let second = ClOrdIdGenerator::with_session("ox", 1_764_000_000_001);
+        assert_ne!(first.next("s1").unwrap(), second.next("s1").unwrap());
+        second.restore_seq(10);
+        assert_eq!(second.parse(&second.next("s1").unwrap()).unwrap().seq, 10);
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let generator = ClOrdIdGenerator::with_session("ox", 1_764_000_000_000);
+        for tag in ["", "toolongtag", "a-b"] {
+            assert_eq!(generator.next(tag), Err(InvalidTag(tag.to_string())));
+        }
+        assert_eq!(generator.seq(), 0);
+        assert_eq!(validate_tag("scalp"), Ok(()));
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use sonic_rs::{from_str, Deserialize, Serialize};
+use crate::common::rest_api::OkxResponse;
+use crate::common::utils::HttpClient;
+
+/// 本地时钟与服务器相差超过该值（毫秒）时告警，OKX 签名允许的误差为 30 秒
+pub const DRIFT_WARN_MS: i64 = 1000;
+/// 往返时间超过该值（毫秒）的样本误差太大，丢弃
+pub const MAX_SAMPLE_RTT_MS: u64 = 2000;
+/// 定时校准间隔（秒）
+pub const SYNC_INTERVAL_SECS: u64 = 60;
+
+/// GET /api/v5/public/time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerTime {
+    pub ts: String,
+}
+
+/// 服务器时钟：offset = 服务器时间 - 本地时间，签名和 WS 登录统一用 now_ms
+pub struct ServerClock {
+    offset_ms: AtomicI64,
+    rtt_ms: AtomicU64,
+    /// 最近一次校准的本地时间（毫秒），0 表示从未校准
+    synced_ms: AtomicU64,
+    /// 下单请求有效期（毫秒），0 表示不带 expTime
+    exp_ms: AtomicU64,
+    warned: AtomicBool,
+}
+
+pub static SERVER_CLOCK: ServerClock = ServerClock::new();
+
+fn local_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+impl Default for ServerClock {
+    fn default() -> Self {
+        ServerClock::new()
+    }
+}
+
+impl ServerClock {
+    pub const fn new() -> ServerClock {
+        ServerClock {
+            offset_ms: AtomicI64::new(0),
+            rtt_ms: AtomicU64::new(0),
+            synced_ms: AtomicU64::new(0),
+            exp_ms: AtomicU64::new(0),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn offset_ms(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn rtt_ms(&self) -> u64 {
+        self.rtt_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced_ms.load(Ordering::Relaxed) != 0
+    }
+
+    /// 校准后的服务器时间（毫秒）
+    pub fn now_ms(&self) -> i64 {
+        local_ms() + self.offset_ms()
+    }
+
+    /// 校准后的服务器时间（秒），用于 WS 登录
+    pub fn now_secs(&self) -> i64 {
+        self.now_ms() / 1000
+    }
+
+    /// 返回类似 "2020-12-08T09:08:57.715Z" 的服务器时间，用于 OK-ACCESS-TIMESTAMP
+    pub fn now_iso(&self) -> String {
+        let now = DateTime::<Utc>::from_timestamp_millis(self.now_ms()).unwrap_or_else(Utc::now);
+        now.format("%Y-%m-%dT%H:%M:%S.%3fZ").to_string()
+    }
+
+    /// 设置下单请求的有效期，0 关闭
+    pub fn set_request_expiry(&self, ms: u64) {
+        self.exp_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// expTime 头的值（服务器时间毫秒），未开启时为 None
+    pub fn exp_time(&self) -> Option<String> {
+        match self.exp_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some((self.now_ms() + ms as i64).to_string()),
+        }
+    }
+
+    /// 用一次请求的样本更新 offset：假设请求和响应耗时相同，服务器时间对应往返的中点
+    /// 返回样本是否被采用
+    pub fn update(&self, send_ms: i64, server_ms: i64, recv_ms: i64) -> bool {
+        let rtt = (recv_ms - send_ms).max(0) as u64;
+        if rtt > MAX_SAMPLE_RTT_MS {
+            warn!("时间同步往返 {}ms 过长，丢弃样本", rtt);
+            return false;
+        }
+        let offset = server_ms - (send_ms + recv_ms) / 2;
+        self.offset_ms.store(offset, Ordering::Relaxed);
+        self.rtt_ms.store(rtt, Ordering::Relaxed);
+        self.synced_ms.store(recv_ms as u64, Ordering::Relaxed);
+        if offset.abs() > DRIFT_WARN_MS {
+            warn!("本地时钟与服务器相差 {}ms（往返 {}ms），签名将使用服务器时间", offset, rtt);
+            self.warned.store(true, Ordering::Relaxed);
+        } else if self.warned.swap(false, Ordering::Relaxed) {
+            info!("本地时钟偏差恢复正常 {}ms", offset);
+        }
+        true
+    }
+
+    /// 请求 /api/v5/public/time 校准一次
+    pub async fn sync(&self) -> Result<i64, Box<dyn std::error::Error>> {
+        let send_ms = local_ms();
+        let response = HttpClient::get("/api/v5/public/time", None).await?;
+        let text = response.text().await?;
+        let recv_ms = local_ms();
+        let server_ms = from_str::<OkxResponse<ServerTime>>(&text)?
+            .into_result()?
+            .first()
+            .and_then(|t| t.ts.parse::<i64>().ok())
+            .ok_or("public/time empty")?;
+        if !self.update(send_ms, server_ms, recv_ms) {
+            return Err("时间同步样本无效".into());
+        }
+        Ok(self.offset_ms())
+    }
+
+    /// 定时校准，失败时沿用上次的 offset
+    pub async fn sync_loop(&'static self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(SYNC_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sync().await {
+                warn!("时间同步失败 {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod clock_test {
+    use super::*;
+
+    #[test]
+    fn test_update_offset() {
+        let clock = ServerClock::new();
+        assert!(clock.update(1_000, 6_050, 1_100));
+        assert_eq!(clock.offset_ms(), 5_000);
+        assert_eq!(clock.rtt_ms(), 100);
+        assert!(clock.is_synced());
+        // 往返过长的样本不采用
+        assert!(!clock.update(1_000, 9_000, 10_000));
+        assert_eq!(clock.offset_ms(), 5_000);
+        assert!(clock.exp_time().is_none());
+        clock.set_request_expiry(500);
+        assert!(clock.exp_time().is_some());
+    }
+}
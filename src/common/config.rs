@@ -1,50 +1,514 @@
-use std::sync::LazyLock;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use log::LevelFilter;
+use sonic_rs::{Deserialize, Serialize, Value};
+use crate::common::credentials::{CredentialProfile, DEFAULT_PROFILE};
+use crate::common::endpoint::{EndpointProfile, Region};
+use crate::common::proxy::{Proxy, ProxyConfig};
+use crate::common::risk::RiskLimits;
+use crate::common::ws_api::{is_candle_channel, TdMode, CHANNEL_BBO_TBT, CHANNEL_BOOKS, CHANNEL_BOOKS5, CHANNEL_TICKERS};
 
-pub const IS_DEV: bool = true;
-pub const WS_URL_PUBLIC: &str = "wss://ws.okx.com:8443/ws/v5/public";
-pub const WS_SIMULATION_URL_PUBLIC: &str = "wss://wspap.okx.com:8443/ws/v5/public";
-pub const WS_SIMULATION_URL_PRIVATE: &str = "wss://wspap.okx.com:8443/ws/v5/private";
-pub const WS__URL_PRIVATE: &str = "wss://ws.okx.com:8443/ws/v5/private";
-pub const REST_URL: &str = "https://www.okx.com";
-pub const REST_SIMULATION_URL: &str = "https://www.okx.com";
-pub static  OKX_API_KEY:LazyLock<String> = LazyLock::new(|| std::env::var("OKX_API_KEY").expect("OKX_API_KEY not set"));
-pub static  OKX_SECRET_KEY:LazyLock<String> = LazyLock::new(|| std::env::var("OKX_SECRET_KEY").expect("OKX_SECRET_KEY not set"));
-pub static  OK_ACCESS_PASSPHRASE:LazyLock<String> = LazyLock::new(|| std::env::var("OK_ACCESS_PASSPHRASE").expect("OK_ACCESS_PASSPHRASE not set"));
-/// 模拟API KEY
-pub static  OKX_SIMULATION_API_KEY:LazyLock<String> = LazyLock::new(|| std::env::var("OKX_SIMULATION_API_KEY").expect("OKX_SIMULATION_API_KEY not set"));
-/// 模拟API Passphrase
-pub static  OK_SIMULATION_ACCESS_PASSPHRASE:LazyLock<String> = LazyLock::new(|| std::env::var("OK_SIMULATION_ACCESS_PASSPHRASE").expect("OK_SIMULATION_ACCESS_PASSPHRASE not set"));
-/// 模拟API Secret Key
-pub static  OKX_SIMULATION_SECRET_KEY:LazyLock<String> = LazyLock::new(|| std::env::var("OKX_SIMULATION_SECRET_KEY").expect("OKX_SIMULATION_SECRET_KEY not set"));
+// 全球站地址，其他站点见 EndpointProfile
+pub const WS_URL_PUBLIC: &str = EndpointProfile::GLOBAL.ws_public;
+pub const WS_SIMULATION_URL_PUBLIC: &str = EndpointProfile::GLOBAL_DEMO.ws_public;
+pub const WS_SIMULATION_URL_PRIVATE: &str = EndpointProfile::GLOBAL_DEMO.ws_private;
+pub const WS__URL_PRIVATE: &str = EndpointProfile::GLOBAL.ws_private;
+pub const REST_URL: &str = EndpointProfile::GLOBAL.rest;
+/// 模拟盘 REST 与实盘同一个域名，靠 x-simulated-trading 请求头区分
+pub const REST_SIMULATION_URL: &str = EndpointProfile::GLOBAL_DEMO.rest;
 
+/// 默认配置文件，不存在时使用默认配置
+pub const CONFIG_PATH: &str = "config.toml";
+/// 指定配置文件路径的环境变量，命令行 --config 优先
+pub const CONFIG_ENV: &str = "OKX_CONFIG";
+/// 配置中可以订阅的公共频道，另外 K 线频道（candle1m 等）在业务连接上订阅
+pub const PUBLIC_CHANNELS: [&str; 4] = [CHANNEL_BOOKS, CHANNEL_BOOKS5, CHANNEL_TICKERS, CHANNEL_BBO_TBT];
+/// request_expiry_ms 的范围，太短时正常的网络延迟也会让请求过期
+pub const MIN_REQUEST_EXPIRY_MS: u64 = 100;
+pub const MAX_REQUEST_EXPIRY_MS: u64 = 60_000;
 
-pub fn get_api_key() -> & 'static str {
-    if IS_DEV {
-        return OKX_SIMULATION_API_KEY.as_str();
+/// 实盘或模拟盘
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Live,
+    #[default]
+    Demo,
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Environment::Live => write!(f, "live"),
+            Environment::Demo => write!(f, "demo"),
+        }
     }
-    OKX_API_KEY.as_str()
 }
-pub fn get_secret_key() -> & 'static str {
-    if IS_DEV {
-        return OKX_SIMULATION_SECRET_KEY.as_str();
+
+/// 接入地址：按 region 和 environment 选择 EndpointProfile，单独设置的地址覆盖 profile 中的值
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointConfig {
+    /// global / eea / us / aws
+    pub region: Region,
+    pub rest: Option<String>,
+    pub ws_public: Option<String>,
+    pub ws_private: Option<String>,
+    pub ws_business: Option<String>,
+}
+
+/// API 凭证配置，[credentials] 下的字段是 default 凭证的来源，其他凭证在 [credentials.profiles.<name>] 中配置
+///
+/// ```toml
+/// [credentials]
+/// profile = "main"
+/// [credentials.profiles.main]
+/// source = "secret_file"
+/// path = "/run/secrets/okx_main.toml"
+/// [credentials.profiles.reader]
+/// read_only = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CredentialsConfig {
+    /// 未指定凭证的客户端使用的 profile
+    pub profile: String,
+    #[serde(flatten)]
+    pub default: CredentialProfile,
+    pub profiles: BTreeMap<String, CredentialProfile>,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        CredentialsConfig {
+            profile: DEFAULT_PROFILE.to_string(),
+            default: CredentialProfile::default(),
+            profiles: BTreeMap::new(),
+        }
     }
-    OKX_SECRET_KEY.as_str()
 }
-pub fn get_passphrase() -> & 'static str {
-    if IS_DEV {
-        return OK_SIMULATION_ACCESS_PASSPHRASE.as_str();
+
+impl CredentialsConfig {
+    pub fn profile(&self, name: &str) -> Option<&CredentialProfile> {
+        match self.profiles.get(name) {
+            Some(profile) => Some(profile),
+            None if name == DEFAULT_PROFILE => Some(&self.default),
+            None => None,
+        }
     }
-    OK_ACCESS_PASSPHRASE.as_str()
 }
-pub fn get_ws_public()->&'static str{
-    if IS_DEV {
-        return WS_SIMULATION_URL_PUBLIC;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// off / error / warn / info / debug / trace
+    pub level: String,
+    /// 日志时间的时区偏移（小时）
+    pub utc_offset_hours: i32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig { level: "info".to_string(), utc_offset_hours: 8 }
+    }
+}
+
+impl LogConfig {
+    pub fn level_filter(&self) -> LevelFilter {
+        LevelFilter::from_str(&self.level).unwrap_or(LevelFilter::Info)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StrategyConfig {
+    /// 交易前为每个产品设置的杠杆
+    pub lever: u32,
+    /// cross / isolated
+    pub mgn_mode: String,
+    /// 策略自定义参数，注册策略时原样传给 StrategyState::configure
+    #[serde(deserialize_with = "any_value")]
+    pub params: Value,
+}
+
+/// sonic_rs::Value 只能从 JSON 反序列化，TOML 中的表先读成 toml::Value 再转换
+fn any_value<'de, D>(deserializer: D) -> Result<Value, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = toml::Value::deserialize(deserializer)?;
+    sonic_rs::to_value(&value).map_err(serde::de::Error::custom)
+}
+
+impl Default for StrategyConfig {
+    fn default() -> Self {
+        StrategyConfig { lever: 5, mgn_mode: TdMode::CROSS.to_string(), params: Value::new_object() }
     }
-    WS_URL_PUBLIC
+}
+
+/// 运行时配置，TOML 或 JSON（按扩展名区分），字段均可省略
+///
+/// ```toml
+/// environment = "demo"
+/// instruments = ["ETH-USDT-SWAP"]
+/// channels = ["books", "tickers", "books5"]
+///
+/// [log]
+/// level = "info"
+/// utc_offset_hours = 8
+///
+/// [strategy]
+/// lever = 5
+/// mgn_mode = "cross"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub environment: Environment,
+    pub endpoints: EndpointConfig,
+    pub credentials: CredentialsConfig,
+    /// 交易的产品
+    pub instruments: Vec<String>,
+    /// 每个产品订阅的行情频道，见 PUBLIC_CHANNELS 和 CANDLE_BARS
+    pub channels: Vec<String>,
+    /// 风控限额，未配置时读取 RISK_LIMITS_PATH
+    pub risk: Option<RiskLimits>,
+    pub log: LogConfig,
+    pub strategy: StrategyConfig,
+    /// REST 和所有 WebSocket 连接使用的代理
+    pub proxy: ProxyConfig,
+    /// 下单请求的有效期（毫秒），超时未处理的请求由服务器拒绝，0 表示不带 expTime
+    pub request_expiry_ms: u64,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            environment: Environment::default(),
+            endpoints: EndpointConfig::default(),
+            credentials: CredentialsConfig::default(),
+            instruments: vec!["ETH-USDT-SWAP".to_string()],
+            channels: vec![CHANNEL_BOOKS.to_string(), CHANNEL_TICKERS.to_string(), CHANNEL_BOOKS5.to_string()],
+            risk: None,
+            log: LogConfig::default(),
+            strategy: StrategyConfig::default(),
+            proxy: ProxyConfig::default(),
+            request_expiry_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, error: std::io::Error },
+    Parse { path: PathBuf, message: String },
+    /// 所有校验失败的字段，一次全部报告
+    Invalid { path: PathBuf, problems: Vec<String> },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "读取配置 {} 失败: {}", path.display(), error),
+            ConfigError::Parse { path, message } => write!(f, "解析配置 {} 失败: {}", path.display(), message),
+            ConfigError::Invalid { path, problems } => {
+                write!(f, "配置 {} 有 {} 处错误:", path.display(), problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl AppConfig {
+    /// 读取并校验配置文件，.json 按 JSON 解析，其余按 TOML 解析
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AppConfig, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io { path: path.to_path_buf(), error })?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let config = if is_json { AppConfig::from_json_str(&text) } else { AppConfig::from_toml_str(&text) }
+            .map_err(|message| ConfigError::Parse { path: path.to_path_buf(), message })?;
+        config.validate().map_err(|problems| ConfigError::Invalid { path: path.to_path_buf(), problems })?;
+        Ok(config)
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<AppConfig, String> {
+        toml::from_str::<AppConfig>(text).map_err(|e| e.to_string())
+    }
+
+    pub fn from_json_str(text: &str) -> Result<AppConfig, String> {
+        sonic_rs::from_str::<AppConfig>(text).map_err(|e| e.to_string())
+    }
+
+    /// 返回所有不合法的字段
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let endpoints = [
+            ("endpoints.rest", &self.endpoints.rest, ["https://", "http://"]),
+            ("endpoints.ws_public", &self.endpoints.ws_public, ["wss://", "ws://"]),
+            ("endpoints.ws_private", &self.endpoints.ws_private, ["wss://", "ws://"]),
+            ("endpoints.ws_business", &self.endpoints.ws_business, ["wss://", "ws://"]),
+        ];
+        for (name, url, schemes) in endpoints {
+            if let Some(url) = url
+                && !schemes.iter().any(|scheme| url.starts_with(scheme))
+            {
+                problems.push(format!("{} = {:?} 必须以 {} 开头", name, url, schemes.join(" 或 ")));
+            }
+        }
+        if EndpointProfile::of(self.endpoints.region, self.environment).is_none() {
+            problems.push(format!("endpoints.region = \"{}\" 没有 {} 环境的地址", self.endpoints.region, self.environment));
+        }
+        if let Some(url) = &self.proxy.url
+            && let Err(e) = Proxy::parse(url)
+        {
+            problems.push(format!("proxy.url: {}", e));
+        }
+        problems.extend(self.credentials.default.problems(DEFAULT_PROFILE));
+        for (name, profile) in &self.credentials.profiles {
+            problems.extend(profile.problems(name));
+        }
+        if self.credentials.profile(&self.credentials.profile).is_none() {
+            problems.push(format!("credentials.profile = {:?} 未在 credentials.profiles 中配置", self.credentials.profile));
+        }
+        if self.instruments.is_empty() {
+            problems.push("instruments 不能为空".to_string());
+        }
+        for inst_id in &self.instruments {
+            if inst_id.is_empty() || !inst_id.contains('-') || inst_id.chars().any(char::is_whitespace) {
+                problems.push(format!("instruments 中的 {:?} 不是合法的 instId", inst_id));
+            }
+        }
+        for channel in &self.channels {
+            if !PUBLIC_CHANNELS.contains(&channel.as_str()) && !is_candle_channel(channel) {
+                problems.push(format!("channels 中的 {:?} 不支持，可选 {} 或 candle1m 等 K 线频道", channel, PUBLIC_CHANNELS.join(" / ")));
+            }
+        }
+        if let Some(risk) = &self.risk {
+            if let Some(pct) = risk.price_band_pct
+                && !(pct > 0.0 && pct < 1.0)
+            {
+                problems.push(format!("risk.price_band_pct = {} 必须在 0 和 1 之间", pct));
+            }
+            let positive = [
+                ("risk.default_max_position", risk.default_max_position),
+                ("risk.max_gross_notional", risk.max_gross_notional),
+                ("risk.max_order_size", risk.max_order_size),
+                ("risk.max_daily_loss", risk.max_daily_loss),
+            ];
+            for (name, value) in positive {
+                if let Some(value) = value
+                    && value <= 0.0
+                {
+                    problems.push(format!("{} = {} 必须大于 0", name, value));
+                }
+            }
+        }
+        if LevelFilter::from_str(&self.log.level).is_err() {
+            problems.push(format!("log.level = {:?} 只能是 off / error / warn / info / debug / trace", self.log.level));
+        }
+        if !(-12..=14).contains(&self.log.utc_offset_hours) {
+            problems.push(format!("log.utc_offset_hours = {} 超出范围 -12..=14", self.log.utc_offset_hours));
+        }
+        if self.request_expiry_ms != 0 && !(MIN_REQUEST_EXPIRY_MS..=MAX_REQUEST_EXPIRY_MS).contains(&self.request_expiry_ms) {
+            problems.push(format!(
+                "request_expiry_ms = {} 只能是 0 或 {}..={}",
+                self.request_expiry_ms, MIN_REQUEST_EXPIRY_MS, MAX_REQUEST_EXPIRY_MS
+            ));
+        }
+        if self.strategy.lever == 0 {
+            problems.push("strategy.lever 必须大于 0".to_string());
+        }
+        if self.strategy.mgn_mode != TdMode::CROSS && self.strategy.mgn_mode != TdMode::ISOLATED {
+            problems.push(format!("strategy.mgn_mode = {:?} 只能是 cross / isolated", self.strategy.mgn_mode));
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    pub fn is_demo(&self) -> bool {
+        self.environment == Environment::Demo
+    }
+
+    /// region 和 environment 对应的地址，组合不存在时（已在 validate 中报错）退回全球站
+    pub fn endpoint_profile(&self) -> EndpointProfile {
+        EndpointProfile::of(self.endpoints.region, self.environment)
+            .or_else(|| EndpointProfile::of(Region::Global, self.environment))
+            .unwrap_or(EndpointProfile::GLOBAL)
+    }
+
+    pub fn rest_url(&self) -> &str {
+        self.endpoints.rest.as_deref().unwrap_or(self.endpoint_profile().rest)
+    }
+
+    pub fn ws_public(&self) -> &str {
+        self.endpoints.ws_public.as_deref().unwrap_or(self.endpoint_profile().ws_public)
+    }
+
+    pub fn ws_private(&self) -> &str {
+        self.endpoints.ws_private.as_deref().unwrap_or(self.endpoint_profile().ws_private)
+    }
+
+    pub fn ws_business(&self) -> &str {
+        self.endpoints.ws_business.as_deref().unwrap_or(self.endpoint_profile().ws_business)
+    }
+}
+
+static CONFIG: OnceLock<AppConfig> = OnceLock::new();
+
+/// 当前配置，load_config 之前调用（如单元测试）返回默认配置
+pub fn config() -> &'static AppConfig {
+    CONFIG.get_or_init(AppConfig::default)
+}
+
+/// 配置文件路径：命令行 --config，其次环境变量 OKX_CONFIG，最后是存在的 CONFIG_PATH
+pub fn resolve_config_path<I: IntoIterator<Item = String>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    if let Ok(path) = std::env::var(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    Some(PathBuf::from(CONFIG_PATH)).filter(|path| path.exists())
+}
+
+/// 去掉 --config 及其参数后的位置参数
+pub fn positional_args<I: IntoIterator<Item = String>>(args: I) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            args.next();
+            continue;
+        }
+        if !arg.starts_with("--") {
+            positional.push(arg);
+        }
+    }
+    positional
+}
+
+/// 启动时调用一次，读取并校验配置后设为全局配置
+pub fn load_config() -> Result<&'static AppConfig, ConfigError> {
+    let config = match resolve_config_path(std::env::args().skip(1)) {
+        Some(path) => AppConfig::load(path)?,
+        None => AppConfig::default(),
+    };
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get_ws_public()->&'static str{
+    config().ws_public()
 }
 pub fn get_ws_private()->&'static str{
-    if IS_DEV {
-        return WS_SIMULATION_URL_PRIVATE;
+    config().ws_private()
+}
+pub fn get_ws_business()->&'static str{
+    config().ws_business()
+}
+pub fn get_rest_url()->&'static str{
+    config().rest_url()
+}
+
+#[cfg(test)]
+mod config_test {
+    use sonic_rs::JsonValueTrait;
+    use super::*;
+
+    #[test]
+    fn test_parse_and_validate() {
+        let text = r#"
+            environment = "live"
+            instruments = ["BTC-USDT-SWAP", "BTC-USD-SWAP"]
+            channels = ["books5", "candle1H"]
+
+            [endpoints]
+            region = "aws"
+            ws_public = "wss://ws.okx.com:8443/ws/v5/public"
+
+            [risk]
+            max_open_orders = 20
+            price_band_pct = 0.02
+
+            [log]
+            level = "debug"
+
+            [strategy]
+            lever = 3
+            params = { spread = 0.001, levels = 3 }
+        "#;
+        let config = AppConfig::from_toml_str(text).unwrap();
+        assert_eq!(config.validate(), Ok(()));
+        assert!(!config.is_demo());
+        assert_eq!(config.rest_url(), EndpointProfile::AWS.rest);
+        assert_eq!(config.ws_public(), WS_URL_PUBLIC);
+        assert_eq!(config.ws_private(), EndpointProfile::AWS.ws_private);
+        assert_eq!(config.ws_business(), EndpointProfile::AWS.ws_business);
+        assert_eq!(config.credentials.profile, DEFAULT_PROFILE);
+        assert_eq!(config.risk.as_ref().unwrap().max_open_orders, Some(20));
+        assert_eq!(config.log.level_filter(), LevelFilter::Debug);
+        assert_eq!(config.strategy.mgn_mode, TdMode::CROSS);
+        assert_eq!(config.strategy.params["levels"].as_u64(), Some(3));
+
+        let json = AppConfig::from_json_str(
+            r#"{"environment":"demo","instruments":["ETH-USDC-SWAP"],"credentials":{"api_key_env":"K","profiles":{"ro":{"read_only":true}}}}"#,
+        ).unwrap();
+        assert!(json.is_demo());
+        assert_eq!(json.ws_private(), WS_SIMULATION_URL_PRIVATE);
+        assert_eq!(json.credentials.default.api_key_env.as_deref(), Some("K"));
+        assert!(json.credentials.profile("ro").unwrap().read_only);
+        assert_eq!(json.channels, AppConfig::default().channels);
+
+        assert!(AppConfig::from_toml_str("environment = \"prod\"").is_err());
+        let bad = AppConfig::from_toml_str(r#"
+            instruments = []
+            channels = ["orders"]
+            [endpoints]
+            region = "aws"
+            rest = "ftp://x"
+            [log]
+            level = "loud"
+            [strategy]
+            lever = 0
+            mgn_mode = "cash"
+            [credentials]
+            profile = "nope"
+            [credentials.profiles.sub]
+            source = "file"
+            [proxy]
+            url = "ftp://x"
+        "#).unwrap();
+        assert_eq!(bad.validate().unwrap_err().len(), 10);
+        let expiry = AppConfig::from_toml_str("request_expiry_ms = 10").unwrap();
+        assert_eq!(expiry.validate().unwrap_err().len(), 1);
+        assert_eq!(AppConfig::from_toml_str("request_expiry_ms = 5000").unwrap().validate(), Ok(()));
     }
-    WS__URL_PRIVATE
-}
\ No newline at end of file
+
+    #[test]
+    fn test_example_file() {
+        let config = AppConfig::load("config.example.toml").unwrap();
+        assert_eq!(config, AppConfig::default());
+    }
+
+    #[test]
+    fn test_args() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(resolve_config_path(args(&["--config", "a.toml", "flatten"])), Some(PathBuf::from("a.toml")));
+        assert_eq!(resolve_config_path(args(&["--config=b.json"])), Some(PathBuf::from("b.json")));
+        assert_eq!(positional_args(args(&["--config", "a.toml", "flatten"])), args(&["flatten"]));
+        assert!(positional_args(args(&["--config=a.toml"])).is_empty());
+        assert_eq!(positional_args(args(&["credentials", "--config", "a.toml", "list", "v.json"])), args(&["credentials", "list", "v.json"]));
+    }
+}
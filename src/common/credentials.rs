@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use dashmap::DashMap;
+use log::info;
+use once_cell::sync::Lazy;
+use sonic_rs::{Deserialize, Serialize};
+use crate::common::config::{config, CredentialsConfig, Environment};
+use crate::common::utils::sign;
+use crate::common::vault::read_vault;
+use zeroize::Zeroize;
+
+/// 未指定 profile 时使用的凭证名
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// 凭证来源
+pub struct CredentialSource;
+impl CredentialSource {
+    /// 环境变量
+    pub const ENV: &'static str = "env";
+    /// 多个 profile 共用的凭证文件，每个 profile 一张表
+    pub const FILE: &'static str = "file";
+    /// 只保存一个 profile 的密钥文件（如容器挂载的 secret）
+    pub const SECRET_FILE: &'static str = "secret_file";
+    /// 用密码加密的凭证文件，启动时解锁，见 vault
+    pub const ENCRYPTED_FILE: &'static str = "encrypted_file";
+
+    pub const ALL: [&'static str; 4] = [
+        CredentialSource::ENV,
+        CredentialSource::FILE,
+        CredentialSource::SECRET_FILE,
+        CredentialSource::ENCRYPTED_FILE,
+    ];
+}
+
+/// 单个凭证的来源配置
+///
+/// env 来源未设置变量名时，default 按 environment 使用原来的变量名，
+/// 其他 profile 使用 OKX_<PROFILE>_API_KEY / OKX_<PROFILE>_SECRET_KEY / OKX_<PROFILE>_PASSPHRASE
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CredentialProfile {
+    pub source: String,
+    pub api_key_env: Option<String>,
+    pub secret_key_env: Option<String>,
+    pub passphrase_env: Option<String>,
+    /// file / secret_file / encrypted_file 的路径
+    pub path: Option<String>,
+    /// 只读 key，用于查询，不允许下单
+    pub read_only: bool,
+}
+
+impl Default for CredentialProfile {
+    fn default() -> Self {
+        CredentialProfile {
+            source: CredentialSource::ENV.to_string(),
+            api_key_env: None,
+            secret_key_env: None,
+            passphrase_env: None,
+            path: None,
+            read_only: false,
+        }
+    }
+}
+
+impl CredentialProfile {
+    /// 配置中的问题，name 为 profile 名
+    pub fn problems(&self, name: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !CredentialSource::ALL.contains(&self.source.as_str()) {
+            problems.push(format!("凭证 {} 的 source = {:?} 只能是 {}", name, self.source, CredentialSource::ALL.join(" / ")));
+        } else if self.source != CredentialSource::ENV && self.path.is_none() {
+            problems.push(format!("凭证 {} 的 source = {:?} 需要设置 path", name, self.source));
+        }
+        problems
+    }
+}
+
+/// 敏感字符串，Debug / Display 只输出 ***，释放时清零
+///
+/// 只有 expose() 能取到原文，序列化会输出原文，只用于写入加密文件
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<S: Into<String>>(value: S) -> Secret {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "***")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "***")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// 一组 API 凭证，secret_key 和 passphrase 不会出现在日志中
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub profile: String,
+    pub api_key: String,
+    pub secret_key: Secret,
+    pub passphrase: Secret,
+    pub read_only: bool,
+}
+
+impl Credentials {
+    /// 用本凭证的 secret 签名
+    pub fn sign(&self, timestamp: &str, method: &str, path: &str, body: &str) -> String {
+        sign(timestamp, method, path, body, self.secret_key.expose())
+    }
+}
+
+/// 凭证文件中的一组密钥
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretEntry {
+    pub api_key: String,
+    pub secret_key: Secret,
+    pub passphrase: Secret,
+}
+
+#[derive(Debug)]
+pub enum CredentialError {
+    UnknownProfile(String),
+    MissingEnv { profile: String, var: String },
+    MissingField { profile: String, field: &'static str },
+    InvalidSource { profile: String, source: String },
+    Io { path: PathBuf, error: std::io::Error },
+    Parse { path: PathBuf, message: String },
+    /// 文件对同组或其他用户可读写
+    InsecurePermissions { path: PathBuf, mode: u32 },
+    /// 只读 key 不能用于交易请求
+    ReadOnly(String),
+    /// 加密文件尚未解锁
+    Locked(PathBuf),
+    /// 密码错误或文件被篡改
+    Decrypt(PathBuf),
+    Crypto(String),
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::UnknownProfile(profile) => write!(f, "未配置凭证 {}", profile),
+            CredentialError::MissingEnv { profile, var } => write!(f, "凭证 {} 的环境变量 {} 未设置", profile, var),
+            CredentialError::MissingField { profile, field } => write!(f, "凭证 {} 缺少 {}", profile, field),
+            CredentialError::InvalidSource { profile, source } => write!(f, "凭证 {} 的来源 {:?} 不合法", profile, source),
+            CredentialError::Io { path, error } => write!(f, "读取凭证文件 {} 失败: {}", path.display(), error),
+            CredentialError::Parse { path, message } => write!(f, "解析凭证文件 {} 失败: {}", path.display(), message),
+            CredentialError::InsecurePermissions { path, mode } => {
+                write!(f, "凭证文件 {} 权限 {:o} 过宽，需要 chmod 600", path.display(), mode)
+            }
+            CredentialError::ReadOnly(profile) => write!(f, "凭证 {} 为只读 key，不能用于交易", profile),
+            CredentialError::Locked(path) => write!(f, "加密凭证文件 {} 未解锁", path.display()),
+            CredentialError::Decrypt(path) => write!(f, "解密 {} 失败，密码错误或文件已损坏", path.display()),
+            CredentialError::Crypto(message) => write!(f, "加密失败: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+/// 凭证文件不能被同组或其他用户访问
+pub fn check_permissions(path: &Path) -> Result<(), CredentialError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = std::fs::metadata(path).map_err(|error| CredentialError::Io { path: path.to_path_buf(), error })?;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(CredentialError::InsecurePermissions { path: path.to_path_buf(), mode });
+        }
+    }
+    Ok(())
+}
+
+fn read_secret_file<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, CredentialError> {
+    check_permissions(path)?;
+    let text = std::fs::read_to_string(path).map_err(|error| CredentialError::Io { path: path.to_path_buf(), error })?;
+    let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        sonic_rs::from_str::<T>(&text).map_err(|e| CredentialError::Parse { path: path.to_path_buf(), message: e.to_string() })
+    } else {
+        toml::from_str::<T>(&text).map_err(|e| CredentialError::Parse { path: path.to_path_buf(), message: e.to_string() })
+    }
+}
+
+/// env 来源的变量名 (api_key, secret_key, passphrase)
+pub fn env_names(name: &str, profile: &CredentialProfile, environment: Environment) -> (String, String, String) {
+    let (api_key, secret_key, passphrase) = if name == DEFAULT_PROFILE {
+        match environment {
+            Environment::Live => ("OKX_API_KEY".to_string(), "OKX_SECRET_KEY".to_string(), "OK_ACCESS_PASSPHRASE".to_string()),
+            Environment::Demo => (
+                "OKX_SIMULATION_API_KEY".to_string(),
+                "OKX_SIMULATION_SECRET_KEY".to_string(),
+                "OK_SIMULATION_ACCESS_PASSPHRASE".to_string(),
+            ),
+        }
+    } else {
+        let prefix = format!("OKX_{}", name.to_uppercase().replace('-', "_"));
+        (format!("{prefix}_API_KEY"), format!("{prefix}_SECRET_KEY"), format!("{prefix}_PASSPHRASE"))
+    };
+    (
+        profile.api_key_env.clone().unwrap_or(api_key),
+        profile.secret_key_env.clone().unwrap_or(secret_key),
+        profile.passphrase_env.clone().unwrap_or(passphrase),
+    )
+}
+
+/// 按配置读取一个 profile 的凭证
+pub fn load_profile(credentials: &CredentialsConfig, environment: Environment, name: &str) -> Result<Credentials, CredentialError> {
+    let profile = credentials.profile(name).ok_or_else(|| CredentialError::UnknownProfile(name.to_string()))?;
+    let entry = match profile.source.as_str() {
+        CredentialSource::ENV => {
+            let (api_key, secret_key, passphrase) = env_names(name, profile, environment);
+            let read = |var: String| std::env::var(&var).map_err(|_| CredentialError::MissingEnv { profile: name.to_string(), var });
+            SecretEntry {
+                api_key: read(api_key)?,
+                secret_key: Secret::new(read(secret_key)?),
+                passphrase: Secret::new(read(passphrase)?),
+            }
+        }
+        CredentialSource::FILE => {
+            let path = profile_path(name, profile)?;
+            let mut entries = read_secret_file::<BTreeMap<String, SecretEntry>>(&path)?;
+            entries.remove(name).ok_or_else(|| CredentialError::UnknownProfile(format!("{} ({})", name, path.display())))?
+        }
+        CredentialSource::SECRET_FILE => read_secret_file::<SecretEntry>(&profile_path(name, profile)?)?,
+        CredentialSource::ENCRYPTED_FILE => {
+            let path = profile_path(name, profile)?;
+            let entries = UNLOCKED.get(&path).ok_or_else(|| CredentialError::Locked(path.clone()))?;
+            entries.get(name).cloned().ok_or_else(|| CredentialError::UnknownProfile(format!("{} ({})", name, path.display())))?
+        }
+        source => return Err(CredentialError::InvalidSource { profile: name.to_string(), source: source.to_string() }),
+    };
+    let fields = [("api_key", entry.api_key.is_empty()), ("secret_key", entry.secret_key.is_empty()), ("passphrase", entry.passphrase.is_empty())];
+    for (field, empty) in fields {
+        if empty {
+            return Err(CredentialError::MissingField { profile: name.to_string(), field });
+        }
+    }
+    Ok(Credentials {
+        profile: name.to_string(),
+        api_key: entry.api_key.clone(),
+        secret_key: entry.secret_key.clone(),
+        passphrase: entry.passphrase.clone(),
+        read_only: profile.read_only,
+    })
+}
+
+fn profile_path(name: &str, profile: &CredentialProfile) -> Result<PathBuf, CredentialError> {
+    profile
+        .path
+        .as_ref()
+        .map(PathBuf::from)
+        .ok_or(CredentialError::MissingField { profile: name.to_string(), field: "path" })
+}
+
+/// 已解锁的加密凭证文件，按配置中的路径索引
+static UNLOCKED: Lazy<DashMap<PathBuf, BTreeMap<String, SecretEntry>>> = Lazy::new(DashMap::new);
+
+/// 配置中需要解锁的加密凭证文件
+pub fn encrypted_paths(credentials: &CredentialsConfig) -> Vec<PathBuf> {
+    let mut paths = std::iter::once(&credentials.default)
+        .chain(credentials.profiles.values())
+        .filter(|profile| profile.source == CredentialSource::ENCRYPTED_FILE)
+        .filter_map(|profile| profile.path.as_ref().map(PathBuf::from))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// 用密码解锁加密凭证文件，返回其中的 profile
+pub fn unlock<P: AsRef<Path>>(path: P, passphrase: &Secret) -> Result<Vec<String>, CredentialError> {
+    let path = path.as_ref();
+    let entries = read_vault(path, passphrase)?;
+    let profiles = entries.keys().cloned().collect();
+    UNLOCKED.insert(path.to_path_buf(), entries);
+    Ok(profiles)
+}
+
+/// 已加载的凭证，按 profile 缓存，首次使用时按全局配置读取
+#[derive(Default)]
+pub struct CredentialStore {
+    loaded: DashMap<String, Arc<Credentials>>,
+}
+
+impl CredentialStore {
+    pub fn new() -> CredentialStore {
+        CredentialStore::default()
+    }
+
+    pub fn get(&self, profile: &str) -> Result<Arc<Credentials>, CredentialError> {
+        if let Some(credentials) = self.loaded.get(profile) {
+            return Ok(credentials.clone());
+        }
+        let config = config();
+        let credentials = Arc::new(load_profile(&config.credentials, config.environment, profile)?);
+        info!("已加载凭证 {} api_key {}", profile, credentials.api_key);
+        self.loaded.insert(profile.to_string(), credentials.clone());
+        Ok(credentials)
+    }
+
+    /// 配置中 credentials.profile 指定的默认凭证
+    pub fn default_credentials(&self) -> Result<Arc<Credentials>, CredentialError> {
+        self.get(&config().credentials.profile)
+    }
+
+    /// 直接放入凭证，覆盖同名 profile
+    pub fn insert(&self, credentials: Credentials) {
+        self.loaded.insert(credentials.profile.clone(), Arc::new(credentials));
+    }
+
+    pub fn profiles(&self) -> Vec<String> {
+        self.loaded.iter().map(|c| c.key().clone()).collect()
+    }
+}
+
+pub static CREDENTIAL_STORE: Lazy<CredentialStore> = Lazy::new(CredentialStore::new);
+
+#[cfg(test)]
+mod credentials_test {
+    use super::*;
+
+    fn write_secret(name: &str, text: &str, mode: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("okx_credentials_test_{}_{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        path
+    }
+
+    #[test]
+    fn test_profiles() {
+        let shared = write_secret("shared.toml", "[sub1]\napi_key = \"k1\"\nsecret_key = \"s1\"\npassphrase = \"p1\"\n", 0o600);
+        let single = write_secret("single.toml", "api_key = \"k2\"\nsecret_key = \"s2\"\npassphrase = \"p2\"\n", 0o600);
+        let open = write_secret("open.toml", "api_key = \"k3\"\nsecret_key = \"s3\"\npassphrase = \"p3\"\n", 0o644);
+        let text = format!(
+            r#"
+            profile = "sub1"
+            [profiles.sub1]
+            source = "file"
+            path = "{}"
+            [profiles.reader]
+            source = "secret_file"
+            path = "{}"
+            read_only = true
+            [profiles.open]
+            source = "secret_file"
+            path = "{}"
+            [profiles.sub2]
+            "#,
+            shared.display(),
+            single.display(),
+            open.display()
+        );
+        let credentials = toml::from_str::<CredentialsConfig>(&text).unwrap();
+        let sub1 = load_profile(&credentials, Environment::Demo, "sub1").unwrap();
+        assert_eq!((sub1.api_key.as_str(), sub1.secret_key.expose(), sub1.read_only), ("k1", "s1", false));
+        let reader = load_profile(&credentials, Environment::Demo, "reader").unwrap();
+        assert!(reader.read_only);
+        assert!(!format!("{:?}", reader).contains("s2"));
+        #[cfg(unix)]
+        assert!(matches!(load_profile(&credentials, Environment::Demo, "open"), Err(CredentialError::InsecurePermissions { .. })));
+        assert!(matches!(
+            load_profile(&credentials, Environment::Demo, "sub2"),
+            Err(CredentialError::MissingEnv { var, .. }) if var == "OKX_SUB2_API_KEY"
+        ));
+        assert!(matches!(load_profile(&credentials, Environment::Demo, "nope"), Err(CredentialError::UnknownProfile(_))));
+        for path in [shared, single, open] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use sonic_rs::{Deserialize, Serialize};
+use crate::common::config::Environment;
+
+/// 接入站点，不同站点的账户互不相通
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    /// www.okx.com
+    #[default]
+    Global,
+    /// my.okx.com（欧洲经济区）
+    Eea,
+    /// app.okx.com（美国）
+    Us,
+    /// 部署在 AWS 的全球站节点，服务器在 AWS 东京时延迟更低，只有实盘
+    Aws,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Global => write!(f, "global"),
+            Region::Eea => write!(f, "eea"),
+            Region::Us => write!(f, "us"),
+            Region::Aws => write!(f, "aws"),
+        }
+    }
+}
+
+/// 一个站点的 REST 和三个 WebSocket 地址
+///
+/// 模拟盘的 REST 与实盘同一个域名，通过请求头 x-simulated-trading: 1 区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointProfile {
+    pub rest: &'static str,
+    pub ws_public: &'static str,
+    pub ws_private: &'static str,
+    pub ws_business: &'static str,
+}
+
+impl EndpointProfile {
+    pub const GLOBAL: EndpointProfile = EndpointProfile {
+        rest: "https://www.okx.com",
+        ws_public: "wss://ws.okx.com:8443/ws/v5/public",
+        ws_private: "wss://ws.okx.com:8443/ws/v5/private",
+        ws_business: "wss://ws.okx.com:8443/ws/v5/business",
+    };
+    pub const GLOBAL_DEMO: EndpointProfile = EndpointProfile {
+        rest: "https://www.okx.com",
+        ws_public: "wss://wspap.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wspap.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wspap.okx.com:8443/ws/v5/business",
+    };
+    pub const EEA: EndpointProfile = EndpointProfile {
+        rest: "https://eea.okx.com",
+        ws_public: "wss://wseea.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wseea.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wseea.okx.com:8443/ws/v5/business",
+    };
+    pub const EEA_DEMO: EndpointProfile = EndpointProfile {
+        rest: "https://eea.okx.com",
+        ws_public: "wss://wseeapap.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wseeapap.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wseeapap.okx.com:8443/ws/v5/business",
+    };
+    pub const US: EndpointProfile = EndpointProfile {
+        rest: "https://us.okx.com",
+        ws_public: "wss://wsus.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wsus.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wsus.okx.com:8443/ws/v5/business",
+    };
+    pub const US_DEMO: EndpointProfile = EndpointProfile {
+        rest: "https://us.okx.com",
+        ws_public: "wss://wsuspap.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wsuspap.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wsuspap.okx.com:8443/ws/v5/business",
+    };
+    pub const AWS: EndpointProfile = EndpointProfile {
+        rest: "https://aws.okx.com",
+        ws_public: "wss://wsaws.okx.com:8443/ws/v5/public",
+        ws_private: "wss://wsaws.okx.com:8443/ws/v5/private",
+        ws_business: "wss://wsaws.okx.com:8443/ws/v5/business",
+    };
+
+    /// 站点和环境对应的地址，AWS 没有模拟盘返回 None
+    pub fn of(region: Region, environment: Environment) -> Option<EndpointProfile> {
+        match (region, environment) {
+            (Region::Global, Environment::Live) => Some(EndpointProfile::GLOBAL),
+            (Region::Global, Environment::Demo) => Some(EndpointProfile::GLOBAL_DEMO),
+            (Region::Eea, Environment::Live) => Some(EndpointProfile::EEA),
+            (Region::Eea, Environment::Demo) => Some(EndpointProfile::EEA_DEMO),
+            (Region::Us, Environment::Live) => Some(EndpointProfile::US),
+            (Region::Us, Environment::Demo) => Some(EndpointProfile::US_DEMO),
+            (Region::Aws, Environment::Live) => Some(EndpointProfile::AWS),
+            (Region::Aws, Environment::Demo) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod endpoint_test {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        assert_eq!(EndpointProfile::of(Region::Aws, Environment::Live).unwrap().ws_public, "wss://wsaws.okx.com:8443/ws/v5/public");
+        assert_eq!(EndpointProfile::of(Region::Aws, Environment::Demo), None);
+        assert_eq!(EndpointProfile::of(Region::Global, Environment::Demo).unwrap().rest, EndpointProfile::GLOBAL.rest);
+        // 每个站点的四个地址属于同一个主机
+        for region in [Region::Global, Region::Eea, Region::Us, Region::Aws] {
+            for environment in [Environment::Live, Environment::Demo] {
+                let Some(profile) = EndpointProfile::of(region, environment) else { continue };
+                let host = |url: &'static str| url.split('/').nth(2).unwrap();
+                assert_eq!(host(profile.ws_public), host(profile.ws_private), "{} {}", region, environment);
+                assert_eq!(host(profile.ws_public), host(profile.ws_business), "{} {}", region, environment);
+                assert!(profile.ws_business.ends_with("/ws/v5/business"));
+            }
+        }
+    }
+}
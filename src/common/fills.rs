@@ -0,0 +1,546 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use dashmap::DashMap;
+use log::warn;
+use sonic_rs::{from_str, to_string, Deserialize, Serialize};
+use crate::common::order_store::OrderData;
+use crate::common::rest_api::OkxResponse;
+use crate::common::instrument::INSTRUMENT_REGISTRY;
+use crate::common::utils::{str_to_f64, HttpClientSimulation};
+
+pub const FILL_JOURNAL_PATH: &str = "data/fills.jsonl";
+
+/// 成交的流动性方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+    Unknown,
+}
+
+impl Liquidity {
+    /// execType: M 为 maker，T 为 taker
+    pub fn from_exec_type(exec_type: &str) -> Liquidity {
+        match exec_type {
+            "M" => Liquidity::Maker,
+            "T" => Liquidity::Taker,
+            _ => Liquidity::Unknown,
+        }
+    }
+}
+
+/// 一笔成交记录，journal 文件中每行一条
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    pub inst_id: String,
+    pub trade_id: String,
+    pub ord_id: String,
+    pub cl_ord_id: String,
+    pub side: String,
+    pub pos_side: String,
+    pub px: String,
+    pub sz: String,
+    pub liquidity: Liquidity,
+    /// 手续费，负数表示扣除，正数表示返佣
+    pub fee: String,
+    pub fee_ccy: String,
+    /// 成交时间（毫秒）
+    pub ts: u64,
+    /// 交易所计算的本笔成交收益（fillPnl），单位同已实现盈亏，旧日志中没有时为空
+    #[serde(default)]
+    pub fill_pnl: String,
+}
+
+impl Fill {
+    fn key(&self) -> (String, String) {
+        (self.inst_id.clone(), self.trade_id.clone())
+    }
+
+    /// 成交顺序：先按时间，同一毫秒内按 tradeId（同一产品内递增）
+    fn sort_key(&self) -> (u64, u64) {
+        (self.ts, self.trade_id.parse::<u64>().unwrap_or(0))
+    }
+
+    /// 交易所给出的成交收益，为空时由本地持仓成本计算
+    fn exchange_pnl(&self) -> Option<f64> {
+        self.fill_pnl.parse::<f64>().ok()
+    }
+
+    /// 带符号的成交张数，卖出为负
+    fn signed_sz(&self) -> f64 {
+        let sz = str_to_f64(&self.sz);
+        if self.side == "sell" { -sz } else { sz }
+    }
+
+    /// 从 orders 频道推送中取出最新一笔成交，没有成交时返回 None
+    pub fn from_order_data(data: &OrderData) -> Option<Fill> {
+        if data.trade_id.is_empty() || str_to_f64(&data.fill_sz) == 0.0 {
+            return None;
+        }
+        Some(Fill {
+            inst_id: data.inst_id.clone(),
+            trade_id: data.trade_id.clone(),
+            ord_id: data.ord_id.clone(),
+            cl_ord_id: data.cl_ord_id.clone(),
+            side: data.side.clone(),
+            pos_side: data.pos_side.clone(),
+            px: data.fill_px.clone(),
+            sz: data.fill_sz.clone(),
+            liquidity: Liquidity::from_exec_type(&data.exec_type),
+            fee: data.fill_fee.clone(),
+            fee_ccy: data.fill_fee_ccy.clone(),
+            ts: data.fill_time.parse::<u64>().unwrap_or(0),
+            fill_pnl: data.fill_pnl.clone(),
+        })
+    }
+}
+
+/// GET /api/v5/trade/fills 和 /api/v5/trade/fills-history 的单条数据
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FillData {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub trade_id: String,
+    pub ord_id: String,
+    pub cl_ord_id: String,
+    pub bill_id: String,
+    pub tag: String,
+    pub fill_px: String,
+    pub fill_sz: String,
+    pub fill_pnl: String,
+    pub side: String,
+    pub pos_side: String,
+    pub exec_type: String,
+    pub fee_ccy: String,
+    pub fee: String,
+    pub ts: String,
+    pub fill_time: String,
+}
+
+impl From<&FillData> for Fill {
+    fn from(data: &FillData) -> Fill {
+        let ts = if data.fill_time.is_empty() { &data.ts } else { &data.fill_time };
+        Fill {
+            inst_id: data.inst_id.clone(),
+            trade_id: data.trade_id.clone(),
+            ord_id: data.ord_id.clone(),
+            cl_ord_id: data.cl_ord_id.clone(),
+            side: data.side.clone(),
+            pos_side: data.pos_side.clone(),
+            px: data.fill_px.clone(),
+            sz: data.fill_sz.clone(),
+            liquidity: Liquidity::from_exec_type(&data.exec_type),
+            fee: data.fee.clone(),
+            fee_ccy: data.fee_ccy.clone(),
+            ts: ts.parse::<u64>().unwrap_or(0),
+            fill_pnl: data.fill_pnl.clone(),
+        }
+    }
+}
+
+/// GET /api/v5/trade/fills，近 3 天的成交
+pub async fn fetch_fills(inst_type: &str, inst_id: Option<&str>, begin: Option<&str>, end: Option<&str>) -> Result<Vec<FillData>, Box<dyn std::error::Error>> {
+    fetch_fill_data("/api/v5/trade/fills", inst_type, inst_id, begin, end, None).await
+}
+
+/// GET /api/v5/trade/fills-history，近 3 个月的成交
+pub async fn fetch_fills_history(inst_type: &str, inst_id: Option<&str>, begin: Option<&str>, end: Option<&str>) -> Result<Vec<FillData>, Box<dyn std::error::Error>> {
+    fetch_fill_data("/api/v5/trade/fills-history", inst_type, inst_id, begin, end, None).await
+}
+
+/// 单页最多 100 条，按 billId 从新到旧，after 为上一页最后一条的 billId
+async fn fetch_fill_data(path: &str, inst_type: &str, inst_id: Option<&str>, begin: Option<&str>, end: Option<&str>, after: Option<&str>) -> Result<Vec<FillData>, Box<dyn std::error::Error>> {
+    let mut params = vec![("instType", inst_type)];
+    if let Some(inst_id) = inst_id {
+        params.push(("instId", inst_id));
+    }
+    if let Some(begin) = begin {
+        params.push(("begin", begin));
+    }
+    if let Some(end) = end {
+        params.push(("end", end));
+    }
+    if let Some(after) = after {
+        params.push(("after", after));
+    }
+    let response = HttpClientSimulation::get(path, Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<FillData>>(&text)?.into_result()
+}
+
+/// fills 接口单页条数
+const FILLS_PAGE: usize = 100;
+
+/// 合约面值和类型，非合约产品（现货）按面值 1 的正向合约处理
+fn contract_spec(inst_id: &str) -> (f64, bool) {
+    match INSTRUMENT_REGISTRY.get(inst_id) {
+        Some(instrument) if instrument.is_derivative() => (instrument.contract_value(), instrument.is_inverse()),
+        Some(_) => (1.0, false),
+        None => (1.0, false),
+    }
+}
+
+/// 单个产品单个持仓方向的持仓成本
+#[derive(Debug, Clone, Copy, Default)]
+struct PnlState {
+    /// 带符号的持仓张数
+    pos: f64,
+    /// 开仓均价，币本位合约使用调和平均
+    avg_px: f64,
+    realized_pnl: f64,
+}
+
+impl PnlState {
+    /// 按一笔成交更新持仓，返回本笔实现的盈亏
+    /// 正向合约盈亏单位为计价币，反向合约盈亏单位为结算币
+    fn apply(&mut self, signed_sz: f64, px: f64, ct_val: f64, inverse: bool) -> f64 {
+        if px <= 0.0 || signed_sz == 0.0 {
+            return 0.0;
+        }
+        let mut pnl = 0.0;
+        let mut remaining = signed_sz;
+        if self.pos != 0.0 && self.pos.signum() != signed_sz.signum() {
+            let closed = remaining.abs().min(self.pos.abs());
+            let direction = self.pos.signum();
+            pnl = if inverse {
+                closed * ct_val * (1.0 / self.avg_px - 1.0 / px) * direction
+            } else {
+                closed * ct_val * (px - self.avg_px) * direction
+            };
+            self.pos -= closed * direction;
+            remaining += closed * direction;
+            if self.pos.abs() < 1e-12 {
+                self.pos = 0.0;
+                self.avg_px = 0.0;
+            }
+        }
+        if remaining.abs() > 1e-12 {
+            let new_pos = self.pos + remaining;
+            self.avg_px = if self.pos == 0.0 {
+                px
+            } else if inverse {
+                new_pos.abs() / (self.pos.abs() / self.avg_px + remaining.abs() / px)
+            } else {
+                (self.pos.abs() * self.avg_px + remaining.abs() * px) / new_pos.abs()
+            };
+            self.pos = new_pos;
+        }
+        self.realized_pnl += pnl;
+        pnl
+    }
+
+    /// 按一笔成交更新持仓，已实现盈亏以交易所的 fillPnl 为准
+    ///
+    /// 日志开始时已有持仓（首次运行或日志被删除）时本地持仓成本从 0 开始，
+    /// 本地计算的平仓盈亏是错的，只在没有 fillPnl 时使用
+    fn apply_fill(&mut self, fill: &Fill, ct_val: f64, inverse: bool) -> f64 {
+        let local = self.apply(fill.signed_sz(), str_to_f64(&fill.px), ct_val, inverse);
+        match fill.exchange_pnl() {
+            Some(pnl) => {
+                self.realized_pnl += pnl - local;
+                pnl
+            }
+            None => local,
+        }
+    }
+}
+
+/// 成交日志：追加写入本地文件，同时维护每个产品的已实现盈亏和手续费
+pub struct FillJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+    fills: RwLock<Vec<Fill>>,
+    seen: Mutex<HashSet<(String, String)>>,
+    /// (instId, posSide) -> 持仓成本
+    pnl: DashMap<(String, String), PnlState>,
+    /// (instId, feeCcy) -> 累计手续费
+    fees: DashMap<(String, String), f64>,
+}
+
+impl FillJournal {
+    /// 打开（不存在则创建）journal 文件，并回放已有记录
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FillJournal, Box<dyn std::error::Error>> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).read(true).open(&path)?;
+        let journal = FillJournal {
+            path: path.clone(),
+            file: Mutex::new(file),
+            fills: RwLock::new(Vec::new()),
+            seen: Mutex::new(HashSet::new()),
+            pnl: DashMap::new(),
+            fees: DashMap::new(),
+        };
+        let reader = BufReader::new(File::open(&path)?);
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match from_str::<Fill>(&line) {
+                Ok(fill) => {
+                    if journal.seen.lock().unwrap().insert(fill.key()) {
+                        journal.apply(fill);
+                    }
+                }
+                Err(e) => warn!("fill journal {} 跳过无法解析的行 {} {}", path.display(), e, line),
+            }
+        }
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 记录一笔成交，重复的 tradeId 会被忽略（返回 None），否则返回已实现盈亏的变化（不含手续费）
+    ///
+    /// 晚到的历史成交会触发重算，此时返回值是重算前后该持仓已实现盈亏的差值
+    pub fn record(&self, fill: Fill) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+        let key = fill.key();
+        // 先占住 tradeId，并发记录同一笔成交时只有一个会写文件
+        if !self.seen.lock().unwrap().insert(key.clone()) {
+            return Ok(None);
+        }
+        let written = to_string(&fill).map_err(|e| e.into()).and_then(|line| {
+            let mut file = self.file.lock().unwrap();
+            writeln!(file, "{}", line)?;
+            file.flush()?;
+            Ok::<(), Box<dyn std::error::Error>>(())
+        });
+        if let Err(e) = written {
+            self.seen.lock().unwrap().remove(&key);
+            return Err(e);
+        }
+        Ok(Some(self.apply(fill)))
+    }
+
+    /// 从 REST /trade/fills 补拉 begin 之后的成交（例如断线期间的成交），翻页直到取完
+    ///
+    /// 返回新记录的成交及其已实现盈亏变化，由调用方计入风控
+    pub async fn sync_from_rest(&self, inst_type: &str, begin: Option<&str>) -> Result<Vec<(Fill, f64)>, Box<dyn std::error::Error>> {
+        let mut data = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page = fetch_fill_data("/api/v5/trade/fills", inst_type, None, begin, None, after.as_deref()).await?;
+            let full = page.len() >= FILLS_PAGE;
+            after = page.last().map(|f| f.bill_id.clone());
+            data.extend(page);
+            if !full || after.is_none() {
+                break;
+            }
+        }
+        // 接口从新到旧返回，按时间顺序记录，避免逐笔触发重放
+        let mut recorded = Vec::new();
+        for fill in data.iter().rev() {
+            let fill = Fill::from(fill);
+            if let Some(pnl) = self.record(fill.clone())? {
+                recorded.push((fill, pnl));
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// 最后一笔成交的时间，用作补拉的起点
+    pub fn last_ts(&self) -> Option<u64> {
+        self.fills.read().unwrap().last().map(|f| f.ts)
+    }
+
+    /// 按时间顺序写入内存并更新持仓成本，调用方负责 seen 去重
+    fn apply(&self, fill: Fill) -> f64 {
+        let (ct_val, inverse) = contract_spec(&fill.inst_id);
+        *self.fees.entry((fill.inst_id.clone(), fill.fee_ccy.clone())).or_default() += str_to_f64(&fill.fee);
+        let position = (fill.inst_id.clone(), fill.pos_side.clone());
+        // 持有 fills 写锁直到持仓成本更新完，保证同一持仓的成交按顺序计算
+        let mut fills = self.fills.write().unwrap();
+        let index = fills.partition_point(|f| f.sort_key() <= fill.sort_key());
+        // REST 补拉的历史成交可能晚于推送到达，均价和盈亏依赖成交顺序，需要按顺序重放
+        let out_of_order = fills[index..].iter().any(|f| f.inst_id == fill.inst_id && f.pos_side == fill.pos_side);
+        let mut state = self.pnl.entry(position.clone()).or_default();
+        if !out_of_order {
+            let pnl = state.apply_fill(&fill, ct_val, inverse);
+            fills.insert(index, fill);
+            return pnl;
+        }
+        fills.insert(index, fill);
+        let before = state.realized_pnl;
+        let mut rebuilt = PnlState::default();
+        for f in fills.iter().filter(|f| f.inst_id == position.0 && f.pos_side == position.1) {
+            rebuilt.apply_fill(f, ct_val, inverse);
+        }
+        *state = rebuilt;
+        rebuilt.realized_pnl - before
+    }
+
+    /// 查询 [start_ms, end_ms) 区间内的成交
+    pub fn query(&self, start_ms: u64, end_ms: u64) -> Vec<Fill> {
+        let fills = self.fills.read().unwrap();
+        let begin = fills.partition_point(|f| f.ts < start_ms);
+        let end = fills.partition_point(|f| f.ts < end_ms);
+        fills[begin..end.max(begin)].to_vec()
+    }
+
+    /// [start_ms, end_ms) 区间内的成交及各自的已实现盈亏（不含手续费）
+    ///
+    /// 持仓成本从日志开头按顺序重放，区间之前的成交只用来建立持仓
+    pub fn query_pnl(&self, start_ms: u64, end_ms: u64) -> Vec<(Fill, f64)> {
+        let fills = self.fills.read().unwrap();
+        let mut states: HashMap<(String, String), PnlState> = HashMap::new();
+        let mut result = Vec::new();
+        for fill in fills.iter().take_while(|f| f.ts < end_ms) {
+            let (ct_val, inverse) = contract_spec(&fill.inst_id);
+            let state = states.entry((fill.inst_id.clone(), fill.pos_side.clone())).or_default();
+            let pnl = state.apply_fill(fill, ct_val, inverse);
+            if fill.ts >= start_ms {
+                result.push((fill.clone(), pnl));
+            }
+        }
+        result
+    }
+
+    /// 某个产品的已实现盈亏（不含手续费）
+    pub fn realized_pnl(&self, inst_id: &str) -> f64 {
+        self.pnl
+            .iter()
+            .filter(|e| e.key().0 == inst_id)
+            .map(|e| e.realized_pnl)
+            .sum()
+    }
+
+    /// 某个产品按手续费币种汇总的手续费
+    pub fn fees(&self, inst_id: &str) -> Vec<(String, f64)> {
+        self.fees
+            .iter()
+            .filter(|e| e.key().0 == inst_id)
+            .map(|e| (e.key().1.clone(), *e.value()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fills.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fills.read().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod fills_test {
+    use super::*;
+
+    fn fill(trade_id: &str, side: &str, px: &str, sz: &str, ts: u64) -> Fill {
+        Fill {
+            inst_id: "TEST-USDT-SWAP".to_string(),
+            trade_id: trade_id.to_string(),
+            ord_id: "1".to_string(),
+            cl_ord_id: String::new(),
+            side: side.to_string(),
+            pos_side: "net".to_string(),
+            px: px.to_string(),
+            sz: sz.to_string(),
+            liquidity: Liquidity::Maker,
+            fee: "-0.01".to_string(),
+            fee_ccy: "USDT".to_string(),
+            ts,
+            fill_pnl: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_pnl_linear_and_inverse() {
+        let mut linear = PnlState::default();
+        linear.apply(2.0, 100.0, 0.1, false);
+        linear.apply(2.0, 110.0, 0.1, false);
+        assert!((linear.avg_px - 105.0).abs() < 1e-9);
+        let pnl = linear.apply(-3.0, 120.0, 0.1, false);
+        assert!((pnl - 4.5).abs() < 1e-9);
+        // 反手：平掉剩余 1 张再开空 1 张
+        let pnl = linear.apply(-2.0, 100.0, 0.1, false);
+        assert!((pnl + 0.5).abs() < 1e-9);
+        assert_eq!(linear.pos, -1.0);
+        assert_eq!(linear.avg_px, 100.0);
+
+        let mut inverse = PnlState::default();
+        inverse.apply(100.0, 50000.0, 100.0, true);
+        let pnl = inverse.apply(-100.0, 55000.0, 100.0, true);
+        assert!((pnl - 10000.0 * (1.0 / 50000.0 - 1.0 / 55000.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_journal_persist_and_query() {
+        let path = std::env::temp_dir().join(format!("okx_fills_{}.jsonl", uuid::Uuid::new_v4()));
+        {
+            let journal = FillJournal::open(&path).unwrap();
+            assert_eq!(journal.record(fill("1", "buy", "100", "1", 1000)).unwrap(), Some(0.0));
+            assert_eq!(journal.record(fill("2", "sell", "110", "1", 3000)).unwrap(), Some(10.0));
+            assert_eq!(journal.record(fill("2", "sell", "110", "1", 3000)).unwrap(), None);
+            assert!(journal.record(fill("3", "buy", "105", "1", 2000)).unwrap().is_some());
+        }
+        let journal = FillJournal::open(&path).unwrap();
+        assert_eq!(journal.len(), 3);
+        // 重放按时间顺序：100 买、105 买（均价 102.5）、110 卖出 1 张
+        assert!((journal.realized_pnl("TEST-USDT-SWAP") - 7.5).abs() < 1e-9);
+        let fills = journal.query(1500, 3000);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].trade_id, "3");
+        let fees = journal.fees("TEST-USDT-SWAP");
+        assert_eq!(fees[0].0, "USDT");
+        assert!((fees[0].1 + 0.03).abs() < 1e-9);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_out_of_order_fill_replays() {
+        let path = std::env::temp_dir().join(format!("okx_fills_{}.jsonl", uuid::Uuid::new_v4()));
+        let journal = FillJournal::open(&path).unwrap();
+        journal.record(fill("1", "buy", "100", "1", 1000)).unwrap();
+        assert_eq!(journal.record(fill("3", "sell", "110", "1", 3000)).unwrap(), Some(10.0));
+        // 补拉到更早的一笔买入：正确顺序下均价 110，卖出没有盈亏，返回值抵消之前记入的 10
+        assert_eq!(journal.record(fill("2", "buy", "120", "1", 2000)).unwrap(), Some(-10.0));
+        assert!(journal.realized_pnl("TEST-USDT-SWAP").abs() < 1e-9);
+        let state = *journal.pnl.get(&("TEST-USDT-SWAP".to_string(), "net".to_string())).unwrap();
+        assert_eq!((state.pos, state.avg_px), (1.0, 110.0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_query_pnl() {
+        let path = std::env::temp_dir().join(format!("okx_fills_{}.jsonl", uuid::Uuid::new_v4()));
+        let journal = FillJournal::open(&path).unwrap();
+        journal.record(fill("1", "buy", "100", "2", 1000)).unwrap();
+        journal.record(fill("2", "sell", "110", "1", 2000)).unwrap();
+        journal.record(fill("3", "sell", "120", "1", 3000)).unwrap();
+        // 区间之前的买入只用来建立持仓成本
+        let pnl: Vec<(String, f64)> = journal.query_pnl(1500, 3000).into_iter().map(|(f, pnl)| (f.trade_id, pnl)).collect();
+        assert_eq!(pnl.len(), 1);
+        assert_eq!(pnl[0].0, "2");
+        assert!((pnl[0].1 - 10.0).abs() < 1e-9);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_exchange_fill_pnl() {
+        let path = std::env::temp_dir().join(format!("okx_fills_{}.jsonl", uuid::Uuid::new_v4()));
+        let journal = FillJournal::open(&path).unwrap();
+        // 日志从持仓中途开始：第一笔是平仓卖出，本地没有持仓成本，以交易所的 fillPnl 为准
+        let mut close = fill("1", "sell", "110", "1", 1000);
+        close.fill_pnl = "5".to_string();
+        assert_eq!(journal.record(close).unwrap(), Some(5.0));
+        assert!((journal.realized_pnl("TEST-USDT-SWAP") - 5.0).abs() < 1e-9);
+        // 补拉到更早的成交时重放仍然使用 fillPnl
+        let mut early = fill("0", "buy", "100", "1", 500);
+        early.fill_pnl = "0".to_string();
+        assert_eq!(journal.record(early).unwrap(), Some(0.0));
+        assert!((journal.realized_pnl("TEST-USDT-SWAP") - 5.0).abs() < 1e-9);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
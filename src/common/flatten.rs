@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use log::{info, warn};
+use sonic_rs::{from_str, json, Deserialize, Serialize};
+use crate::common::account::fetch_positions;
+use crate::common::algo::{cancel_algos, orders_algo_pending, AlgoAck, AlgoOrdType};
+use crate::common::order_store::{cancel_batch_orders, fetch_orders_pending};
+use crate::common::rest_api::OkxResponse;
+use crate::common::utils::HttpClientSimulation;
+use crate::common::ws_api::OpAckData;
+
+/// cancel-batch-orders 单次最多 20 个
+const CANCEL_BATCH_SIZE: usize = 20;
+/// cancel-algos 单次最多 10 个
+const CANCEL_ALGO_BATCH_SIZE: usize = 10;
+/// orders-pending 单次最多返回 100 条，撤单后重新查询，最多查询的轮数
+const MAX_CANCEL_ROUNDS: usize = 5;
+
+/// POST /api/v5/trade/close-position 的返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClosePositionAck {
+    pub inst_id: String,
+    pub pos_side: String,
+    pub cl_ord_id: String,
+    pub tag: String,
+}
+
+/// 市价全平单个仓位
+/// pos_side 单向持仓传 None（net），双向持仓传 long / short；auto_cxl 为 true 时先撤掉该产品的挂单
+pub async fn close_position(inst_id: &str, mgn_mode: &str, pos_side: Option<&str>, auto_cxl: bool) -> Result<ClosePositionAck, Box<dyn std::error::Error>> {
+    let mut body = json!({
+        "instId": inst_id,
+        "mgnMode": mgn_mode,
+        "autoCxl": auto_cxl,
+    });
+    if let Some(pos_side) = pos_side {
+        body["posSide"] = json!(pos_side);
+    }
+    let response = HttpClientSimulation::post("/api/v5/trade/close-position", body).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<ClosePositionAck>>(&text)?
+        .into_result()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "close-position empty".into())
+}
+
+/// 单个产品的清仓结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstrumentFlatten {
+    pub canceled_orders: usize,
+    pub canceled_algos: usize,
+    /// 已平掉的仓位方向 net / long / short
+    pub closed_positions: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// flatten_all 的结果，按 instId 汇总
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlattenReport {
+    pub instruments: BTreeMap<String, InstrumentFlatten>,
+    /// 不属于单个产品的错误，如查询失败
+    pub errors: Vec<String>,
+}
+
+impl FlattenReport {
+    fn entry(&mut self, inst_id: &str) -> &mut InstrumentFlatten {
+        self.instruments.entry(inst_id.to_string()).or_default()
+    }
+
+    /// 所有步骤都成功
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.instruments.values().all(|i| i.errors.is_empty())
+    }
+
+    /// 按 ordId 对应回执，回执顺序不保证与请求一致
+    fn on_cancel_acks(&mut self, acks: &[OpAckData], orders: &[(&str, &str)]) {
+        for (inst_id, ord_id) in orders.iter() {
+            let entry = self.entry(inst_id);
+            match acks.iter().find(|ack| ack.ord_id == *ord_id) {
+                Some(ack) if ack.s_code == "0" => entry.canceled_orders += 1,
+                Some(ack) => entry.errors.push(format!("撤单 {} 失败 {} {}", ord_id, ack.s_code, ack.s_msg)),
+                None => entry.errors.push(format!("撤单 {} 没有回执", ord_id)),
+            }
+        }
+    }
+
+    /// 按 algoId 对应回执
+    fn on_algo_acks(&mut self, acks: &[AlgoAck], algos: &[(&str, &str)]) {
+        for (inst_id, algo_id) in algos.iter() {
+            let entry = self.entry(inst_id);
+            match acks.iter().find(|ack| ack.algo_id == *algo_id) {
+                Some(ack) if ack.s_code == "0" => entry.canceled_algos += 1,
+                Some(ack) => entry.errors.push(format!("撤策略单 {} 失败 {} {}", algo_id, ack.s_code, ack.s_msg)),
+                None => entry.errors.push(format!("撤策略单 {} 没有回执", algo_id)),
+            }
+        }
+    }
+}
+
+impl fmt::Display for FlattenReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (inst_id, r) in self.instruments.iter() {
+            writeln!(
+                f,
+                "{} 撤单 {} 撤策略单 {} 平仓 {:?} 错误 {:?}",
+                inst_id, r.canceled_orders, r.canceled_algos, r.closed_positions, r.errors
+            )?;
+        }
+        for e in self.errors.iter() {
+            writeln!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+/// 紧急清仓：撤掉所有挂单和策略委托，再市价平掉所有仓位，单步失败不中断后续步骤
+pub async fn flatten_all() -> FlattenReport {
+    let mut report = FlattenReport::default();
+    cancel_all_orders(&mut report).await;
+    cancel_all_algos(&mut report).await;
+    close_all_positions(&mut report).await;
+    if report.is_clean() {
+        info!("清仓完成\n{}", report);
+    } else {
+        warn!("清仓存在错误\n{}", report);
+    }
+    report
+}
+
+async fn cancel_all_orders(report: &mut FlattenReport) {
+    for _ in 0..MAX_CANCEL_ROUNDS {
+        let pending = match fetch_orders_pending(None, None).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                report.errors.push(format!("查询挂单失败 {}", e));
+                return;
+            }
+        };
+        if pending.is_empty() {
+            return;
+        }
+        let orders = pending.iter().map(|o| (o.inst_id.as_str(), o.ord_id.as_str())).collect::<Vec<_>>();
+        for batch in orders.chunks(CANCEL_BATCH_SIZE) {
+            match cancel_batch_orders(batch).await {
+                Ok(acks) => report.on_cancel_acks(&acks, batch),
+                Err(e) => {
+                    for (inst_id, _) in batch.iter() {
+                        report.entry(inst_id).errors.push(format!("批量撤单失败 {}", e));
+                    }
+                }
+            }
+        }
+        // 不足一页说明已经撤完
+        if pending.len() < 100 {
+            return;
+        }
+    }
+}
+
+async fn cancel_all_algos(report: &mut FlattenReport) {
+    let ord_types = [
+        AlgoOrdType::CONDITIONAL,
+        AlgoOrdType::OCO,
+        AlgoOrdType::TRIGGER,
+        AlgoOrdType::MOVE_ORDER_STOP,
+        AlgoOrdType::ICEBERG,
+        AlgoOrdType::TWAP,
+    ];
+    for ord_type in ord_types {
+        let pending = match orders_algo_pending(ord_type, None).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                report.errors.push(format!("查询 {} 策略单失败 {}", ord_type, e));
+                continue;
+            }
+        };
+        let algos = pending.iter().map(|a| (a.inst_id.as_str(), a.algo_id.as_str())).collect::<Vec<_>>();
+        for batch in algos.chunks(CANCEL_ALGO_BATCH_SIZE) {
+            match cancel_algos(batch).await {
+                Ok(acks) => report.on_algo_acks(&acks, batch),
+                Err(e) => {
+                    for (inst_id, _) in batch.iter() {
+                        report.entry(inst_id).errors.push(format!("撤策略单失败 {}", e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn close_all_positions(report: &mut FlattenReport) {
+    let positions = match fetch_positions(None).await {
+        Ok(positions) => positions,
+        Err(e) => {
+            report.errors.push(format!("查询持仓失败 {}", e));
+            return;
+        }
+    };
+    for p in positions.iter().filter(|p| !p.pos.is_empty() && p.pos != "0") {
+        let pos_side = if p.pos_side == "net" { None } else { Some(p.pos_side.as_str()) };
+        match close_position(&p.inst_id, &p.mgn_mode, pos_side, true).await {
+            Ok(_) => report.entry(&p.inst_id).closed_positions.push(p.pos_side.clone()),
+            Err(e) => report.entry(&p.inst_id).errors.push(format!("平仓 {} 失败 {}", p.pos_side, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod flatten_test {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = FlattenReport::default();
+        let batch = [("BTC-USDT-SWAP", "1"), ("ETH-USDT-SWAP", "2")];
+        // 回执顺序与请求不同
+        let acks = from_str::<Vec<OpAckData>>(r#"[{"ordId":"2","sCode":"51400","sMsg":"already filled"},{"ordId":"1","sCode":"0","sMsg":""}]"#).unwrap();
+        report.on_cancel_acks(&acks, &batch);
+        let algos = [("BTC-USDT-SWAP", "a1"), ("BTC-USDT-SWAP", "a2")];
+        let algo_acks = from_str::<Vec<AlgoAck>>(r#"[{"algoId":"a2","sCode":"0","sMsg":""}]"#).unwrap();
+        report.on_algo_acks(&algo_acks, &algos);
+        assert_eq!(report.instruments["BTC-USDT-SWAP"].canceled_algos, 1);
+        assert_eq!(report.instruments["BTC-USDT-SWAP"].errors, vec!["撤策略单 a1 没有回执".to_string()]);
+        report.entry("BTC-USDT-SWAP").closed_positions.push("net".to_string());
+        assert_eq!(report.instruments["BTC-USDT-SWAP"].canceled_orders, 1);
+        assert_eq!(report.instruments["ETH-USDT-SWAP"].errors.len(), 1);
+        assert!(!report.is_clean());
+        assert!(report.to_string().contains("BTC-USDT-SWAP"));
+    }
+}
@@ -0,0 +1,875 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::Deserializer;
+use sonic_rs::{from_str, Deserialize, Serialize};
+use crate::common::rest_api::OkxResponse;
+use tokio::sync::broadcast;
+use crate::common::utils::{str_to_f64, write_atomic, HttpClient};
+use crate::common::ws_api::InstType;
+
+/// 仓库中的产品列表，只读，作为没有缓存时的初始数据和测试数据
+///
+/// 字段为 snake_case，读取时也兼容 OKX 原始的 camelCase
+pub const INSTRUMENTS_PATH: &str = "data/instruments.json";
+/// 定时刷新写回的缓存文件，不纳入版本管理，启动时优先读取
+pub const INSTRUMENTS_CACHE_PATH: &str = "data/instruments.cache.json";
+/// 定时刷新间隔（秒）
+pub const REFRESH_INTERVAL_SECS: u64 = 3600;
+/// 事件通道容量，订阅者处理过慢时会丢失最旧的事件
+const EVENT_CAPACITY: usize = 1024;
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// GET /api/v5/public/instruments 返回的产品，覆盖 SPOT / MARGIN / SWAP / FUTURES / OPTION
+///
+/// OKX 对所有类型返回同一组字段，不适用的字段为空字符串，按类型取用的字段见 kind()。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Instrument {
+    #[serde(alias = "instType")]
+    pub inst_type: String,
+    #[serde(alias = "instId")]
+    pub inst_id: String,
+    #[serde(alias = "instIdCode", deserialize_with = "null_as_default")]
+    pub inst_id_code: u64,
+    /// 标的指数，币币和杠杆为空
+    pub uly: String,
+    #[serde(alias = "instFamily", deserialize_with = "null_as_default")]
+    pub inst_family: String,
+    /// 交易货币，仅币币 / 杠杆
+    #[serde(alias = "baseCcy", deserialize_with = "null_as_default")]
+    pub base_ccy: String,
+    /// 计价货币，仅币币 / 杠杆
+    #[serde(alias = "quoteCcy", deserialize_with = "null_as_default")]
+    pub quote_ccy: String,
+    /// 结算货币，仅衍生品
+    #[serde(alias = "settleCcy")]
+    pub settle_ccy: String,
+    #[serde(alias = "ctVal")]
+    pub ct_val: String,
+    #[serde(alias = "ctMult")]
+    pub ct_mult: String,
+    #[serde(alias = "ctValCcy")]
+    pub ct_val_ccy: String,
+    /// linear / inverse，仅交割和永续
+    #[serde(alias = "ctType")]
+    pub ct_type: String,
+    /// C / P，仅期权
+    #[serde(alias = "optType")]
+    pub opt_type: String,
+    /// 行权价，仅期权
+    pub stk: String,
+    #[serde(alias = "listTime")]
+    pub list_time: String,
+    /// 到期 / 交割时间（毫秒），仅交割和期权
+    #[serde(alias = "expTime")]
+    pub exp_time: String,
+    /// 交割合约的周期 this_week / next_week / quarter / next_quarter
+    pub alias: String,
+    pub lever: String,
+    #[serde(alias = "tickSz")]
+    pub tick_sz: String,
+    #[serde(alias = "lotSz")]
+    pub lot_sz: String,
+    #[serde(alias = "minSz")]
+    pub min_sz: String,
+    #[serde(alias = "maxLmtSz")]
+    pub max_lmt_sz: String,
+    #[serde(alias = "maxMktSz")]
+    pub max_mkt_sz: String,
+    #[serde(alias = "maxLmtAmt")]
+    pub max_lmt_amt: String,
+    #[serde(alias = "maxMktAmt", deserialize_with = "null_as_default")]
+    pub max_mkt_amt: String,
+    #[serde(alias = "maxTwapSz")]
+    pub max_twap_sz: String,
+    #[serde(alias = "maxIcebergSz")]
+    pub max_iceberg_sz: String,
+    #[serde(alias = "maxTriggerSz")]
+    pub max_trigger_sz: String,
+    #[serde(alias = "maxStopSz")]
+    pub max_stop_sz: String,
+    /// live / suspend / preopen / test
+    pub state: String,
+    #[serde(alias = "ruleType")]
+    pub rule_type: String,
+    #[serde(alias = "futureSettlement")]
+    pub future_settlement: bool,
+    #[serde(alias = "tradeQuoteCcyList", deserialize_with = "null_as_default")]
+    pub trade_quote_ccy_list: Vec<String>,
+    // 以下字段策略暂不使用，保留以便写回的缓存文件字段完整
+    #[serde(alias = "auctionEndTime")]
+    pub auction_end_time: String,
+    pub category: String,
+    #[serde(alias = "contTdSwTime")]
+    pub cont_td_sw_time: String,
+    #[serde(alias = "groupId")]
+    pub group_id: String,
+    #[serde(alias = "maxPlatOILmt", deserialize_with = "null_as_default")]
+    pub max_plat_oi_lmt: String,
+    #[serde(alias = "openType")]
+    pub open_type: String,
+    #[serde(alias = "posLmtAmt")]
+    pub pos_lmt_amt: String,
+    #[serde(alias = "posLmtPct")]
+    pub pos_lmt_pct: String,
+    #[serde(alias = "preMktSwTime")]
+    pub pre_mkt_sw_time: String,
+}
+
+/// 产品变化事件
+#[derive(Debug, Clone)]
+pub enum InstrumentEvent {
+    /// 新上线
+    Listed(Arc<Instrument>),
+    /// 下线（刷新结果中不再出现）
+    Delisted(Arc<Instrument>),
+    /// state 变化，如 live -> suspend
+    StateChanged { inst_id: String, old: String, new: String },
+    /// 价格精度变化，本地按 tick 整数保存的价格需要重建
+    TickSizeChanged { inst_id: String, old: String, new: String },
+    /// 下单数量精度或最小下单量变化
+    LotSizeChanged { inst_id: String, lot_sz: String, min_sz: String },
+}
+
+impl InstrumentEvent {
+    pub fn inst_id(&self) -> &str {
+        match self {
+            InstrumentEvent::Listed(i) | InstrumentEvent::Delisted(i) => &i.inst_id,
+            InstrumentEvent::StateChanged { inst_id, .. }
+            | InstrumentEvent::TickSizeChanged { inst_id, .. }
+            | InstrumentEvent::LotSizeChanged { inst_id, .. } => inst_id,
+        }
+    }
+}
+
+/// 比较同一类型产品的新旧列表，生成变化事件
+pub fn diff_instruments(old: &[Arc<Instrument>], new: &[Instrument]) -> Vec<InstrumentEvent> {
+    let old_map = old.iter().map(|i| (i.inst_id.as_str(), i)).collect::<HashMap<_, _>>();
+    let mut events = Vec::new();
+    for n in new.iter() {
+        let Some(o) = old_map.get(n.inst_id.as_str()) else {
+            events.push(InstrumentEvent::Listed(Arc::new(n.clone())));
+            continue;
+        };
+        if o.state != n.state {
+            events.push(InstrumentEvent::StateChanged { inst_id: n.inst_id.clone(), old: o.state.clone(), new: n.state.clone() });
+        }
+        if o.tick_sz != n.tick_sz {
+            events.push(InstrumentEvent::TickSizeChanged { inst_id: n.inst_id.clone(), old: o.tick_sz.clone(), new: n.tick_sz.clone() });
+        }
+        if o.lot_sz != n.lot_sz || o.min_sz != n.min_sz {
+            events.push(InstrumentEvent::LotSizeChanged { inst_id: n.inst_id.clone(), lot_sz: n.lot_sz.clone(), min_sz: n.min_sz.clone() });
+        }
+    }
+    for o in old.iter() {
+        if !new.iter().any(|n| n.inst_id == o.inst_id) {
+            events.push(InstrumentEvent::Delisted(o.clone()));
+        }
+    }
+    events
+}
+
+/// 按产品类型区分的特有字段
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstrumentKind<'a> {
+    Spot { base_ccy: &'a str, quote_ccy: &'a str },
+    Margin { base_ccy: &'a str, quote_ccy: &'a str },
+    Swap { settle_ccy: &'a str, inverse: bool },
+    Futures { settle_ccy: &'a str, inverse: bool, exp_time: u64, alias: &'a str },
+    Option { settle_ccy: &'a str, opt_type: &'a str, strike: f64, exp_time: u64 },
+    Unknown,
+}
+
+impl Instrument {
+    pub fn kind(&self) -> InstrumentKind<'_> {
+        match self.inst_type.as_str() {
+            InstType::SPOT => InstrumentKind::Spot { base_ccy: &self.base_ccy, quote_ccy: &self.quote_ccy },
+            InstType::MARGIN => InstrumentKind::Margin { base_ccy: &self.base_ccy, quote_ccy: &self.quote_ccy },
+            InstType::SWAP => InstrumentKind::Swap { settle_ccy: &self.settle_ccy, inverse: self.is_inverse() },
+            InstType::FUTURES => InstrumentKind::Futures {
+                settle_ccy: &self.settle_ccy,
+                inverse: self.is_inverse(),
+                exp_time: self.exp_time.parse().unwrap_or(0),
+                alias: &self.alias,
+            },
+            InstType::OPTION => InstrumentKind::Option {
+                settle_ccy: &self.settle_ccy,
+                opt_type: &self.opt_type,
+                strike: str_to_f64(&self.stk),
+                exp_time: self.exp_time.parse().unwrap_or(0),
+            },
+            _ => InstrumentKind::Unknown,
+        }
+    }
+
+    /// 币本位（反向）合约
+    pub fn is_inverse(&self) -> bool {
+        self.ct_type == "inverse"
+    }
+
+    /// 交割、永续和期权
+    pub fn is_derivative(&self) -> bool {
+        matches!(self.inst_type.as_str(), InstType::SWAP | InstType::FUTURES | InstType::OPTION)
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.state == "live"
+    }
+
+    /// 到期时间（毫秒），永续和现货为 None
+    pub fn expiry_ms(&self) -> Option<u64> {
+        self.exp_time.parse::<u64>().ok().filter(|t| *t > 0)
+    }
+
+    /// 每张合约的面值（ctVal * ctMult），单位为 ctValCcy，非合约产品为 1
+    pub fn contract_value(&self) -> f64 {
+        let ct_val = str_to_f64(&self.ct_val);
+        if ct_val <= 0.0 {
+            return 1.0;
+        }
+        let ct_mult = str_to_f64(&self.ct_mult);
+        if ct_mult > 0.0 { ct_val * ct_mult } else { ct_val }
+    }
+
+    /// 名义价值，单位为计价币：正向合约 张数 * 面值 * 价格，反向合约 张数 * 面值（USD）
+    pub fn notional_quote(&self, sz: f64, px: f64) -> f64 {
+        if self.is_inverse() {
+            sz.abs() * self.contract_value()
+        } else {
+            sz.abs() * self.contract_value() * px
+        }
+    }
+
+    /// 名义价值，单位为币：正向合约 张数 * 面值，反向合约 张数 * 面值 / 价格
+    pub fn notional_coin(&self, sz: f64, px: f64) -> f64 {
+        if !self.is_inverse() {
+            return sz.abs() * self.contract_value();
+        }
+        if px <= 0.0 {
+            return 0.0;
+        }
+        sz.abs() * self.contract_value() / px
+    }
+
+    /// 按开平仓价格计算盈亏，sz 带符号（多头为正），单位为结算币
+    /// 正向合约 sz * 面值 * (平仓价 - 开仓价)，反向合约 sz * 面值 * (1 / 开仓价 - 1 / 平仓价)
+    pub fn pnl(&self, sz: f64, open_px: f64, close_px: f64) -> f64 {
+        if !self.is_inverse() {
+            return sz * self.contract_value() * (close_px - open_px);
+        }
+        if open_px <= 0.0 || close_px <= 0.0 {
+            return 0.0;
+        }
+        sz * self.contract_value() * (1.0 / open_px - 1.0 / close_px)
+    }
+
+    /// 按杠杆计算的初始保证金，单位为结算币（反向合约为币）
+    pub fn initial_margin(&self, sz: f64, px: f64, lever: f64) -> f64 {
+        if lever <= 0.0 {
+            return 0.0;
+        }
+        if self.is_inverse() {
+            self.notional_coin(sz, px) / lever
+        } else {
+            self.notional_quote(sz, px) / lever
+        }
+    }
+
+    /// 把 ctValCcy 计的数量（正向合约为币，反向合约为 USD）换算成张数，按 lotSz 向下取整
+    pub fn quantity_to_sz(&self, quantity: f64) -> Option<String> {
+        let lot_sz = if self.lot_sz.is_empty() { &self.min_sz } else { &self.lot_sz };
+        let step = str_to_f64(lot_sz);
+        if step <= 0.0 || quantity < 0.0 {
+            return None;
+        }
+        // 加一个极小值，避免 0.3 / 0.1 之类的浮点误差被向下取整
+        let lots = (quantity / self.contract_value() / step + 1e-9).floor();
+        let decimals = lot_sz.split_once('.').map(|(_, d)| d.len()).unwrap_or(0);
+        Some(format!("{:.*}", decimals, lots * step))
+    }
+}
+
+/// 产品规则类型
+pub struct RuleType;
+impl RuleType {
+    /// 普通交易
+    pub const NORMAL: &'static str = "normal";
+    /// 盘前交易
+    pub const PRE_MARKET: &'static str = "pre_market";
+}
+
+/// 查询结果的排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InstrumentSort {
+    #[default]
+    InstId,
+    /// 上线时间从早到晚
+    ListTime,
+    /// 上线时间从晚到早
+    ListTimeDesc,
+    /// 最大杠杆从高到低
+    LeverDesc,
+}
+
+/// 产品查询条件，未设置的条件不过滤
+///
+/// ```ignore
+/// let ids = INSTRUMENT_REGISTRY.query_ids(
+///     &InstrumentQuery::new().inst_type(InstType::SWAP).live().ct_type("linear").settle_ccy("USDT").min_lever(50.0).sort(InstrumentSort::ListTime),
+/// );
+/// tx.send(subscribe_many(CHANNEL_TICKERS, &ids)).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InstrumentQuery {
+    inst_type: Option<String>,
+    ct_type: Option<String>,
+    settle_ccy: Option<String>,
+    category: Option<String>,
+    state: Option<String>,
+    min_lever: Option<f64>,
+    max_lever: Option<f64>,
+    inst_family: Option<String>,
+    uly: Option<String>,
+    listed_after: Option<u64>,
+    listed_before: Option<u64>,
+    rule_type: Option<String>,
+    sort: InstrumentSort,
+    limit: Option<usize>,
+}
+
+impl InstrumentQuery {
+    pub fn new() -> InstrumentQuery {
+        InstrumentQuery::default()
+    }
+
+    pub fn inst_type(mut self, inst_type: &str) -> InstrumentQuery {
+        self.inst_type = Some(inst_type.to_string());
+        self
+    }
+
+    /// linear / inverse
+    pub fn ct_type(mut self, ct_type: &str) -> InstrumentQuery {
+        self.ct_type = Some(ct_type.to_string());
+        self
+    }
+
+    pub fn settle_ccy(mut self, settle_ccy: &str) -> InstrumentQuery {
+        self.settle_ccy = Some(settle_ccy.to_string());
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> InstrumentQuery {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn state(mut self, state: &str) -> InstrumentQuery {
+        self.state = Some(state.to_string());
+        self
+    }
+
+    /// 只要 state 为 live 的产品
+    pub fn live(self) -> InstrumentQuery {
+        self.state("live")
+    }
+
+    /// 最大杠杆不低于 lever
+    pub fn min_lever(mut self, lever: f64) -> InstrumentQuery {
+        self.min_lever = Some(lever);
+        self
+    }
+
+    /// 最大杠杆不高于 lever
+    pub fn max_lever(mut self, lever: f64) -> InstrumentQuery {
+        self.max_lever = Some(lever);
+        self
+    }
+
+    pub fn inst_family(mut self, inst_family: &str) -> InstrumentQuery {
+        self.inst_family = Some(inst_family.to_string());
+        self
+    }
+
+    pub fn uly(mut self, uly: &str) -> InstrumentQuery {
+        self.uly = Some(uly.to_string());
+        self
+    }
+
+    /// 上线时间（毫秒）不早于 ms
+    pub fn listed_after(mut self, ms: u64) -> InstrumentQuery {
+        self.listed_after = Some(ms);
+        self
+    }
+
+    /// 上线时间（毫秒）早于 ms
+    pub fn listed_before(mut self, ms: u64) -> InstrumentQuery {
+        self.listed_before = Some(ms);
+        self
+    }
+
+    /// normal / pre_market，见 RuleType
+    pub fn rule_type(mut self, rule_type: &str) -> InstrumentQuery {
+        self.rule_type = Some(rule_type.to_string());
+        self
+    }
+
+    pub fn sort(mut self, sort: InstrumentSort) -> InstrumentQuery {
+        self.sort = sort;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> InstrumentQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, i: &Instrument) -> bool {
+        let eq = |filter: &Option<String>, value: &str| filter.as_deref().is_none_or(|f| f == value);
+        let lever = str_to_f64(&i.lever);
+        let list_time = i.list_time.parse::<u64>().unwrap_or(0);
+        eq(&self.inst_type, &i.inst_type)
+            && eq(&self.ct_type, &i.ct_type)
+            && eq(&self.settle_ccy, &i.settle_ccy)
+            && eq(&self.category, &i.category)
+            && eq(&self.state, &i.state)
+            && eq(&self.inst_family, &i.inst_family)
+            && eq(&self.uly, &i.uly)
+            && eq(&self.rule_type, &i.rule_type)
+            && self.min_lever.is_none_or(|min| lever >= min)
+            && self.max_lever.is_none_or(|max| lever <= max)
+            && self.listed_after.is_none_or(|t| list_time >= t)
+            && self.listed_before.is_none_or(|t| list_time < t)
+    }
+
+    fn sort_and_limit(&self, mut result: Vec<Arc<Instrument>>) -> Vec<Arc<Instrument>> {
+        let list_time = |i: &Instrument| i.list_time.parse::<u64>().unwrap_or(0);
+        match self.sort {
+            InstrumentSort::InstId => result.sort_by(|a, b| a.inst_id.cmp(&b.inst_id)),
+            InstrumentSort::ListTime => result.sort_by_key(|i| (list_time(i), i.inst_id.clone())),
+            InstrumentSort::ListTimeDesc => result.sort_by_key(|i| (std::cmp::Reverse(list_time(i)), i.inst_id.clone())),
+            InstrumentSort::LeverDesc => result.sort_by(|a, b| {
+                str_to_f64(&b.lever).total_cmp(&str_to_f64(&a.lever)).then_with(|| a.inst_id.cmp(&b.inst_id))
+            }),
+        }
+        if let Some(limit) = self.limit {
+            result.truncate(limit);
+        }
+        result
+    }
+}
+
+#[derive(Default)]
+struct RegistryInner {
+    by_id: HashMap<String, Arc<Instrument>>,
+    by_code: HashMap<u64, String>,
+    by_family: HashMap<String, Vec<String>>,
+    by_uly: HashMap<String, Vec<String>>,
+}
+
+impl RegistryInner {
+    fn insert(&mut self, instrument: Instrument) {
+        self.remove(&instrument.inst_id);
+        let inst_id = instrument.inst_id.clone();
+        if instrument.inst_id_code != 0 {
+            self.by_code.insert(instrument.inst_id_code, inst_id.clone());
+        }
+        if !instrument.inst_family.is_empty() {
+            self.by_family.entry(instrument.inst_family.clone()).or_default().push(inst_id.clone());
+        }
+        if !instrument.uly.is_empty() {
+            self.by_uly.entry(instrument.uly.clone()).or_default().push(inst_id.clone());
+        }
+        self.by_id.insert(inst_id, Arc::new(instrument));
+    }
+
+    fn remove(&mut self, inst_id: &str) -> Option<Arc<Instrument>> {
+        let old = self.by_id.remove(inst_id)?;
+        self.by_code.remove(&old.inst_id_code);
+        if let Some(ids) = self.by_family.get_mut(&old.inst_family) {
+            ids.retain(|id| id != inst_id);
+        }
+        if let Some(ids) = self.by_uly.get_mut(&old.uly) {
+            ids.retain(|id| id != inst_id);
+        }
+        Some(old)
+    }
+
+    fn collect(&self, ids: Option<&Vec<String>>) -> Vec<Arc<Instrument>> {
+        ids.map(|ids| ids.iter().filter_map(|id| self.by_id.get(id).cloned()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// 所有类型产品的注册表，支持按 instId / instIdCode / instFamily / uly 查询
+///
+/// MARGIN 与 SPOT 的 instId 相同，同时加载时后加载的覆盖先加载的，两者精度一致。
+pub struct InstrumentRegistry {
+    inner: RwLock<RegistryInner>,
+    events: broadcast::Sender<InstrumentEvent>,
+}
+
+impl Default for InstrumentRegistry {
+    fn default() -> Self {
+        InstrumentRegistry::new()
+    }
+}
+
+impl InstrumentRegistry {
+    pub fn new() -> InstrumentRegistry {
+        InstrumentRegistry {
+            inner: RwLock::new(RegistryInner::default()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// 订阅产品变化事件
+    pub fn subscribe(&self) -> broadcast::Receiver<InstrumentEvent> {
+        self.events.subscribe()
+    }
+
+    /// 用某个类型的最新产品列表替换本地数据，返回并广播变化事件
+    /// inst_family 不为空时只替换该品种（期权按品种查询）
+    pub fn apply_snapshot(&self, inst_type: &str, inst_family: Option<&str>, instruments: Vec<Instrument>) -> Vec<InstrumentEvent> {
+        let events = {
+            let mut inner = self.inner.write().unwrap();
+            let old = inner
+                .by_id
+                .values()
+                .filter(|i| i.inst_type == inst_type && inst_family.is_none_or(|f| i.inst_family == f))
+                .cloned()
+                .collect::<Vec<_>>();
+            let events = diff_instruments(&old, &instruments);
+            for event in events.iter() {
+                if let InstrumentEvent::Delisted(i) = event {
+                    inner.remove(&i.inst_id);
+                }
+            }
+            for instrument in instruments {
+                inner.insert(instrument);
+            }
+            events
+        };
+        self.publish(&events);
+        events
+    }
+
+    /// 处理 instruments 频道推送：只更新推送中的产品，不会判定其他产品下线
+    pub fn apply_updates(&self, instruments: Vec<Instrument>) -> Vec<InstrumentEvent> {
+        let events = {
+            let mut inner = self.inner.write().unwrap();
+            let old = instruments
+                .iter()
+                .filter_map(|i| inner.by_id.get(&i.inst_id).cloned())
+                .collect::<Vec<_>>();
+            let events = diff_instruments(&old, &instruments)
+                .into_iter()
+                .filter(|e| !matches!(e, InstrumentEvent::Delisted(_)))
+                .collect::<Vec<_>>();
+            for instrument in instruments {
+                inner.insert(instrument);
+            }
+            events
+        };
+        self.publish(&events);
+        events
+    }
+
+    fn publish(&self, events: &[InstrumentEvent]) {
+        for event in events.iter() {
+            info!("产品变化 {:?}", event);
+            // 没有订阅者时发送失败，忽略
+            let _ = self.events.send(event.clone());
+        }
+    }
+
+    /// 按 instId 排序写入缓存文件（snake_case），先写临时文件再替换
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let mut all = self.all();
+        all.sort_by(|a, b| a.inst_id.cmp(&b.inst_id));
+        let list = all.iter().map(|i| i.as_ref()).collect::<Vec<&Instrument>>();
+        write_atomic(path, sonic_rs::to_string_pretty(&list)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// 从 REST 刷新指定类型的产品，targets 为 (instType, instFamily)，成功后写回缓存文件
+    pub async fn refresh<P: AsRef<Path>>(&self, targets: &[(&str, Option<&str>)], path: P) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut changes = 0;
+        for (inst_type, inst_family) in targets.iter() {
+            let instruments = fetch_instruments(inst_type, *inst_family).await?;
+            if instruments.is_empty() {
+                // 空列表多半是接口异常，不当作全部下线处理
+                warn!("{} {:?} 产品列表为空，跳过", inst_type, inst_family);
+                continue;
+            }
+            changes += self.apply_snapshot(inst_type, *inst_family, instruments).len();
+        }
+        self.save(path)?;
+        Ok(changes)
+    }
+
+    /// 定时刷新，失败时保留当前数据
+    pub async fn refresh_loop(&'static self, targets: Vec<(&'static str, Option<&'static str>)>, interval_secs: u64) {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match self.refresh(&targets, INSTRUMENTS_CACHE_PATH).await {
+                Ok(changes) => info!("产品刷新完成，共 {} 个，变化 {}", self.len(), changes),
+                Err(e) => warn!("产品刷新失败 {}", e),
+            }
+        }
+    }
+
+    pub fn from_instruments(instruments: Vec<Instrument>) -> InstrumentRegistry {
+        let registry = InstrumentRegistry::new();
+        registry.insert_all(instruments);
+        registry
+    }
+
+    /// 从缓存文件加载
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InstrumentRegistry, Box<dyn std::error::Error>> {
+        let instruments = sonic_rs::from_reader::<BufReader<File>, Vec<Instrument>>(BufReader::new(File::open(path)?))?;
+        Ok(InstrumentRegistry::from_instruments(instruments))
+    }
+
+    pub fn insert(&self, instrument: Instrument) {
+        self.inner.write().unwrap().insert(instrument);
+    }
+
+    pub fn insert_all(&self, instruments: Vec<Instrument>) {
+        let mut inner = self.inner.write().unwrap();
+        for instrument in instruments {
+            inner.insert(instrument);
+        }
+    }
+
+    pub fn remove(&self, inst_id: &str) -> Option<Arc<Instrument>> {
+        self.inner.write().unwrap().remove(inst_id)
+    }
+
+    pub fn get(&self, inst_id: &str) -> Option<Arc<Instrument>> {
+        self.inner.read().unwrap().by_id.get(inst_id).cloned()
+    }
+
+    pub fn get_by_code(&self, inst_id_code: u64) -> Option<Arc<Instrument>> {
+        let inner = self.inner.read().unwrap();
+        let inst_id = inner.by_code.get(&inst_id_code)?;
+        inner.by_id.get(inst_id).cloned()
+    }
+
+    /// 同一交易品种下的所有产品，如 BTC-USD 下的交割、永续和期权
+    pub fn by_inst_family(&self, inst_family: &str) -> Vec<Arc<Instrument>> {
+        let inner = self.inner.read().unwrap();
+        inner.collect(inner.by_family.get(inst_family))
+    }
+
+    pub fn by_uly(&self, uly: &str) -> Vec<Arc<Instrument>> {
+        let inner = self.inner.read().unwrap();
+        inner.collect(inner.by_uly.get(uly))
+    }
+
+    pub fn by_inst_type(&self, inst_type: &str) -> Vec<Arc<Instrument>> {
+        self.inner
+            .read()
+            .unwrap()
+            .by_id
+            .values()
+            .filter(|i| i.inst_type == inst_type)
+            .cloned()
+            .collect()
+    }
+
+    /// 按条件查询产品
+    pub fn query(&self, query: &InstrumentQuery) -> Vec<Arc<Instrument>> {
+        let result = {
+            let inner = self.inner.read().unwrap();
+            match (&query.inst_family, &query.uly) {
+                // 有品种 / 标的条件时走索引
+                (Some(family), _) => inner.collect(inner.by_family.get(family)),
+                (None, Some(uly)) => inner.collect(inner.by_uly.get(uly)),
+                (None, None) => inner.by_id.values().cloned().collect(),
+            }
+        };
+        let result = result.into_iter().filter(|i| query.matches(i)).collect();
+        query.sort_and_limit(result)
+    }
+
+    /// 按条件查询 instId，可直接用于 subscribe_many
+    pub fn query_ids(&self, query: &InstrumentQuery) -> Vec<String> {
+        self.query(query).iter().map(|i| i.inst_id.clone()).collect()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Instrument>> {
+        self.inner.read().unwrap().by_id.values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 全局产品注册表，启动时先读缓存文件，没有缓存或读取失败时读仓库中的产品列表，都失败时为空
+pub static INSTRUMENT_REGISTRY: Lazy<InstrumentRegistry> = Lazy::new(|| {
+    for path in [INSTRUMENTS_CACHE_PATH, INSTRUMENTS_PATH] {
+        if !Path::new(path).exists() {
+            continue;
+        }
+        match InstrumentRegistry::load(path) {
+            Ok(registry) => {
+                info!("从 {} 加载 {} 个产品", path, registry.len());
+                return registry;
+            }
+            Err(e) => warn!("加载产品列表 {} 失败 {}", path, e),
+        }
+    }
+    InstrumentRegistry::new()
+});
+
+/// GET /api/v5/public/instruments，期权必须指定 inst_family
+pub async fn fetch_instruments(inst_type: &str, inst_family: Option<&str>) -> Result<Vec<Instrument>, Box<dyn std::error::Error>> {
+    let mut params = vec![("instType", inst_type)];
+    if let Some(inst_family) = inst_family {
+        params.push(("instFamily", inst_family));
+    }
+    let response = HttpClient::get("/api/v5/public/instruments", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<Instrument>>(&text)?.into_result()
+}
+
+#[cfg(test)]
+mod instrument_test {
+    use super::*;
+
+    const SAMPLE: &str = r#"[
+        {"instType":"SPOT","instId":"BTC-USDT","instIdCode":1,"uly":"","instFamily":"","baseCcy":"BTC","quoteCcy":"USDT","settleCcy":"","tickSz":"0.1","lotSz":"0.00000001","minSz":"0.00001","state":"live"},
+        {"instType":"FUTURES","instId":"BTC-USD-251226","instIdCode":2,"uly":"BTC-USD","instFamily":"BTC-USD","settleCcy":"BTC","ctVal":"100","ctType":"inverse","expTime":"1766736000000","alias":"quarter","tickSz":"0.1","state":"live"},
+        {"instType":"OPTION","instId":"BTC-USD-251226-100000-C","instIdCode":3,"uly":"BTC-USD","instFamily":"BTC-USD","settleCcy":"BTC","optType":"C","stk":"100000","expTime":"1766736000000","tickSz":"0.0005","state":"live","baseCcy":null},
+        {"instType":"SWAP","instId":"BTC-USD-SWAP","instIdCode":4,"uly":"BTC-USD","instFamily":"BTC-USD","settleCcy":"BTC","ctVal":"100","ctType":"inverse","tickSz":"0.1","state":"live"}
+    ]"#;
+
+    #[test]
+    fn test_kinds_and_lookup() {
+        let registry = InstrumentRegistry::from_instruments(from_str::<Vec<Instrument>>(SAMPLE).unwrap());
+        assert_eq!(registry.len(), 4);
+        assert_eq!(
+            registry.get("BTC-USDT").unwrap().kind(),
+            InstrumentKind::Spot { base_ccy: "BTC", quote_ccy: "USDT" }
+        );
+        let option = registry.get_by_code(3).unwrap();
+        assert!(matches!(option.kind(), InstrumentKind::Option { strike, .. } if strike == 100000.0));
+        assert_eq!(option.expiry_ms(), Some(1766736000000));
+        assert_eq!(registry.by_inst_family("BTC-USD").len(), 3);
+        assert_eq!(registry.by_uly("BTC-USD").len(), 3);
+        assert!(registry.get("BTC-USD-SWAP").unwrap().is_inverse());
+        registry.remove("BTC-USD-SWAP");
+        assert_eq!(registry.by_uly("BTC-USD").len(), 2);
+        assert!(registry.get_by_code(4).is_none());
+    }
+
+    #[test]
+    fn test_snapshot_events_and_save() {
+        let registry = InstrumentRegistry::from_instruments(from_str::<Vec<Instrument>>(SAMPLE).unwrap());
+        let mut rx = registry.subscribe();
+        let mut swap = registry.get("BTC-USD-SWAP").unwrap().as_ref().clone();
+        swap.tick_sz = "0.5".to_string();
+        swap.state = "suspend".to_string();
+        let mut listed = swap.clone();
+        listed.inst_id = "ETH-USD-SWAP".to_string();
+        listed.inst_id_code = 5;
+        let events = registry.apply_snapshot(InstType::SWAP, None, vec![swap, listed]);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(rx.try_recv().unwrap(), InstrumentEvent::StateChanged { .. }));
+        assert_eq!(registry.get("BTC-USD-SWAP").unwrap().tick_sz, "0.5");
+        // 其他类型不受影响
+        assert!(registry.get("BTC-USDT").is_some());
+
+        let events = registry.apply_snapshot(InstType::SWAP, None, vec![registry.get("ETH-USD-SWAP").unwrap().as_ref().clone()]);
+        assert!(matches!(&events[0], InstrumentEvent::Delisted(i) if i.inst_id == "BTC-USD-SWAP"));
+
+        let path = std::env::temp_dir().join(format!("okx_instruments_test_{}.json", std::process::id()));
+        registry.save(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"inst_id\""));
+        assert_eq!(InstrumentRegistry::load(&path).unwrap().len(), registry.len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_apply_updates() {
+        let registry = InstrumentRegistry::from_instruments(from_str::<Vec<Instrument>>(SAMPLE).unwrap());
+        let mut spot = registry.get("BTC-USDT").unwrap().as_ref().clone();
+        spot.state = "suspend".to_string();
+        let events = registry.apply_updates(vec![spot]);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], InstrumentEvent::StateChanged { new, .. } if new == "suspend"));
+        assert_eq!(registry.len(), 4);
+    }
+
+    #[test]
+    fn test_inverse_math() {
+        let registry = InstrumentRegistry::from_instruments(from_str::<Vec<Instrument>>(SAMPLE).unwrap());
+        let swap = registry.get("BTC-USD-SWAP").unwrap();
+        // 10 张 * 100 USD = 1000 USD，50000 时为 0.02 BTC
+        assert_eq!(swap.notional_quote(-10.0, 50000.0), 1000.0);
+        assert!((swap.notional_coin(10.0, 50000.0) - 0.02).abs() < 1e-12);
+        // 多头 50000 -> 40000 亏损 1000 * (1/50000 - 1/40000) = -0.005 BTC
+        assert!((swap.pnl(10.0, 50000.0, 40000.0) + 0.005).abs() < 1e-12);
+        assert!((swap.pnl(-10.0, 50000.0, 40000.0) - 0.005).abs() < 1e-12);
+        assert!((swap.initial_margin(10.0, 50000.0, 10.0) - 0.002).abs() < 1e-12);
+
+        let linear = Instrument { inst_id: "ETH-USDC-SWAP".into(), ct_val: "0.1".into(), ct_type: "linear".into(), lot_sz: "0.01".into(), ..Default::default() };
+        assert!((linear.pnl(-2.0, 3000.0, 2900.0) - 20.0).abs() < 1e-9);
+        assert!((linear.notional_quote(2.0, 3000.0) - 600.0).abs() < 1e-9);
+        assert_eq!(linear.quantity_to_sz(0.3).unwrap(), "3.00");
+        assert_eq!(swap.quantity_to_sz(1050.0), None);
+    }
+
+    #[test]
+    fn test_query() {
+        let registry = InstrumentRegistry::from_instruments(from_str::<Vec<Instrument>>(SAMPLE).unwrap());
+        let family = registry.query_ids(&InstrumentQuery::new().inst_family("BTC-USD"));
+        assert_eq!(family, vec!["BTC-USD-251226", "BTC-USD-251226-100000-C", "BTC-USD-SWAP"]);
+        let inverse = registry.query_ids(&InstrumentQuery::new().ct_type("inverse").inst_type(InstType::FUTURES));
+        assert_eq!(inverse, vec!["BTC-USD-251226"]);
+
+        // 缓存文件中的 USDT 本位永续
+        let registry = InstrumentRegistry::load(INSTRUMENTS_PATH).unwrap();
+        let query = InstrumentQuery::new()
+            .inst_type(InstType::SWAP)
+            .live()
+            .ct_type("linear")
+            .settle_ccy("USDT")
+            .min_lever(50.0)
+            .sort(InstrumentSort::ListTime);
+        let swaps = registry.query(&query);
+        assert!(!swaps.is_empty());
+        assert!(swaps.iter().all(|i| str_to_f64(&i.lever) >= 50.0 && i.settle_ccy == "USDT"));
+        assert!(swaps.windows(2).all(|w| w[0].list_time.parse::<u64>().unwrap() <= w[1].list_time.parse::<u64>().unwrap()));
+        assert_eq!(registry.query(&query.limit(2)).len(), 2);
+    }
+
+    #[test]
+    fn test_load_cache_file() {
+        // 仓库中的产品列表为 snake_case
+        let registry = InstrumentRegistry::load(INSTRUMENTS_PATH).unwrap();
+        let btc = registry.get("BTC-USDT-SWAP").unwrap();
+        assert_eq!(btc.inst_type, InstType::SWAP);
+        assert!(!btc.tick_sz.is_empty());
+    }
+}
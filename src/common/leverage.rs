@@ -0,0 +1,272 @@
+use std::fmt;
+use dashmap::DashMap;
+use log::{info, warn};
+use sonic_rs::{from_str, json, Deserialize, Serialize};
+use crate::common::account::{get_pos_mode, PosMode};
+use crate::common::rest_api::OkxResponse;
+use crate::common::instrument::INSTRUMENT_REGISTRY;
+use crate::common::utils::{str_to_f64, HttpClientSimulation};
+use crate::common::ws_api::TdMode;
+
+/// 逐仓保证金调整方向
+pub struct MarginAdjustType;
+impl MarginAdjustType {
+    /// 增加保证金
+    pub const ADD: &'static str = "add";
+    /// 减少保证金
+    pub const REDUCE: &'static str = "reduce";
+}
+
+/// set-leverage / leverage-info 的返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LeverageInfo {
+    pub inst_id: String,
+    pub mgn_mode: String,
+    pub pos_side: String,
+    pub lever: String,
+    pub ccy: String,
+}
+
+/// GET /api/v5/account/max-size 的返回，单位为张
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MaxSize {
+    pub inst_id: String,
+    pub ccy: String,
+    pub max_buy: String,
+    pub max_sell: String,
+}
+
+/// GET /api/v5/account/max-avail-size 的返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MaxAvailSize {
+    pub inst_id: String,
+    pub avail_buy: String,
+    pub avail_sell: String,
+}
+
+/// POST /api/v5/account/position/margin-balance 的返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AdjustMarginData {
+    pub inst_id: String,
+    pub pos_side: String,
+    pub amt: String,
+    #[serde(rename = "type")]
+    pub adjust_type: String,
+    pub leverage: String,
+    pub ccy: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeverageError {
+    UnknownInstrument(String),
+    InvalidMarginMode(String),
+    OutOfRange { inst_id: String, lever: u32, max: u32 },
+}
+
+impl fmt::Display for LeverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeverageError::UnknownInstrument(inst_id) => write!(f, "未知产品 {}", inst_id),
+            LeverageError::InvalidMarginMode(mode) => write!(f, "保证金模式 {} 只能是 cross / isolated", mode),
+            LeverageError::OutOfRange { inst_id, lever, max } => write!(f, "{} 杠杆 {} 超出范围 1..={}", inst_id, lever, max),
+        }
+    }
+}
+
+impl std::error::Error for LeverageError {}
+
+fn check_mgn_mode(mgn_mode: &str) -> Result<(), LeverageError> {
+    match mgn_mode {
+        TdMode::CROSS | TdMode::ISOLATED => Ok(()),
+        _ => Err(LeverageError::InvalidMarginMode(mgn_mode.to_string())),
+    }
+}
+
+/// POST /api/v5/account/set-leverage
+/// 双向持仓的逐仓需要分别设置 long / short，此时传 pos_side，其余情况传 None
+pub async fn set_leverage(inst_id: &str, lever: u32, mgn_mode: &str, pos_side: Option<&str>) -> Result<Vec<LeverageInfo>, Box<dyn std::error::Error>> {
+    check_mgn_mode(mgn_mode)?;
+    let mut body = json!({
+        "instId": inst_id,
+        "lever": lever.to_string(),
+        "mgnMode": mgn_mode,
+    });
+    if let Some(pos_side) = pos_side {
+        body["posSide"] = json!(pos_side);
+    }
+    let response = HttpClientSimulation::post("/api/v5/account/set-leverage", body).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<LeverageInfo>>(&text)?.into_result()
+}
+
+/// GET /api/v5/account/leverage-info
+pub async fn fetch_leverage_info(inst_id: &str, mgn_mode: &str) -> Result<Vec<LeverageInfo>, Box<dyn std::error::Error>> {
+    check_mgn_mode(mgn_mode)?;
+    let response = HttpClientSimulation::get("/api/v5/account/leverage-info", Some(&[("instId", inst_id), ("mgnMode", mgn_mode)])).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<LeverageInfo>>(&text)?.into_result()
+}
+
+/// GET /api/v5/account/max-size，当前杠杆下最大可开数量，px 为空时按最新价计算
+pub async fn fetch_max_size(inst_id: &str, td_mode: &str, px: Option<&str>) -> Result<MaxSize, Box<dyn std::error::Error>> {
+    let mut params = vec![("instId", inst_id), ("tdMode", td_mode)];
+    if let Some(px) = px {
+        params.push(("px", px));
+    }
+    let response = HttpClientSimulation::get("/api/v5/account/max-size", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<MaxSize>>(&text)?
+        .into_result()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "max-size empty".into())
+}
+
+/// GET /api/v5/account/max-avail-size，最大可用数量（平仓时传 reduce_only）
+pub async fn fetch_max_avail_size(inst_id: &str, td_mode: &str, reduce_only: bool) -> Result<MaxAvailSize, Box<dyn std::error::Error>> {
+    let mut params = vec![("instId", inst_id), ("tdMode", td_mode)];
+    if reduce_only {
+        params.push(("reduceOnly", "true"));
+    }
+    let response = HttpClientSimulation::get("/api/v5/account/max-avail-size", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<MaxAvailSize>>(&text)?
+        .into_result()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "max-avail-size empty".into())
+}
+
+/// POST /api/v5/account/position/margin-balance，逐仓仓位增加或减少保证金
+pub async fn adjust_margin(inst_id: &str, pos_side: &str, adjust_type: &str, amt: &str) -> Result<AdjustMarginData, Box<dyn std::error::Error>> {
+    let body = json!({
+        "instId": inst_id,
+        "posSide": pos_side,
+        "type": adjust_type,
+        "amt": amt,
+    });
+    let response = HttpClientSimulation::post("/api/v5/account/position/margin-balance", body).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<AdjustMarginData>>(&text)?
+        .into_result()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "margin-balance empty".into())
+}
+
+/// 单个产品期望的保证金模式和杠杆
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeverageTarget {
+    pub inst_id: String,
+    pub mgn_mode: String,
+    pub lever: u32,
+}
+
+/// 策略声明的杠杆计划，交易开始前由 apply 统一设置
+#[derive(Default)]
+pub struct LeveragePlan {
+    targets: DashMap<String, LeverageTarget>,
+}
+
+impl LeveragePlan {
+    pub fn new() -> LeveragePlan {
+        LeveragePlan::default()
+    }
+
+    /// 声明某个产品的杠杆，不能超过产品的最大杠杆（Instrument.lever）
+    /// 没有杠杆的产品（现货、期权，lever 为空）不需要设置，直接忽略
+    pub fn declare(&self, inst_id: &str, mgn_mode: &str, lever: u32) -> Result<(), LeverageError> {
+        check_mgn_mode(mgn_mode)?;
+        let instrument = INSTRUMENT_REGISTRY.get(inst_id).ok_or_else(|| LeverageError::UnknownInstrument(inst_id.to_string()))?;
+        let max = str_to_f64(&instrument.lever) as u32;
+        if max == 0 {
+            info!("{} {} 没有杠杆，不设置", inst_id, instrument.inst_type);
+            return Ok(());
+        }
+        if lever == 0 || lever > max {
+            return Err(LeverageError::OutOfRange { inst_id: inst_id.to_string(), lever, max });
+        }
+        self.targets.insert(inst_id.to_string(), LeverageTarget {
+            inst_id: inst_id.to_string(),
+            mgn_mode: mgn_mode.to_string(),
+            lever,
+        });
+        Ok(())
+    }
+
+    pub fn get(&self, inst_id: &str) -> Option<LeverageTarget> {
+        self.targets.get(inst_id).map(|t| t.clone())
+    }
+
+    pub fn targets(&self) -> Vec<LeverageTarget> {
+        self.targets.iter().map(|t| t.value().clone()).collect()
+    }
+
+    /// 查询当前杠杆，与计划不一致的调用 set-leverage，返回每个产品的结果
+    pub async fn apply(&self) -> Vec<(String, Result<(), String>)> {
+        let mut results = Vec::new();
+        for target in self.targets() {
+            let result = apply_target(&target).await.map_err(|e| e.to_string());
+            match &result {
+                Ok(()) => info!("{} {} 杠杆 {}x", target.inst_id, target.mgn_mode, target.lever),
+                Err(e) => warn!("{} 设置杠杆失败: {}", target.inst_id, e),
+            }
+            results.push((target.inst_id, result));
+        }
+        results
+    }
+}
+
+async fn apply_target(target: &LeverageTarget) -> Result<(), Box<dyn std::error::Error>> {
+    let current = fetch_leverage_info(&target.inst_id, &target.mgn_mode).await?;
+    let lever = target.lever.to_string();
+    if !current.is_empty() && current.iter().all(|c| str_to_f64(&c.lever) == target.lever as f64) {
+        return Ok(());
+    }
+    // 双向持仓的逐仓杠杆按 long / short 分别设置
+    if target.mgn_mode == TdMode::ISOLATED && get_pos_mode() == PosMode::LongShort {
+        for pos_side in ["long", "short"] {
+            set_leverage(&target.inst_id, target.lever, &target.mgn_mode, Some(pos_side)).await?;
+        }
+    } else {
+        set_leverage(&target.inst_id, target.lever, &target.mgn_mode, None).await?;
+    }
+    info!("{} 杠杆已从 {:?} 调整为 {}", target.inst_id, current.iter().map(|c| c.lever.as_str()).collect::<Vec<_>>(), lever);
+    Ok(())
+}
+
+#[cfg(test)]
+mod leverage_test {
+    use crate::common::instrument::Instrument;
+    use crate::common::ws_api::InstType;
+    use super::*;
+
+    #[test]
+    fn test_declare_bounds() {
+        let plan = LeveragePlan::new();
+        assert_eq!(plan.declare("BTC-USDT-SWAP", TdMode::CROSS, 10), Ok(()));
+        assert_eq!(plan.get("BTC-USDT-SWAP").unwrap().lever, 10);
+        assert!(matches!(plan.declare("BTC-USDT-SWAP", TdMode::CROSS, 100_000), Err(LeverageError::OutOfRange { .. })));
+        assert!(matches!(plan.declare("BTC-USDT-SWAP", "cash", 10), Err(LeverageError::InvalidMarginMode(_))));
+        assert_eq!(plan.declare("BTC-USD-SWAP", TdMode::ISOLATED, 10), Ok(()));
+        assert!(matches!(plan.declare("NOPE-USDT-SWAP", TdMode::CROSS, 10), Err(LeverageError::UnknownInstrument(_))));
+    }
+
+    #[test]
+    fn test_declare_skips_spot() {
+        INSTRUMENT_REGISTRY.insert(Instrument {
+            inst_type: InstType::SPOT.to_string(),
+            inst_id: "LEVERTEST-USDT".to_string(),
+            state: "live".to_string(),
+            ..Default::default()
+        });
+        let plan = LeveragePlan::new();
+        assert_eq!(plan.declare("LEVERTEST-USDT", TdMode::CROSS, 10), Ok(()));
+        assert!(plan.get("LEVERTEST-USDT").is_none());
+        INSTRUMENT_REGISTRY.remove("LEVERTEST-USDT");
+    }
+}
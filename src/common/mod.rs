@@ -1,4 +1,23 @@
 pub mod config;
 pub mod utils;
 pub mod rest_api;
-pub mod ws_api;
\ No newline at end of file
+pub mod ws_api;
+pub mod order_store;
+pub mod account;
+pub mod fills;
+pub mod algo;
+pub mod cl_ord_id;
+pub mod rate_limit;
+pub mod risk;
+pub mod leverage;
+pub mod flatten;
+pub mod reconcile;
+pub mod snapshot;
+pub mod clock;
+pub mod retry;
+pub mod instrument;
+pub mod status;
+pub mod credentials;
+pub mod vault;
+pub mod endpoint;
+pub mod proxy;
@@ -0,0 +1,498 @@
+use dashmap::DashMap;
+use log::{info, warn};
+use sonic_rs::{from_str, json, Deserialize, Serialize};
+use crate::common::rest_api::OkxResponse;
+use crate::common::utils::HttpClientSimulation;
+use crate::common::ws_api::{OpAckData, OpResponse};
+
+/// orders 频道推送的单个订单数据
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OrderData {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub ord_id: String,
+    pub cl_ord_id: String,
+    pub tag: String,
+    pub px: String,
+    pub sz: String,
+    pub ord_type: String,
+    pub side: String,
+    pub pos_side: String,
+    pub td_mode: String,
+    pub fill_px: String,               // 最新成交价
+    pub trade_id: String,              // 最新成交ID
+    pub fill_sz: String,               // 最新成交数量
+    pub fill_pnl: String,              // 最新成交收益
+    pub fill_time: String,             // 最新成交时间
+    pub fill_fee: String,              // 最新成交手续费（负数为扣除）
+    pub fill_fee_ccy: String,          // 最新成交手续费币种
+    pub exec_type: String,             // T: taker M: maker
+    pub acc_fill_sz: String,           // 累计成交数量
+    pub avg_px: String,                // 成交均价
+    pub state: String,                 // live / partially_filled / filled / canceled / mmp_canceled
+    pub lever: String,
+    pub fee: String,                   // 累计手续费
+    pub fee_ccy: String,
+    pub pnl: String,
+    pub reduce_only: String,
+    pub cancel_source: String,
+    pub u_time: String,
+    pub c_time: String,
+    pub code: String,
+    pub msg: String,
+}
+
+/// 订单生命周期状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderState {
+    /// 已通过 WS 发出，尚未收到回执
+    PendingSubmit,
+    Live,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    MmpCanceled,
+    /// 下单回执 sCode 非 0
+    Rejected,
+}
+
+impl OrderState {
+    pub fn from_okx(state: &str) -> Option<OrderState> {
+        match state {
+            "live" => Some(OrderState::Live),
+            "partially_filled" => Some(OrderState::PartiallyFilled),
+            "filled" => Some(OrderState::Filled),
+            "canceled" => Some(OrderState::Canceled),
+            "mmp_canceled" => Some(OrderState::MmpCanceled),
+            _ => None,
+        }
+    }
+
+    /// 终态：不会再有更新
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderState::Filled | OrderState::Canceled | OrderState::MmpCanceled | OrderState::Rejected
+        )
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.is_terminal()
+    }
+}
+
+/// 本地维护的订单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalOrder {
+    pub ord_id: String,
+    pub cl_ord_id: String,
+    pub inst_id: String,
+    pub side: String,
+    pub pos_side: String,
+    pub ord_type: String,
+    pub px: String,
+    pub sz: String,
+    pub state: OrderState,
+    pub acc_fill_sz: String,
+    pub avg_px: String,
+    pub fee: String,
+    pub fee_ccy: String,
+    /// 最后更新时间（毫秒），取 OKX 推送的 uTime，未收到推送前为 0
+    pub u_time: u64,
+    /// 拒单原因
+    pub reject_msg: String,
+    /// clOrdId 不带本程序前缀的订单（网页或其他程序下的单）
+    #[serde(default)]
+    pub foreign: bool,
+}
+
+impl LocalOrder {
+    /// 刚发出、尚未收到回执的订单
+    pub fn pending(cl_ord_id: &str, inst_id: &str, side: &str, ord_type: &str, px: Option<&str>, sz: &str) -> LocalOrder {
+        LocalOrder {
+            ord_id: String::new(),
+            cl_ord_id: cl_ord_id.to_string(),
+            inst_id: inst_id.to_string(),
+            side: side.to_string(),
+            pos_side: String::new(),
+            ord_type: ord_type.to_string(),
+            px: px.unwrap_or_default().to_string(),
+            sz: sz.to_string(),
+            state: OrderState::PendingSubmit,
+            acc_fill_sz: "0".to_string(),
+            avg_px: String::new(),
+            fee: "0".to_string(),
+            fee_ccy: String::new(),
+            u_time: 0,
+            reject_msg: String::new(),
+            foreign: false,
+        }
+    }
+
+    fn from_order_data(data: &OrderData) -> LocalOrder {
+        LocalOrder {
+            ord_id: data.ord_id.clone(),
+            cl_ord_id: data.cl_ord_id.clone(),
+            inst_id: data.inst_id.clone(),
+            side: data.side.clone(),
+            pos_side: data.pos_side.clone(),
+            ord_type: data.ord_type.clone(),
+            px: data.px.clone(),
+            sz: data.sz.clone(),
+            state: OrderState::Live,
+            acc_fill_sz: "0".to_string(),
+            avg_px: String::new(),
+            fee: "0".to_string(),
+            fee_ccy: String::new(),
+            u_time: 0,
+            reject_msg: String::new(),
+            foreign: false,
+        }
+    }
+}
+
+/// 单个账户的订单存储，按 clOrdId（没有时用 ordId）作为主键，另外维护 ordId 索引
+pub struct OrderStore {
+    orders: DashMap<String, LocalOrder>,
+    /// ordId -> 主键
+    ord_id_index: DashMap<String, String>,
+    /// WS 请求 id -> 主键列表，用于没有 clOrdId 的下单回执
+    pending_req: DashMap<String, Vec<String>>,
+}
+
+impl Default for OrderStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderStore {
+    pub fn new() -> OrderStore {
+        OrderStore {
+            orders: DashMap::new(),
+            ord_id_index: DashMap::new(),
+            pending_req: DashMap::new(),
+        }
+    }
+
+    /// 通过 WS 发出下单请求时登记，状态为 PendingSubmit
+    pub fn track_submit(&self, req_id: &str, order: LocalOrder) {
+        let key = if order.cl_ord_id.is_empty() {
+            format!("req-{}-{}", req_id, self.pending_req.get(req_id).map(|v| v.len()).unwrap_or(0))
+        } else {
+            order.cl_ord_id.clone()
+        };
+        self.orders.insert(key.clone(), order);
+        self.pending_req.entry(req_id.to_string()).or_default().push(key);
+    }
+
+    /// 处理 WS 交易操作的回执（order / batch-orders）
+    pub fn on_op_response(&self, resp: &OpResponse) {
+        if resp.op != "order" && resp.op != "batch-orders" {
+            if resp.code.as_deref().unwrap_or("0") != "0" {
+                warn!("op {} id {} 失败 code {:?} msg {}", resp.op, resp.id, resp.code, resp.msg);
+            }
+            return;
+        }
+        let pending = self.pending_req.remove(&resp.id).map(|(_, v)| v).unwrap_or_default();
+        for (i, ack) in resp.data.iter().enumerate() {
+            let key = if !ack.cl_ord_id.is_empty() {
+                Some(ack.cl_ord_id.clone())
+            } else {
+                pending.get(i).cloned()
+            };
+            match key {
+                Some(key) => self.apply_ack(&key, ack),
+                None => warn!("未登记的下单回执 id {} {:?}", resp.id, ack),
+            }
+        }
+    }
+
+    fn apply_ack(&self, key: &str, ack: &OpAckData) {
+        if !self.orders.contains_key(key) {
+            warn!("未登记的下单回执 {:?}", ack);
+            return;
+        }
+        // 没有 clOrdId 的订单推送先于回执到达时，推送按 ordId 建了条目，合并到下单时登记的条目
+        let pushed = if !ack.ord_id.is_empty() && ack.ord_id != key {
+            self.orders.remove(&ack.ord_id).map(|(_, order)| order)
+        } else {
+            None
+        };
+        let Some(mut order) = self.orders.get_mut(key) else {
+            warn!("未登记的下单回执 {:?}", ack);
+            return;
+        };
+        if let Some(pushed) = pushed {
+            *order = pushed;
+        }
+        if !ack.ord_id.is_empty() {
+            order.ord_id = ack.ord_id.clone();
+            self.ord_id_index.insert(ack.ord_id.clone(), key.to_string());
+        }
+        if ack.s_code == "0" {
+            // 订单推送可能先于回执到达，只推进仍处于 PendingSubmit 的订单
+            if order.state == OrderState::PendingSubmit {
+                order.state = OrderState::Live;
+            }
+        } else {
+            order.state = OrderState::Rejected;
+            order.reject_msg = format!("{} {}", ack.s_code, ack.s_msg);
+            info!("订单被拒 key {} {}", key, order.reject_msg);
+        }
+    }
+
+    /// 发送前被本地拒绝（风控、限速）的订单，只处理仍在 PendingSubmit 的订单
+    pub fn reject_local(&self, cl_ord_id: &str, reason: &str) {
+        if let Some(mut order) = self.orders.get_mut(cl_ord_id)
+            && order.state == OrderState::PendingSubmit
+        {
+            order.state = OrderState::Rejected;
+            order.reject_msg = reason.to_string();
+            info!("订单被本地拒绝 {} {}", cl_ord_id, reason);
+        }
+    }
+
+    /// 处理 orders 频道推送
+    pub fn on_order_update(&self, data: &OrderData) {
+        let key = self.key_of(&data.cl_ord_id, &data.ord_id);
+        let u_time = data.u_time.parse::<u64>().unwrap_or(0);
+        let mut order = self
+            .orders
+            .entry(key.clone())
+            .or_insert_with(|| LocalOrder::from_order_data(data));
+        // 乱序的旧推送直接丢弃
+        if u_time != 0 && u_time < order.u_time {
+            return;
+        }
+        if let Some(state) = OrderState::from_okx(&data.state) {
+            // 终态不会回退
+            if order.state.is_terminal() && !state.is_terminal() {
+                return;
+            }
+            order.state = state;
+        }
+        if !data.ord_id.is_empty() {
+            order.ord_id = data.ord_id.clone();
+            self.ord_id_index.insert(data.ord_id.clone(), key.clone());
+        }
+        if !data.px.is_empty() {
+            order.px = data.px.clone();
+        }
+        if !data.sz.is_empty() {
+            order.sz = data.sz.clone();
+        }
+        if !data.pos_side.is_empty() {
+            order.pos_side = data.pos_side.clone();
+        }
+        if !data.acc_fill_sz.is_empty() {
+            order.acc_fill_sz = data.acc_fill_sz.clone();
+        }
+        if !data.avg_px.is_empty() {
+            order.avg_px = data.avg_px.clone();
+        }
+        if !data.fee.is_empty() {
+            order.fee = data.fee.clone();
+            order.fee_ccy = data.fee_ccy.clone();
+        }
+        order.u_time = u_time;
+    }
+
+    fn key_of(&self, cl_ord_id: &str, ord_id: &str) -> String {
+        if !cl_ord_id.is_empty() {
+            return cl_ord_id.to_string();
+        }
+        if let Some(key) = self.ord_id_index.get(ord_id) {
+            return key.clone();
+        }
+        ord_id.to_string()
+    }
+
+    /// 从快照恢复订单，已存在的（例如已收到推送的）不覆盖
+    pub fn restore(&self, orders: Vec<LocalOrder>) {
+        for order in orders {
+            let key = if order.cl_ord_id.is_empty() { order.ord_id.clone() } else { order.cl_ord_id.clone() };
+            if key.is_empty() || self.orders.contains_key(&key) {
+                continue;
+            }
+            if !order.ord_id.is_empty() {
+                self.ord_id_index.insert(order.ord_id.clone(), key.clone());
+            }
+            self.orders.insert(key, order);
+        }
+    }
+
+    /// 标记为外部订单
+    pub fn mark_foreign(&self, cl_ord_id: &str, ord_id: &str) {
+        let key = self.key_of(cl_ord_id, ord_id);
+        if let Some(mut order) = self.orders.get_mut(&key) {
+            order.foreign = true;
+        }
+    }
+
+    /// 所有未终结的外部订单
+    pub fn foreign_orders(&self) -> Vec<LocalOrder> {
+        self.orders
+            .iter()
+            .filter(|o| o.foreign && o.state.is_open())
+            .map(|o| o.clone())
+            .collect()
+    }
+
+    pub fn get_by_cl_ord_id(&self, cl_ord_id: &str) -> Option<LocalOrder> {
+        self.orders.get(cl_ord_id).map(|o| o.clone())
+    }
+
+    pub fn get_by_ord_id(&self, ord_id: &str) -> Option<LocalOrder> {
+        let key = self.ord_id_index.get(ord_id)?.clone();
+        self.orders.get(&key).map(|o| o.clone())
+    }
+
+    /// 所有未终结的订单，可按 instId 过滤
+    pub fn open_orders(&self, inst_id: Option<&str>) -> Vec<LocalOrder> {
+        self.orders
+            .iter()
+            .filter(|o| o.state.is_open())
+            .filter(|o| inst_id.is_none_or(|id| o.inst_id == id))
+            .map(|o| o.clone())
+            .collect()
+    }
+
+    /// 清理已终结的订单，返回清理数量
+    pub fn purge_terminal(&self) -> usize {
+        let keys = self
+            .orders
+            .iter()
+            .filter(|o| o.state.is_terminal())
+            .map(|o| o.key().clone())
+            .collect::<Vec<String>>();
+        for key in keys.iter() {
+            if let Some((_, order)) = self.orders.remove(key) {
+                self.ord_id_index.remove(&order.ord_id);
+            }
+        }
+        keys.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+}
+
+/// GET /api/v5/trade/orders-pending，返回字段与 orders 频道一致，单次最多 100 条
+pub async fn fetch_orders_pending(inst_type: Option<&str>, inst_id: Option<&str>) -> Result<Vec<OrderData>, Box<dyn std::error::Error>> {
+    fetch_orders_pending_after(inst_type, inst_id, None).await
+}
+
+/// orders-pending 翻页，after 为上一页最后一条的 ordId
+pub async fn fetch_orders_pending_after(inst_type: Option<&str>, inst_id: Option<&str>, after: Option<&str>) -> Result<Vec<OrderData>, Box<dyn std::error::Error>> {
+    let mut params = Vec::new();
+    if let Some(after) = after {
+        params.push(("after", after));
+    }
+    if let Some(inst_type) = inst_type {
+        params.push(("instType", inst_type));
+    }
+    if let Some(inst_id) = inst_id {
+        params.push(("instId", inst_id));
+    }
+    let response = HttpClientSimulation::get("/api/v5/trade/orders-pending", Some(&params)).await?;
+    let text = response.text().await?;
+    from_str::<OkxResponse<OrderData>>(&text)?.into_result()
+}
+
+/// GET /api/v5/trade/order，查询单个订单的最新状态
+pub async fn fetch_order(inst_id: &str, ord_id: &str) -> Result<Option<OrderData>, Box<dyn std::error::Error>> {
+    let response = HttpClientSimulation::get("/api/v5/trade/order", Some(&[("instId", inst_id), ("ordId", ord_id)])).await?;
+    let text = response.text().await?;
+    Ok(from_str::<OkxResponse<OrderData>>(&text)?.into_result()?.into_iter().next())
+}
+
+/// POST /api/v5/trade/cancel-batch-orders，参数为 (instId, ordId) 列表，单次最多 20 个
+/// 整体失败（code 1）时 data 里仍有逐单结果，因此不用 into_result
+pub async fn cancel_batch_orders(orders: &[(&str, &str)]) -> Result<Vec<OpAckData>, Box<dyn std::error::Error>> {
+    let body = orders
+        .iter()
+        .map(|(inst_id, ord_id)| json!({"instId": inst_id, "ordId": ord_id}))
+        .collect::<Vec<_>>();
+    let response = HttpClientSimulation::post("/api/v5/trade/cancel-batch-orders", json!(body)).await?;
+    let text = response.text().await?;
+    let result = from_str::<OkxResponse<OpAckData>>(&text)?;
+    if result.code != "0" && result.data.is_empty() {
+        return Err(format!("okx error code {} msg {}", result.code, result.msg).into());
+    }
+    Ok(result.data)
+}
+
+#[cfg(test)]
+mod order_store_test {
+    use sonic_rs::from_str;
+    use crate::common::ws_api::ChannelData;
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let store = OrderStore::new();
+        store.track_submit("1", LocalOrder::pending("abc1", "BTC-USDT-SWAP", "buy", "limit", Some("50000"), "1"));
+        assert_eq!(store.get_by_cl_ord_id("abc1").unwrap().state, OrderState::PendingSubmit);
+
+        let ack = from_str::<OpResponse>(r#"{"id":"1","op":"order","data":[{"clOrdId":"abc1","ordId":"100","tag":"","sCode":"0","sMsg":""}],"code":"0","msg":""}"#).unwrap();
+        store.on_op_response(&ack);
+        assert_eq!(store.get_by_ord_id("100").unwrap().state, OrderState::Live);
+
+        let push = from_str::<ChannelData<OrderData>>(r#"{"arg":{"channel":"orders","instType":"SWAP","uid":"1"},"data":[{"instId":"BTC-USDT-SWAP","ordId":"100","clOrdId":"abc1","px":"50000","sz":"1","side":"buy","state":"partially_filled","accFillSz":"0.4","avgPx":"50000","fee":"-0.1","feeCcy":"USDT","uTime":"1700000000002"}]}"#).unwrap();
+        store.on_order_update(&push.data[0]);
+        let order = store.get_by_cl_ord_id("abc1").unwrap();
+        assert_eq!(order.state, OrderState::PartiallyFilled);
+        assert_eq!(order.acc_fill_sz, "0.4");
+
+        // 乱序的旧推送不会覆盖
+        let mut old = push.data[0].clone();
+        old.state = "live".to_string();
+        old.u_time = "1700000000001".to_string();
+        store.on_order_update(&old);
+        assert_eq!(store.get_by_cl_ord_id("abc1").unwrap().state, OrderState::PartiallyFilled);
+        assert_eq!(store.open_orders(Some("BTC-USDT-SWAP")).len(), 1);
+    }
+
+    #[test]
+    fn test_reject_without_cl_ord_id() {
+        let store = OrderStore::new();
+        store.track_submit("7", LocalOrder::pending("", "ETH-USDT-SWAP", "sell", "market", None, "1"));
+        let ack = from_str::<OpResponse>(r#"{"id":"7","op":"order","data":[{"clOrdId":"","ordId":"","tag":"","sCode":"51008","sMsg":"Insufficient balance"}],"code":"1","msg":""}"#).unwrap();
+        store.on_op_response(&ack);
+        assert!(store.open_orders(None).is_empty());
+        assert_eq!(store.purge_terminal(), 1);
+    }
+
+    #[test]
+    fn test_push_before_ack_without_cl_ord_id() {
+        let store = OrderStore::new();
+        store.track_submit("8", LocalOrder::pending("", "ETH-USDT-SWAP", "buy", "limit", Some("2000"), "2"));
+        let push = from_str::<ChannelData<OrderData>>(r#"{"arg":{"channel":"orders","instType":"SWAP","uid":"1"},"data":[{"instId":"ETH-USDT-SWAP","ordId":"200","clOrdId":"","px":"2000","sz":"2","side":"buy","state":"partially_filled","accFillSz":"1","avgPx":"2000","uTime":"1700000000001"}]}"#).unwrap();
+        store.on_order_update(&push.data[0]);
+        assert_eq!(store.len(), 2);
+
+        // 回执到达后按 ordId 建的条目合并进下单时登记的条目，推送的状态不回退
+        let ack = from_str::<OpResponse>(r#"{"id":"8","op":"order","data":[{"clOrdId":"","ordId":"200","tag":"","sCode":"0","sMsg":""}],"code":"0","msg":""}"#).unwrap();
+        store.on_op_response(&ack);
+        assert_eq!(store.len(), 1);
+        let order = store.get_by_ord_id("200").unwrap();
+        assert_eq!(order.state, OrderState::PartiallyFilled);
+        assert_eq!(order.acc_fill_sz, "1");
+
+        // 之后的推送更新同一个条目
+        let mut filled = push.data[0].clone();
+        filled.state = "filled".to_string();
+        filled.acc_fill_sz = "2".to_string();
+        filled.u_time = "1700000000002".to_string();
+        store.on_order_update(&filled);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get_by_ord_id("200").unwrap().state, OrderState::Filled);
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use log::warn;
+use once_cell::sync::Lazy;
+use sonic_rs::{from_str, Deserialize};
+
+/// 限速维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitScope {
+    /// 按账户 + 产品（下单、撤单、改单）
+    Instrument,
+    /// 按账户
+    Account,
+    /// 按 IP（公共接口）
+    Ip,
+}
+
+/// 限速规则：bucket 相同的规则共用一个令牌桶
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitRule {
+    /// REST 路径或 WS op
+    pub key: &'static str,
+    pub bucket: &'static str,
+    pub scope: LimitScope,
+    pub capacity: u32,
+    pub window_ms: u64,
+}
+
+const fn rule(key: &'static str, bucket: &'static str, scope: LimitScope, capacity: u32, window_ms: u64) -> RateLimitRule {
+    RateLimitRule { key, bucket, scope, capacity, window_ms }
+}
+
+/// 账户维度的下单类请求共用的令牌桶
+const ACCOUNT_ORDERS: &str = "account-orders";
+/// 未在表中列出的公共接口使用的默认规则
+const PUBLIC_DEFAULT: RateLimitRule = rule("public", "public", LimitScope::Ip, 20, 2000);
+
+/// OKX 各接口限速，统一在这里维护
+pub const RATE_LIMITS: &[RateLimitRule] = &[
+    // WS 交易
+    rule("order", "order", LimitScope::Instrument, 60, 2000),
+    rule("order", ACCOUNT_ORDERS, LimitScope::Account, 1000, 2000),
+    rule("batch-orders", "batch-orders", LimitScope::Instrument, 300, 2000),
+    rule("batch-orders", ACCOUNT_ORDERS, LimitScope::Account, 1000, 2000),
+    rule("cancel-order", "cancel-order", LimitScope::Instrument, 60, 2000),
+    rule("amend-order", "amend-order", LimitScope::Instrument, 60, 2000),
+    rule("amend-order", ACCOUNT_ORDERS, LimitScope::Account, 1000, 2000),
+    // REST 交易
+    rule("/api/v5/trade/order", "/api/v5/trade/order", LimitScope::Instrument, 60, 2000),
+    rule("/api/v5/trade/order", ACCOUNT_ORDERS, LimitScope::Account, 1000, 2000),
+    rule("/api/v5/trade/batch-orders", "/api/v5/trade/batch-orders", LimitScope::Instrument, 300, 2000),
+    rule("/api/v5/trade/cancel-order", "/api/v5/trade/cancel-order", LimitScope::Instrument, 60, 2000),
+    rule("/api/v5/trade/cancel-batch-orders", "/api/v5/trade/cancel-batch-orders", LimitScope::Instrument, 300, 2000),
+    rule("/api/v5/trade/amend-order", "/api/v5/trade/amend-order", LimitScope::Instrument, 60, 2000),
+    rule("/api/v5/trade/close-position", "/api/v5/trade/close-position", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/order-algo", "/api/v5/trade/order-algo", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/cancel-algos", "/api/v5/trade/cancel-algos", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/amend-algos", "/api/v5/trade/amend-algos", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/orders-pending", "/api/v5/trade/orders-pending", LimitScope::Account, 60, 2000),
+    rule("/api/v5/trade/orders-algo-pending", "/api/v5/trade/orders-algo-pending", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/orders-algo-history", "/api/v5/trade/orders-algo-history", LimitScope::Account, 20, 2000),
+    rule("/api/v5/trade/fills", "/api/v5/trade/fills", LimitScope::Account, 60, 2000),
+    rule("/api/v5/trade/fills-history", "/api/v5/trade/fills-history", LimitScope::Account, 10, 2000),
+    // REST 账户
+    rule("/api/v5/account/balance", "/api/v5/account/balance", LimitScope::Account, 10, 2000),
+    rule("/api/v5/account/positions", "/api/v5/account/positions", LimitScope::Account, 10, 2000),
+    rule("/api/v5/account/config", "/api/v5/account/config", LimitScope::Account, 5, 2000),
+    // REST 公共
+    rule("/api/v5/public/instruments", "/api/v5/public/instruments", LimitScope::Ip, 20, 2000),
+    rule("/api/v5/public/time", "/api/v5/public/time", LimitScope::Ip, 10, 2000),
+    rule("/api/v5/market/ticker", "/api/v5/market/ticker", LimitScope::Ip, 20, 2000),
+];
+
+/// 被限速时的处理方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// 等待到有令牌为止
+    Wait,
+    /// 立即返回错误
+    Reject,
+    /// 等待，但如果等待期间同一 key 有更新的请求进来，则放弃当前请求（例如反复改单只保留最后一次）
+    Coalesce(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThrottleError {
+    Rejected { key: String, retry_after: Duration },
+    Coalesced { key: String },
+}
+
+impl fmt::Display for ThrottleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThrottleError::Rejected { key, retry_after } => write!(f, "{} 被限速，{}ms 后重试", key, retry_after.as_millis()),
+            ThrottleError::Coalesced { key } => write!(f, "{} 已被更新的请求替代", key),
+        }
+    }
+}
+
+impl std::error::Error for ThrottleError {}
+
+/// 单个 key 的限速统计
+#[derive(Debug, Default)]
+pub struct ThrottleStats {
+    pub allowed: AtomicU64,
+    pub throttled: AtomicU64,
+    pub rejected: AtomicU64,
+    pub coalesced: AtomicU64,
+    pub waited_ms: AtomicU64,
+}
+
+/// 统计快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThrottleSnapshot {
+    pub key: String,
+    pub allowed: u64,
+    pub throttled: u64,
+    pub rejected: u64,
+    pub coalesced: u64,
+    pub waited_ms: u64,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    /// 每毫秒补充的令牌数
+    refill_per_ms: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rule: &RateLimitRule, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: rule.capacity as f64,
+            capacity: rule.capacity as f64,
+            refill_per_ms: rule.capacity as f64 / rule.window_ms as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64() * 1000.0;
+        self.tokens = (self.tokens + elapsed * self.refill_per_ms).min(self.capacity);
+        self.last = now;
+    }
+
+    /// 距离有 cost 个令牌还需要等待的时间，超过容量的按容量计
+    fn wait_time(&self, cost: f64) -> Duration {
+        let cost = cost.min(self.capacity);
+        if self.tokens >= cost {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((cost - self.tokens) / self.refill_per_ms / 1000.0)
+    }
+}
+
+/// 令牌桶限速器，WS 交易和 REST 请求都经过这里
+pub struct RateLimiter {
+    rules: Vec<RateLimitRule>,
+    buckets: Mutex<HashMap<(&'static str, String), TokenBucket>>,
+    stats: DashMap<String, ThrottleStats>,
+    coalesce: DashMap<String, u64>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(RATE_LIMITS.to_vec())
+    }
+}
+
+pub static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::default);
+
+impl RateLimiter {
+    pub fn new(rules: Vec<RateLimitRule>) -> RateLimiter {
+        RateLimiter {
+            rules,
+            buckets: Mutex::new(HashMap::new()),
+            stats: DashMap::new(),
+            coalesce: DashMap::new(),
+        }
+    }
+
+    fn rules_for(&self, key: &str) -> Vec<RateLimitRule> {
+        let rules = self.rules.iter().filter(|r| r.key == key).copied().collect::<Vec<_>>();
+        if rules.is_empty() && (key.starts_with("/api/v5/public/") || key.starts_with("/api/v5/market/")) {
+            return vec![PUBLIC_DEFAULT];
+        }
+        rules
+    }
+
+    /// key 是否在限速表中
+    pub fn is_limited(&self, key: &str) -> bool {
+        !self.rules_for(key).is_empty()
+    }
+
+    /// 尝试取令牌，成功返回 ZERO，否则返回需要等待的时间（不消耗令牌）
+    ///
+    /// legs 为 (instId, 订单数)，批量操作按产品分组，每个订单消耗一个令牌，所有桶一起取或都不取
+    fn try_take(&self, rules: &[RateLimitRule], legs: &[(Option<String>, u32)]) -> Duration {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut costs: HashMap<(&'static str, String), (RateLimitRule, f64)> = HashMap::new();
+        for r in rules.iter() {
+            for (inst_id, cost) in legs.iter() {
+                let scope_key = match r.scope {
+                    LimitScope::Instrument => inst_id.clone().unwrap_or_default(),
+                    LimitScope::Account | LimitScope::Ip => String::new(),
+                };
+                costs.entry((r.bucket, scope_key)).or_insert((*r, 0.0)).1 += *cost as f64;
+            }
+        }
+        let mut wait = Duration::ZERO;
+        for (key, (r, cost)) in costs.iter() {
+            let bucket = buckets.entry(key.clone()).or_insert_with(|| TokenBucket::new(r, now));
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time(*cost));
+        }
+        if wait.is_zero() {
+            for (key, (_, cost)) in costs.iter() {
+                if let Some(bucket) = buckets.get_mut(key) {
+                    bucket.tokens -= cost.min(bucket.capacity);
+                }
+            }
+        }
+        wait
+    }
+
+    /// 按 endpoint（REST 路径或 WS op）和 instId 取令牌
+    pub async fn acquire(&self, key: &str, inst_id: Option<&str>, mode: ThrottleMode) -> Result<(), ThrottleError> {
+        let generation = match &mode {
+            ThrottleMode::Coalesce(c) => self.supersede(c),
+            _ => 0,
+        };
+        self.acquire_legs(key, &[(inst_id.map(str::to_string), 1)], &mode, generation).await
+    }
+
+    /// 登记一个新的合并请求，之前登记的同一 key 的请求在等待时会被放弃
+    ///
+    /// 排队发送时在入队处调用，出队后用返回的序号调用 acquire_ws
+    pub fn supersede(&self, coalesce_key: &str) -> u64 {
+        let mut entry = self.coalesce.entry(coalesce_key.to_string()).or_default();
+        *entry += 1;
+        *entry
+    }
+
+    /// WS 交易 op 限速，方式由 WsOp::mode 决定，generation 为 Coalesce 时 supersede 的返回值
+    pub async fn acquire_ws(&self, op: &WsOp, generation: u64) -> Result<(), ThrottleError> {
+        self.acquire_legs(&op.op, &op.legs, &op.mode(), generation).await
+    }
+
+    async fn acquire_legs(&self, key: &str, legs: &[(Option<String>, u32)], mode: &ThrottleMode, generation: u64) -> Result<(), ThrottleError> {
+        let rules = self.rules_for(key);
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let mut throttled = false;
+        loop {
+            let wait = self.try_take(&rules, legs);
+            if wait.is_zero() {
+                self.record(key, |s| &s.allowed, 1);
+                if throttled {
+                    self.record(key, |s| &s.waited_ms, started.elapsed().as_millis() as u64);
+                }
+                return Ok(());
+            }
+            if !throttled {
+                throttled = true;
+                self.record(key, |s| &s.throttled, 1);
+            }
+            match mode {
+                ThrottleMode::Wait => {}
+                ThrottleMode::Reject => {
+                    self.record(key, |s| &s.rejected, 1);
+                    warn!("{} {:?} 被限速拒绝", key, legs);
+                    return Err(ThrottleError::Rejected { key: key.to_string(), retry_after: wait });
+                }
+                ThrottleMode::Coalesce(c) => {
+                    if self.coalesce.get(c).map(|g| *g).unwrap_or(0) != generation {
+                        self.record(key, |s| &s.coalesced, 1);
+                        return Err(ThrottleError::Coalesced { key: c.clone() });
+                    }
+                }
+            }
+            tokio::time::sleep(wait).await;
+            if let ThrottleMode::Coalesce(c) = mode
+                && self.coalesce.get(c).map(|g| *g).unwrap_or(0) != generation
+            {
+                self.record(key, |s| &s.coalesced, 1);
+                return Err(ThrottleError::Coalesced { key: c.clone() });
+            }
+        }
+    }
+
+    fn record(&self, key: &str, counter: fn(&ThrottleStats) -> &AtomicU64, value: u64) {
+        let stats = self.stats.entry(key.to_string()).or_default();
+        counter(&stats).fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// 各 key 的限速统计
+    pub fn stats(&self) -> Vec<ThrottleSnapshot> {
+        self.stats
+            .iter()
+            .map(|s| ThrottleSnapshot {
+                key: s.key().clone(),
+                allowed: s.allowed.load(Ordering::Relaxed),
+                throttled: s.throttled.load(Ordering::Relaxed),
+                rejected: s.rejected.load(Ordering::Relaxed),
+                coalesced: s.coalesced.load(Ordering::Relaxed),
+                waited_ms: s.waited_ms.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// REST 请求统一走这里限速（等待模式），path 可以带查询字符串
+pub async fn throttle_rest(path: &str, inst_id: Option<&str>) {
+    let key = path.split('?').next().unwrap_or(path);
+    // 等待模式不会返回错误
+    let _ = RATE_LIMITER.acquire(key, inst_id, ThrottleMode::Wait).await;
+}
+
+#[derive(Deserialize)]
+struct WsOpRequest {
+    op: String,
+    #[serde(default)]
+    args: Vec<WsOpArg>,
+}
+
+#[derive(Deserialize)]
+struct WsOpArg {
+    #[serde(rename = "instId", default)]
+    inst_id: String,
+    #[serde(rename = "ordId", default)]
+    ord_id: String,
+    #[serde(rename = "clOrdId", default)]
+    cl_ord_id: String,
+}
+
+/// WS 发送的一条 op 消息的限速信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsOp {
+    pub op: String,
+    /// 第一个参数的 instId，用于按产品分队列
+    pub inst_id: Option<String>,
+    /// 按 instId 分组的订单数，批量操作每个订单消耗一个令牌
+    pub legs: Vec<(Option<String>, u32)>,
+    /// 改单的目标订单（ordId 或 clOrdId），用于合并同一订单的多次改单
+    pub target: Option<String>,
+}
+
+impl WsOp {
+    /// 新单被限速时直接拒绝，由策略决定是否重下；改单只保留最新一次；撤单等其他 op 等待
+    pub fn mode(&self) -> ThrottleMode {
+        match (self.op.as_str(), &self.target) {
+            ("order" | "batch-orders", _) => ThrottleMode::Reject,
+            ("amend-order", Some(target)) => ThrottleMode::Coalesce(self.coalesce_key(target)),
+            _ => ThrottleMode::Wait,
+        }
+    }
+
+    /// Coalesce 模式的 key，入队时传给 RateLimiter::supersede
+    pub fn coalesce(&self) -> Option<String> {
+        match self.mode() {
+            ThrottleMode::Coalesce(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn coalesce_key(&self, target: &str) -> String {
+        format!("{}:{}", self.op, target)
+    }
+}
+
+/// 解析 WS 发送的消息，取出 op、各产品的订单数和改单目标，用于限速
+pub fn ws_op(message: &str) -> Option<WsOp> {
+    let req = from_str::<WsOpRequest>(message).ok()?;
+    let inst_id = req.args.first().map(|a| a.inst_id.clone()).filter(|i| !i.is_empty());
+    let mut legs: Vec<(Option<String>, u32)> = Vec::new();
+    for arg in req.args.iter() {
+        let key = Some(arg.inst_id.clone()).filter(|i| !i.is_empty());
+        match legs.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, count)) => *count += 1,
+            None => legs.push((key, 1)),
+        }
+    }
+    if legs.is_empty() {
+        legs.push((None, 1));
+    }
+    let target = req
+        .args
+        .first()
+        .map(|a| if a.ord_id.is_empty() { a.cl_ord_id.clone() } else { a.ord_id.clone() })
+        .filter(|t| !t.is_empty());
+    Some(WsOp { op: req.op, inst_id, legs, target })
+}
+
+#[cfg(test)]
+mod rate_limit_test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reject_and_wait() {
+        let limiter = RateLimiter::new(vec![rule("order", "order", LimitScope::Instrument, 2, 100)]);
+        limiter.acquire("order", Some("BTC-USDT-SWAP"), ThrottleMode::Reject).await.unwrap();
+        limiter.acquire("order", Some("BTC-USDT-SWAP"), ThrottleMode::Reject).await.unwrap();
+        let err = limiter.acquire("order", Some("BTC-USDT-SWAP"), ThrottleMode::Reject).await;
+        assert!(matches!(err, Err(ThrottleError::Rejected { .. })));
+        // 不同产品互不影响
+        limiter.acquire("order", Some("ETH-USDT-SWAP"), ThrottleMode::Reject).await.unwrap();
+        let started = Instant::now();
+        limiter.acquire("order", Some("BTC-USDT-SWAP"), ThrottleMode::Wait).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        let stats = limiter.stats();
+        assert_eq!(stats[0].rejected, 1);
+        assert_eq!(stats[0].throttled, 2);
+        // 未配置的私有接口不限速
+        limiter.acquire("/api/v5/unknown", None, ThrottleMode::Reject).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let limiter = std::sync::Arc::new(RateLimiter::new(vec![rule("amend-order", "amend-order", LimitScope::Instrument, 1, 100)]));
+        limiter.acquire("amend-order", Some("BTC-USDT-SWAP"), ThrottleMode::Wait).await.unwrap();
+        let first = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                limiter.acquire("amend-order", Some("BTC-USDT-SWAP"), ThrottleMode::Coalesce("ord1".to_string())).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let second = limiter.acquire("amend-order", Some("BTC-USDT-SWAP"), ThrottleMode::Coalesce("ord1".to_string())).await;
+        assert!(matches!(first.await.unwrap(), Err(ThrottleError::Coalesced { .. })));
+        assert!(second.is_ok());
+    }
+
+    #[test]
+    fn test_ws_op() {
+        let op = ws_op(r#"{"id":"1","op":"order","args":[{"instId":"BTC-USDT-SWAP","side":"buy"}]}"#).unwrap();
+        assert_eq!(op.op, "order");
+        assert_eq!(op.inst_id.as_deref(), Some("BTC-USDT-SWAP"));
+        assert_eq!(op.mode(), ThrottleMode::Reject);
+        let op = ws_op(r#"{"op":"login","args":[{"apiKey":"x"}]}"#).unwrap();
+        assert_eq!(op.op, "login");
+        assert_eq!(op.inst_id, None);
+        let op = ws_op(r#"{"id":"2","op":"amend-order","args":[{"instId":"BTC-USDT-SWAP","ordId":"42","newPx":"1"}]}"#).unwrap();
+        assert_eq!(op.mode(), ThrottleMode::Coalesce("amend-order:42".to_string()));
+        assert_eq!(ws_op(r#"{"op":"cancel-order","args":[{"instId":"A","ordId":"1"}]}"#).unwrap().mode(), ThrottleMode::Wait);
+        let batch = ws_op(r#"{"id":"3","op":"batch-orders","args":[{"instId":"A"},{"instId":"B"},{"instId":"A"}]}"#).unwrap();
+        assert_eq!(batch.legs, vec![(Some("A".to_string()), 2), (Some("B".to_string()), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_cost() {
+        let limiter = RateLimiter::new(vec![
+            rule("batch-orders", "batch-orders", LimitScope::Instrument, 3, 1000),
+            rule("batch-orders", ACCOUNT_ORDERS, LimitScope::Account, 4, 1000),
+        ]);
+        let batch = ws_op(r#"{"op":"batch-orders","args":[{"instId":"A"},{"instId":"A"},{"instId":"B"}]}"#).unwrap();
+        limiter.acquire_ws(&batch, 0).await.unwrap();
+        // 账户桶已用 3 个，再来 2 个订单超出，整批拒绝且不消耗令牌
+        let second = ws_op(r#"{"op":"batch-orders","args":[{"instId":"B"},{"instId":"C"}]}"#).unwrap();
+        assert!(matches!(limiter.acquire_ws(&second, 0).await, Err(ThrottleError::Rejected { .. })));
+        let single = ws_op(r#"{"op":"batch-orders","args":[{"instId":"C"}]}"#).unwrap();
+        limiter.acquire_ws(&single, 0).await.unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::Utc;
+use log::{info, warn};
+use sonic_rs::{Deserialize, Serialize};
+use crate::common::account::{fetch_balance, fetch_positions, AccountState};
+use crate::common::algo::{orders_algo_pending, AlgoOrdType, AlgoOrderStore};
+use crate::common::cl_ord_id::ClOrdIdGenerator;
+use crate::common::fills::FillJournal;
+use crate::common::risk::RiskGate;
+use crate::common::ws_api::InstType;
+use crate::common::order_store::{fetch_order, fetch_orders_pending_after, OrderState, OrderStore};
+
+/// orders-pending 单页最多 100 条
+const ORDERS_PENDING_PAGE: usize = 100;
+
+/// 对账结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    /// 交易所上的挂单数量
+    pub open_orders: usize,
+    /// 外部挂单的 ordId
+    pub foreign_orders: Vec<String>,
+    /// 本地认为未终结、但交易所已不在挂单列表中的订单，已重新查询状态
+    pub resolved_orders: usize,
+    pub algo_orders: usize,
+    /// 外部策略委托的 algoId
+    pub foreign_algos: Vec<String>,
+    /// 断线期间补拉到的成交
+    pub backfilled_fills: usize,
+    pub positions: usize,
+    pub balances: usize,
+    pub errors: Vec<String>,
+}
+
+/// 对账时补拉成交的产品类型
+const FILL_INST_TYPES: [&str; 3] = [InstType::SWAP, InstType::FUTURES, InstType::SPOT];
+
+/// 从 REST 拉取挂单、策略委托、持仓和余额，覆盖本地状态，并补拉成交
+///
+/// 启动时和每次私有连接重新登录后调用，用于补上断线期间漏掉的推送。
+/// 与 WS 推送并发时依赖各 store 的 uTime 比较丢弃较旧的数据，成交按 tradeId 去重。
+pub async fn reconcile(orders: &OrderStore, algos: &AlgoOrderStore, account: &AccountState, ids: &ClOrdIdGenerator, fills: &FillJournal, risk: &RiskGate) -> ReconcileReport {
+    let mut report = ReconcileReport::default();
+    let snapshot_ms = Utc::now().timestamp_millis() as u64;
+    reconcile_orders(orders, ids, &mut report).await;
+    reconcile_algos(algos, ids, &mut report).await;
+    reconcile_fills(fills, risk, &mut report).await;
+    match fetch_positions(None).await {
+        Ok(positions) => {
+            report.positions = positions.iter().filter(|p| !p.pos.is_empty() && p.pos != "0").count();
+            account.replace_positions(&positions, snapshot_ms);
+        }
+        Err(e) => report.errors.push(format!("查询持仓失败 {}", e)),
+    }
+    match fetch_balance(None).await {
+        Ok(balance) => {
+            report.balances = balance.iter().map(|a| a.details.len()).sum();
+            account.on_account(&balance);
+        }
+        Err(e) => report.errors.push(format!("查询余额失败 {}", e)),
+    }
+    if report.errors.is_empty() {
+        info!(
+            "对账完成 挂单 {} 外部挂单 {} 补查 {} 策略单 {} 外部策略单 {} 补拉成交 {} 持仓 {} 币种 {}",
+            report.open_orders, report.foreign_orders.len(), report.resolved_orders,
+            report.algo_orders, report.foreign_algos.len(), report.backfilled_fills, report.positions, report.balances
+        );
+    } else {
+        warn!("对账存在错误 {:?}", report.errors);
+    }
+    if !report.foreign_orders.is_empty() || !report.foreign_algos.is_empty() {
+        warn!("存在外部订单 {:?} 外部策略单 {:?}", report.foreign_orders, report.foreign_algos);
+    }
+    report
+}
+
+async fn reconcile_orders(orders: &OrderStore, ids: &ClOrdIdGenerator, report: &mut ReconcileReport) {
+    let mut pending = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let page = match fetch_orders_pending_after(None, None, after.as_deref()).await {
+            Ok(page) => page,
+            Err(e) => {
+                // 挂单列表不完整时不能据此判断哪些订单已终结
+                report.errors.push(format!("查询挂单失败 {}", e));
+                return;
+            }
+        };
+        let full = page.len() >= ORDERS_PENDING_PAGE;
+        after = page.last().map(|o| o.ord_id.clone());
+        pending.extend(page);
+        if !full || after.is_none() {
+            break;
+        }
+    }
+    report.open_orders = pending.len();
+    for data in pending.iter() {
+        orders.on_order_update(data);
+        if !ids.is_ours(&data.cl_ord_id) {
+            orders.mark_foreign(&data.cl_ord_id, &data.ord_id);
+            report.foreign_orders.push(data.ord_id.clone());
+        }
+    }
+    // 本地未终结但已不在挂单列表中：断线期间成交或撤销，逐个补查
+    let missing = orders
+        .open_orders(None)
+        .into_iter()
+        .filter(|o| o.state != OrderState::PendingSubmit && !o.ord_id.is_empty())
+        .filter(|o| !pending.iter().any(|p| p.ord_id == o.ord_id))
+        .collect::<Vec<_>>();
+    for order in missing.iter() {
+        match fetch_order(&order.inst_id, &order.ord_id).await {
+            Ok(Some(data)) => {
+                orders.on_order_update(&data);
+                report.resolved_orders += 1;
+            }
+            Ok(None) => report.errors.push(format!("订单 {} 不存在", order.ord_id)),
+            Err(e) => report.errors.push(format!("查询订单 {} 失败 {}", order.ord_id, e)),
+        }
+    }
+}
+
+/// 从成交日志最后一笔开始补拉，没有记录时从当天 0 点（UTC）开始
+async fn reconcile_fills(fills: &FillJournal, risk: &RiskGate, report: &mut ReconcileReport) {
+    let begin = fills.last_ts().unwrap_or_else(|| {
+        let midnight = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap_or_default();
+        midnight.and_utc().timestamp_millis() as u64
+    });
+    let begin = begin.to_string();
+    for inst_type in FILL_INST_TYPES {
+        match fills.sync_from_rest(inst_type, Some(&begin)).await {
+            Ok(recorded) => {
+                report.backfilled_fills += recorded.len();
+                for (fill, pnl) in recorded.iter() {
+                    risk.record_fill(fill, *pnl);
+                }
+            }
+            Err(e) => report.errors.push(format!("补拉 {} 成交失败 {}", inst_type, e)),
+        }
+    }
+}
+
+async fn reconcile_algos(algos: &AlgoOrderStore, ids: &ClOrdIdGenerator, report: &mut ReconcileReport) {
+    let ord_types = [
+        AlgoOrdType::CONDITIONAL,
+        AlgoOrdType::OCO,
+        AlgoOrdType::TRIGGER,
+        AlgoOrdType::MOVE_ORDER_STOP,
+        AlgoOrdType::ICEBERG,
+        AlgoOrdType::TWAP,
+    ];
+    for ord_type in ord_types {
+        match orders_algo_pending(ord_type, None).await {
+            Ok(pending) => {
+                report.algo_orders += pending.len();
+                report.foreign_algos.extend(
+                    pending.iter().filter(|a| !ids.is_ours(&a.algo_cl_ord_id)).map(|a| a.algo_id.clone()),
+                );
+                algos.on_update(&pending);
+            }
+            Err(e) => report.errors.push(format!("查询 {} 策略单失败 {}", ord_type, e)),
+        }
+    }
+}
@@ -1,6 +1,25 @@
 use sonic_rs::{from_str, Deserialize, Serialize};
 use crate::common::utils::{HttpClient};
 
+/// REST 接口的通用响应结构
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OkxResponse<T> {
+    pub code: String,
+    pub msg: String,
+    #[serde(default = "Vec::new")]
+    pub data: Vec<T>,
+}
+
+impl<T> OkxResponse<T> {
+    /// code 非 "0" 时转为错误
+    pub fn into_result(self) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        if self.code != "0" {
+            return Err(format!("okx error code {} msg {}", self.code, self.msg).into());
+        }
+        Ok(self.data)
+    }
+}
+
 // 主响应结构体
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OkxSwapInstrumentsResponse {
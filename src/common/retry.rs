@@ -0,0 +1,324 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::time::Duration;
+use chrono::Utc;
+use log::{error, info, warn};
+use reqwest::{Response, StatusCode};
+use sonic_rs::Deserialize;
+use crate::common::credentials::CredentialError;
+
+/// 限速（HTTP 429 对应的业务码）
+pub const CODE_RATE_LIMITED: &str = "50011";
+/// 可以重试的服务端错误码：服务暂不可用、接口超时、系统繁忙、系统错误
+const RETRYABLE_CODES: [&str; 4] = ["50001", "50004", "50013", "50026"];
+
+/// 失败后可以安全重试的 POST 接口：重复执行结果相同
+const SAFE_POST_PATHS: [&str; 6] = [
+    "/api/v5/trade/cancel-order",
+    "/api/v5/trade/cancel-batch-orders",
+    "/api/v5/trade/cancel-algos",
+    "/api/v5/trade/mass-cancel",
+    "/api/v5/account/set-leverage",
+    "/api/v5/account/set-position-mode",
+];
+
+/// 不受熔断限制的接口：一键平仓（flatten_all）用到的查询、撤单和市价全平，熔断期间仍然要能清仓
+const BREAKER_EXEMPT_PATHS: [&str; 8] = [
+    "/api/v5/trade/orders-pending",
+    "/api/v5/trade/orders-algo-pending",
+    "/api/v5/account/positions",
+    "/api/v5/trade/cancel-order",
+    "/api/v5/trade/cancel-batch-orders",
+    "/api/v5/trade/cancel-algos",
+    "/api/v5/trade/mass-cancel",
+    "/api/v5/trade/close-position",
+];
+
+/// 重试策略
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 总尝试次数（含第一次）
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// 被限速时的最小等待
+    pub rate_limit_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay_ms: 200,
+        max_delay_ms: 5_000,
+        rate_limit_delay_ms: 1_000,
+    };
+    /// 不重试
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        base_delay_ms: 0,
+        max_delay_ms: 0,
+        rate_limit_delay_ms: 0,
+    };
+
+    /// 第 attempt 次失败后的等待：指数退避加少量抖动
+    pub fn backoff(&self, attempt: u32, rate_limited: bool) -> Duration {
+        let exp = self.base_delay_ms.saturating_mul(1u64 << attempt.min(16)).min(self.max_delay_ms);
+        let base = if rate_limited { exp.max(self.rate_limit_delay_ms) } else { exp };
+        let jitter = (Utc::now().timestamp_subsec_micros() as u64) % (self.base_delay_ms / 4 + 1);
+        Duration::from_millis(base + jitter)
+    }
+}
+
+/// GET 总是可以重试；POST 只有幂等接口才重试
+///
+/// 下单不重试：超时的请求可能已经成交，重试时同一个 clOrdId 会被交易所以 51016 拒绝，
+/// 调用方会把已经下出去的订单当成失败，由订单推送和对账确认结果
+pub fn post_is_retryable(path: &str) -> bool {
+    SAFE_POST_PATHS.contains(&path)
+}
+
+/// REST 请求错误
+#[derive(Debug)]
+pub enum RestError {
+    Http(reqwest::Error),
+    /// 熔断中，直接失败
+    CircuitOpen { retry_after_ms: i64 },
+    /// 凭证不可用，请求未发出
+    Credentials(CredentialError),
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestError::Http(e) => write!(f, "{}", e),
+            RestError::CircuitOpen { retry_after_ms } => write!(f, "REST 熔断中，{}ms 后重试", retry_after_ms),
+            RestError::Credentials(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RestError::Http(e) => Some(e),
+            RestError::CircuitOpen { .. } => None,
+            RestError::Credentials(e) => Some(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RestError {
+    fn from(e: reqwest::Error) -> Self {
+        RestError::Http(e)
+    }
+}
+
+impl From<CredentialError> for RestError {
+    fn from(e: CredentialError) -> Self {
+        RestError::Credentials(e)
+    }
+}
+
+/// 熔断器：连续 threshold 次服务端错误后打开，cooldown 内请求直接失败；
+/// 冷却结束后放行请求（半开），成功则关闭，失败则重新打开
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown_ms: i64,
+    failures: AtomicU32,
+    open_until_ms: AtomicI64,
+}
+
+pub static REST_BREAKER: CircuitBreaker = CircuitBreaker::new(5, 30_000);
+
+impl CircuitBreaker {
+    pub const fn new(threshold: u32, cooldown_ms: i64) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold,
+            cooldown_ms,
+            failures: AtomicU32::new(0),
+            open_until_ms: AtomicI64::new(0),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), RestError> {
+        self.check_at(Utc::now().timestamp_millis())
+    }
+
+    fn check_at(&self, now_ms: i64) -> Result<(), RestError> {
+        let open_until = self.open_until_ms.load(Ordering::Relaxed);
+        if now_ms < open_until {
+            return Err(RestError::CircuitOpen { retry_after_ms: open_until - now_ms });
+        }
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.check().is_err()
+    }
+
+    pub fn on_success(&self) {
+        if self.failures.swap(0, Ordering::Relaxed) >= self.threshold {
+            info!("REST 熔断恢复");
+        }
+    }
+
+    pub fn on_failure(&self) {
+        self.on_failure_at(Utc::now().timestamp_millis());
+    }
+
+    fn on_failure_at(&self, now_ms: i64) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.threshold {
+            self.open_until_ms.store(now_ms + self.cooldown_ms, Ordering::Relaxed);
+            error!("REST 连续失败 {} 次，熔断 {}ms", failures, self.cooldown_ms);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CodeOnly {
+    #[serde(default)]
+    code: String,
+}
+
+/// 单次响应的分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Ok,
+    RateLimited,
+    ServerError,
+}
+
+fn classify(status: StatusCode, body: &[u8]) -> Outcome {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Outcome::RateLimited;
+    }
+    if status.is_server_error() {
+        return Outcome::ServerError;
+    }
+    let code = sonic_rs::from_slice::<CodeOnly>(body).map(|c| c.code).unwrap_or_default();
+    if code == CODE_RATE_LIMITED {
+        Outcome::RateLimited
+    } else if RETRYABLE_CODES.contains(&code.as_str()) {
+        Outcome::ServerError
+    } else {
+        Outcome::Ok
+    }
+}
+
+/// 用已读出的 body 重新构造 Response，调用方仍按原来的方式读取
+fn rebuild(status: StatusCode, headers: reqwest::header::HeaderMap, body: Vec<u8>) -> Response {
+    let mut builder = http::Response::builder().status(status);
+    if let Some(h) = builder.headers_mut() {
+        *h = headers;
+    }
+    Response::from(builder.body(body).unwrap())
+}
+
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let secs = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// 熔断期间仍然放行的请求
+fn bypasses_breaker(path: &str) -> bool {
+    BREAKER_EXEMPT_PATHS.contains(&path)
+}
+
+/// 按策略发送请求：send 每次重新构造并签名请求，以免时间戳过期
+/// 限速（429 / 50011）和服务端错误会退避重试，最后一次的响应原样返回给调用方；
+/// 熔断打开时直接失败，BREAKER_EXEMPT_PATHS 里的接口除外
+pub async fn send_with_retry<F, Fut>(path: &str, policy: RetryPolicy, mut send: F) -> Result<Response, RestError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Response, reqwest::Error>>,
+{
+    let exempt = bypasses_breaker(path);
+    let mut attempt = 0;
+    loop {
+        if !exempt {
+            REST_BREAKER.check()?;
+        }
+        attempt += 1;
+        let last = attempt >= policy.max_attempts;
+        let response = match send().await {
+            Ok(response) => response,
+            Err(e) => {
+                // 超时、连接失败
+                REST_BREAKER.on_failure();
+                if last {
+                    return Err(e.into());
+                }
+                let delay = policy.backoff(attempt - 1, false);
+                warn!("{} 请求失败 {}，{:?} 后第 {} 次重试", path, e, delay, attempt);
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+        let outcome = classify(status, &body);
+        match outcome {
+            Outcome::Ok => REST_BREAKER.on_success(),
+            Outcome::ServerError => REST_BREAKER.on_failure(),
+            Outcome::RateLimited => {}
+        }
+        if outcome == Outcome::Ok || last {
+            return Ok(rebuild(status, headers, body));
+        }
+        let rate_limited = outcome == Outcome::RateLimited;
+        let mut delay = policy.backoff(attempt - 1, rate_limited);
+        if rate_limited && let Some(after) = retry_after(&headers) {
+            delay = delay.max(after);
+        }
+        warn!("{} 返回 {} {}，{:?} 后第 {} 次重试", path, status, String::from_utf8_lossy(&body), delay, attempt);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod retry_test {
+    use super::*;
+
+    #[test]
+    fn test_post_retryable() {
+        assert!(post_is_retryable("/api/v5/trade/cancel-order"));
+        assert!(post_is_retryable("/api/v5/account/set-leverage"));
+        assert!(!post_is_retryable("/api/v5/trade/order"));
+        assert!(!post_is_retryable("/api/v5/trade/batch-orders"));
+        assert!(!post_is_retryable("/api/v5/trade/order-algo"));
+        // 一键平仓用到的撤单和全平不受熔断限制，下单仍然受限
+        assert!(bypasses_breaker("/api/v5/trade/cancel-batch-orders"));
+        assert!(bypasses_breaker("/api/v5/trade/cancel-algos"));
+        assert!(bypasses_breaker("/api/v5/trade/close-position"));
+        assert!(bypasses_breaker("/api/v5/account/positions"));
+        assert!(!bypasses_breaker("/api/v5/trade/order"));
+    }
+
+    #[test]
+    fn test_classify_and_breaker() {
+        assert_eq!(classify(StatusCode::TOO_MANY_REQUESTS, b""), Outcome::RateLimited);
+        assert_eq!(classify(StatusCode::OK, br#"{"code":"50011","msg":"Too Many Requests","data":[]}"#), Outcome::RateLimited);
+        assert_eq!(classify(StatusCode::OK, br#"{"code":"51008","msg":"","data":[]}"#), Outcome::Ok);
+        assert_eq!(classify(StatusCode::BAD_GATEWAY, b"<html>"), Outcome::ServerError);
+
+        let breaker = CircuitBreaker::new(2, 1_000);
+        breaker.on_failure_at(10_000);
+        assert!(breaker.check_at(10_000).is_ok());
+        breaker.on_failure_at(10_000);
+        assert!(matches!(breaker.check_at(10_500), Err(RestError::CircuitOpen { retry_after_ms: 500 })));
+        assert!(breaker.check_at(11_000).is_ok());
+        breaker.on_success();
+        assert!(breaker.check_at(11_000).is_ok());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::DEFAULT;
+        assert!(policy.backoff(0, false) < Duration::from_millis(300));
+        assert!(policy.backoff(0, true) >= Duration::from_millis(1_000));
+        assert!(policy.backoff(10, false) <= Duration::from_millis(5_100));
+    }
+}
@@ -0,0 +1,760 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use chrono::{DateTime, NaiveDate, Utc};
+use dashmap::DashMap;
+use log::{error, info, warn};
+use sonic_rs::{from_str, Deserialize, JsonValueTrait, Serialize, Value};
+use crate::common::account::{get_pos_mode, AccountState};
+use crate::common::fills::Fill;
+use crate::common::order_store::OrderStore;
+use crate::common::instrument::INSTRUMENT_REGISTRY;
+use crate::common::utils::str_to_f64;
+use crate::common::ws_api::{OrderType, Side};
+
+pub const RISK_LIMITS_PATH: &str = "data/risk.json";
+
+/// 风控限额，字段为 None 时不检查
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskLimits {
+    /// 单个产品的最大持仓（张），按 instId 配置
+    pub max_position: HashMap<String, f64>,
+    /// 未单独配置的产品的最大持仓（张）
+    pub default_max_position: Option<f64>,
+    /// 所有持仓加挂单的最大名义价值（USD）
+    pub max_gross_notional: Option<f64>,
+    /// 最大挂单数量
+    pub max_open_orders: Option<usize>,
+    /// 单笔最大下单量（张）
+    pub max_order_size: Option<f64>,
+    /// 限价单价格偏离买一卖一或标记价格的最大比例，如 0.05 表示 5%
+    pub price_band_pct: Option<f64>,
+    /// 当日最大亏损（USD），超过后触发全局停止交易
+    pub max_daily_loss: Option<f64>,
+}
+
+impl Default for RiskLimits {
+    fn default() -> Self {
+        RiskLimits {
+            max_position: HashMap::new(),
+            default_max_position: None,
+            max_gross_notional: None,
+            max_open_orders: None,
+            max_order_size: None,
+            price_band_pct: Some(0.05),
+            max_daily_loss: None,
+        }
+    }
+}
+
+impl RiskLimits {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RiskLimits, Box<dyn std::error::Error>> {
+        let limits = sonic_rs::from_reader::<BufReader<File>, RiskLimits>(BufReader::new(File::open(path)?))?;
+        Ok(limits)
+    }
+
+    /// 配置文件不存在时使用默认值
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> RiskLimits {
+        let path = path.as_ref();
+        if !path.exists() {
+            info!("风控配置 {} 不存在，使用默认限额", path.display());
+            return RiskLimits::default();
+        }
+        match RiskLimits::load(path) {
+            Ok(limits) => limits,
+            Err(e) => {
+                error!("风控配置 {} 解析失败 {}，使用默认限额", path.display(), e);
+                RiskLimits::default()
+            }
+        }
+    }
+
+    fn max_position_of(&self, inst_id: &str) -> Option<f64> {
+        self.max_position.get(inst_id).copied().or(self.default_max_position)
+    }
+}
+
+/// 待风控检查的下单意图
+#[derive(Debug, Clone)]
+pub struct OrderIntent {
+    pub inst_id: String,
+    pub side: String,
+    pub td_mode: String,
+    pub ord_type: String,
+    /// 市价单为 None
+    pub px: Option<String>,
+    pub sz: String,
+    pub reduce_only: bool,
+    /// net / long / short，为空时按当前持仓模式和 reduce_only 推断
+    pub pos_side: String,
+}
+
+impl OrderIntent {
+    /// 订单作用的持仓方向
+    pub fn leg(&self) -> &str {
+        if self.pos_side.is_empty() {
+            get_pos_mode().pos_side(&self.side, self.reduce_only)
+        } else {
+            &self.pos_side
+        }
+    }
+}
+
+/// WS 下单消息中的一笔订单
+#[derive(Debug, Clone)]
+pub struct WsOrder {
+    pub cl_ord_id: String,
+    pub intent: OrderIntent,
+}
+
+#[derive(Deserialize)]
+struct WsOrderRequest {
+    op: String,
+    #[serde(default)]
+    args: Vec<Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct WsOrderArg {
+    inst_id: String,
+    side: String,
+    td_mode: String,
+    ord_type: String,
+    px: Option<String>,
+    sz: String,
+    pos_side: String,
+    cl_ord_id: String,
+}
+
+/// 解析发往下单通道的消息，不是 order / batch-orders 时返回 None
+///
+/// 双向持仓的平仓单没有 reduceOnly，按 posSide 与方向相反判断
+pub fn parse_ws_orders(message: &str) -> Option<Result<Vec<WsOrder>, RiskReject>> {
+    let malformed = || RiskReject::Malformed { message: message.to_string() };
+    let req = from_str::<WsOrderRequest>(message).ok()?;
+    if req.op != "order" && req.op != "batch-orders" {
+        return None;
+    }
+    let mut orders = Vec::with_capacity(req.args.len());
+    for value in req.args.iter() {
+        let Ok(arg) = sonic_rs::from_value::<WsOrderArg>(value) else {
+            return Some(Err(malformed()));
+        };
+        let reduce_only = match value.get("reduceOnly") {
+            Some(v) => v.as_bool().unwrap_or(false) || v.as_str() == Some("true"),
+            None => false,
+        } || (arg.pos_side == "long" && arg.side == Side::SELL)
+            || (arg.pos_side == "short" && arg.side == Side::BUY);
+        orders.push(WsOrder {
+            cl_ord_id: arg.cl_ord_id,
+            intent: OrderIntent {
+                inst_id: arg.inst_id,
+                side: arg.side,
+                td_mode: arg.td_mode,
+                ord_type: arg.ord_type,
+                px: arg.px.filter(|p| !p.is_empty()),
+                sz: arg.sz,
+                reduce_only,
+                pos_side: arg.pos_side,
+            },
+        });
+    }
+    Some(Ok(orders))
+}
+
+/// 风控拒单原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskReject {
+    KillSwitch,
+    TradingPaused { inst_id: String },
+    MaxOrderSize { sz: f64, max: f64 },
+    MaxPosition { inst_id: String, projected: f64, max: f64 },
+    MaxGrossNotional { projected: f64, max: f64 },
+    MaxOpenOrders { count: usize, max: usize },
+    NoReferencePrice { inst_id: String },
+    PriceBand { px: f64, reference: f64, band_pct: f64 },
+    /// 发往下单通道的 order / batch-orders 消息无法解析
+    Malformed { message: String },
+}
+
+impl fmt::Display for RiskReject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskReject::KillSwitch => write!(f, "已触发停止交易"),
+            RiskReject::TradingPaused { inst_id } => write!(f, "{} 暂停交易", inst_id),
+            RiskReject::MaxOrderSize { sz, max } => write!(f, "下单量 {} 超过单笔上限 {}", sz, max),
+            RiskReject::MaxPosition { inst_id, projected, max } => write!(f, "{} 成交后持仓 {} 超过上限 {}", inst_id, projected, max),
+            RiskReject::MaxGrossNotional { projected, max } => write!(f, "总名义价值 {} 超过上限 {}", projected, max),
+            RiskReject::MaxOpenOrders { count, max } => write!(f, "挂单数 {} 已达上限 {}", count, max),
+            RiskReject::NoReferencePrice { inst_id } => write!(f, "{} 没有参考价格，无法做价格检查", inst_id),
+            RiskReject::PriceBand { px, reference, band_pct } => write!(f, "价格 {} 偏离参考价 {} 超过 {}%", px, reference, band_pct * 100.0),
+            RiskReject::Malformed { message } => write!(f, "无法解析的下单消息 {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RiskReject {}
+
+/// 暂停交易的来源，同一产品可以同时有多个来源，全部解除后才恢复交易
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PauseReason {
+    /// 影响交易的系统维护
+    Maintenance,
+    /// 产品状态不是 live
+    State,
+    /// 产品下线
+    Delisted,
+    /// tickSz 变化后重建盘口
+    BookRebuild,
+    /// 启动时杠杆没有设置成功，需要人工处理后重启
+    Leverage,
+}
+
+impl fmt::Display for PauseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PauseReason::Maintenance => write!(f, "系统维护"),
+            PauseReason::State => write!(f, "产品状态"),
+            PauseReason::Delisted => write!(f, "产品下线"),
+            PauseReason::BookRebuild => write!(f, "重建盘口"),
+            PauseReason::Leverage => write!(f, "杠杆设置失败"),
+        }
+    }
+}
+
+/// 价格检查用的参考价
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReferencePrice {
+    pub bid: f64,
+    pub ask: f64,
+    pub mark: f64,
+}
+
+impl ReferencePrice {
+    /// 标记价格，没有时用买一卖一中间价
+    pub fn price(&self) -> Option<f64> {
+        if self.mark > 0.0 {
+            Some(self.mark)
+        } else if self.bid > 0.0 && self.ask > 0.0 {
+            Some((self.bid + self.ask) / 2.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// 按 1:1 计为 USD 的币种
+const USD_CCYS: [&str; 4] = ["USD", "USDT", "USDC", "USDG"];
+
+#[derive(Debug)]
+struct DailyLoss {
+    day: NaiveDate,
+    pnl: f64,
+}
+
+/// 已实现盈亏的币种：合约为结算币，现货为计价币
+fn pnl_ccy(inst_id: &str) -> String {
+    match INSTRUMENT_REGISTRY.get(inst_id) {
+        Some(instrument) if instrument.is_derivative() => instrument.settle_ccy.clone(),
+        Some(instrument) => instrument.quote_ccy.clone(),
+        None => {
+            // 不在注册表中时按 instId 推断，BTC-USD-SWAP 这类币本位合约以币结算
+            let mut parts = inst_id.split('-');
+            let (base, quote) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+            if quote == "USD" { base } else { quote }.to_string()
+        }
+    }
+}
+
+/// 合约名义价值（USD）：正向合约 张数 * 面值 * 价格，反向合约 张数 * 面值
+pub fn notional_usd(inst_id: &str, sz: f64, px: f64) -> f64 {
+    match INSTRUMENT_REGISTRY.get(inst_id) {
+        Some(instrument) => instrument.notional_quote(sz, px),
+        None => sz.abs() * px,
+    }
+}
+
+/// 下单前的风控检查，所有订单在进入下单通道前都要经过这里
+pub struct RiskGate {
+    limits: RwLock<RiskLimits>,
+    kill_switch: AtomicBool,
+    daily: Mutex<DailyLoss>,
+    prices: DashMap<String, ReferencePrice>,
+    paused: DashMap<String, HashSet<PauseReason>>,
+}
+
+impl RiskGate {
+    pub fn new(limits: RiskLimits) -> RiskGate {
+        RiskGate {
+            limits: RwLock::new(limits),
+            kill_switch: AtomicBool::new(false),
+            daily: Mutex::new(DailyLoss { day: Utc::now().date_naive(), pnl: 0.0 }),
+            prices: DashMap::new(),
+            paused: DashMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> RiskLimits {
+        self.limits.read().unwrap().clone()
+    }
+
+    pub fn set_limits(&self, limits: RiskLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// 更新买一卖一（来自 tickers / books5）
+    pub fn update_bbo(&self, inst_id: &str, bid: f64, ask: f64) {
+        let mut price = self.prices.entry(inst_id.to_string()).or_default();
+        price.bid = bid;
+        price.ask = ask;
+    }
+
+    /// 更新标记价格
+    pub fn update_mark(&self, inst_id: &str, mark: f64) {
+        self.prices.entry(inst_id.to_string()).or_default().mark = mark;
+    }
+
+    pub fn reference_price(&self, inst_id: &str) -> Option<ReferencePrice> {
+        self.prices.get(inst_id).map(|p| *p)
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.kill_switch.load(Ordering::SeqCst)
+    }
+
+    pub fn trip_kill_switch(&self, reason: &str) {
+        if !self.kill_switch.swap(true, Ordering::SeqCst) {
+            error!("触发停止交易: {}", reason);
+        }
+    }
+
+    /// 人工确认后恢复交易
+    pub fn reset_kill_switch(&self) {
+        self.kill_switch.store(false, Ordering::SeqCst);
+        info!("停止交易已解除");
+    }
+
+    /// 暂停单个产品的交易，detail 只用于日志
+    pub fn pause(&self, inst_id: &str, reason: PauseReason, detail: &str) {
+        warn!("{} 暂停交易: {} {}", inst_id, reason, detail);
+        self.paused.entry(inst_id.to_string()).or_default().insert(reason);
+    }
+
+    /// 解除一个暂停来源，没有其他来源时恢复交易
+    pub fn resume(&self, inst_id: &str, reason: PauseReason) {
+        match self.paused.get_mut(inst_id) {
+            Some(mut reasons) => {
+                if !reasons.remove(&reason) {
+                    return;
+                }
+                if !reasons.is_empty() {
+                    info!("{} 解除{}，仍因 {:?} 暂停", inst_id, reason, reasons.value());
+                    return;
+                }
+            }
+            None => return,
+        }
+        if self.paused.remove_if(inst_id, |_, reasons| reasons.is_empty()).is_some() {
+            info!("{} 恢复交易", inst_id);
+        }
+    }
+
+    pub fn is_paused(&self, inst_id: &str) -> bool {
+        self.paused.contains_key(inst_id)
+    }
+
+    /// 当前的暂停来源
+    pub fn pause_reasons(&self, inst_id: &str) -> HashSet<PauseReason> {
+        self.paused.get(inst_id).map(|reasons| reasons.clone()).unwrap_or_default()
+    }
+
+    /// 记录一笔成交的已实现盈亏和手续费，不是今天（UTC）的成交不计入当日亏损
+    ///
+    /// 盈亏以结算币计（币本位合约为币），手续费以 feeCcy 计，都换算成 USD 后再计入
+    pub fn record_fill(&self, fill: &Fill, pnl: f64) {
+        let today = Utc::now().date_naive();
+        let day = DateTime::from_timestamp_millis(fill.ts as i64).map(|t| t.date_naive());
+        if day != Some(today) {
+            return;
+        }
+        self.record_pnl(self.fill_usd_pnl(fill, pnl));
+    }
+
+    /// 用今天（UTC）的全部成交重算当日盈亏，覆盖快照恢复的值，重算后重新检查亏损上限
+    ///
+    /// 快照只每隔一段时间保存一次，快照之后记入日志的成交只能从日志里找回
+    pub fn rebuild_daily_pnl(&self, fills: &[(Fill, f64)]) {
+        let today = Utc::now().date_naive();
+        let total: f64 = fills
+            .iter()
+            .filter(|(fill, _)| DateTime::from_timestamp_millis(fill.ts as i64).map(|t| t.date_naive()) == Some(today))
+            .map(|(fill, pnl)| self.fill_usd_pnl(fill, *pnl))
+            .sum();
+        {
+            let mut daily = self.daily.lock().unwrap();
+            daily.day = today;
+            daily.pnl = total;
+        }
+        info!("按 {} 笔成交重算当日盈亏 {} {}", fills.len(), today, total);
+        self.record_pnl(0.0);
+    }
+
+    /// 一笔成交的已实现盈亏加手续费换算成 USD，无法换算的部分不计入
+    fn fill_usd_pnl(&self, fill: &Fill, pnl: f64) -> f64 {
+        let mut total = 0.0;
+        for (ccy, amount) in [(pnl_ccy(&fill.inst_id), pnl), (fill.fee_ccy.clone(), str_to_f64(&fill.fee))] {
+            if amount == 0.0 {
+                continue;
+            }
+            match self.usd_price(&ccy, fill) {
+                Some(price) => total += amount * price,
+                None => warn!("{} 成交 {} 的 {} {} 无法换算成 USD，不计入当日盈亏", fill.inst_id, fill.trade_id, amount, ccy),
+            }
+        }
+        total
+    }
+
+    /// ccy 的 USD 价格：稳定币为 1；成交产品以 USD 计价且 ccy 为其交易币时用该产品的参考价，
+    /// 没有参考价时用成交价；其他币种用 {ccy}-USDT 或 {ccy}-USDT-SWAP 的参考价
+    fn usd_price(&self, ccy: &str, fill: &Fill) -> Option<f64> {
+        if USD_CCYS.contains(&ccy) {
+            return Some(1.0);
+        }
+        let mut parts = fill.inst_id.split('-');
+        let (base, quote) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        if base == ccy && USD_CCYS.contains(&quote) {
+            let px = str_to_f64(&fill.px);
+            return self.reference_price(&fill.inst_id)
+                .and_then(|p| p.price())
+                .or(Some(px).filter(|px| *px > 0.0));
+        }
+        [format!("{}-USDT", ccy), format!("{}-USDT-SWAP", ccy)]
+            .iter()
+            .find_map(|inst_id| self.reference_price(inst_id).and_then(|p| p.price()))
+    }
+
+    /// 记录已实现盈亏（含手续费），当日亏损超限时触发停止交易
+    pub fn record_pnl(&self, pnl: f64) {
+        let today = Utc::now().date_naive();
+        let mut daily = self.daily.lock().unwrap();
+        if daily.day != today {
+            daily.day = today;
+            daily.pnl = 0.0;
+        }
+        daily.pnl += pnl;
+        if let Some(max) = self.limits.read().unwrap().max_daily_loss
+            && daily.pnl <= -max
+        {
+            let reason = format!("当日亏损 {} 超过上限 {}", -daily.pnl, max);
+            drop(daily);
+            self.trip_kill_switch(&reason);
+        }
+    }
+
+    /// 当日 (日期, 已实现盈亏)
+    pub fn daily_pnl(&self) -> (NaiveDate, f64) {
+        let daily = self.daily.lock().unwrap();
+        (daily.day, daily.pnl)
+    }
+
+    /// 从快照恢复当日盈亏，不是今天的快照忽略；恢复后重新检查亏损上限
+    pub fn restore_daily_pnl(&self, day: NaiveDate, pnl: f64) {
+        if day != Utc::now().date_naive() {
+            return;
+        }
+        {
+            let mut daily = self.daily.lock().unwrap();
+            daily.day = day;
+            daily.pnl = pnl;
+        }
+        info!("恢复当日盈亏 {} {}", day, pnl);
+        self.record_pnl(0.0);
+    }
+
+    /// 检查下单意图，不通过时返回拒单原因并记录日志
+    pub fn check(&self, intent: &OrderIntent, account: &AccountState, orders: &OrderStore) -> Result<(), RiskReject> {
+        let result = self.evaluate(intent, account, orders);
+        if let Err(reject) = &result {
+            warn!("风控拒单 {} {} {} {:?} {}: {}", intent.inst_id, intent.side, intent.ord_type, intent.px, intent.sz, reject);
+        }
+        result
+    }
+
+    /// 检查已登记到订单存储的订单，挂单数不把它自己算在内
+    ///
+    /// 下单通道发送前对每个 order / batch-orders 消息调用，策略绕过 submit_order 直接发消息也会被检查
+    pub fn check_submitted(&self, order: &WsOrder, account: &AccountState, orders: &OrderStore) -> Result<(), RiskReject> {
+        let result = self.evaluate_excluding(&order.intent, Some(&order.cl_ord_id), account, orders);
+        if let Err(reject) = &result {
+            let intent = &order.intent;
+            warn!("风控拒单 {} {} {} {} {:?} {}: {}", order.cl_ord_id, intent.inst_id, intent.side, intent.ord_type, intent.px, intent.sz, reject);
+        }
+        result
+    }
+
+    fn evaluate(&self, intent: &OrderIntent, account: &AccountState, orders: &OrderStore) -> Result<(), RiskReject> {
+        self.evaluate_excluding(intent, None, account, orders)
+    }
+
+    fn evaluate_excluding(&self, intent: &OrderIntent, cl_ord_id: Option<&str>, account: &AccountState, orders: &OrderStore) -> Result<(), RiskReject> {
+        if self.is_killed() {
+            return Err(RiskReject::KillSwitch);
+        }
+        if self.is_paused(&intent.inst_id) {
+            return Err(RiskReject::TradingPaused { inst_id: intent.inst_id.clone() });
+        }
+        let limits = self.limits.read().unwrap();
+        let sz = str_to_f64(&intent.sz);
+        if let Some(max) = limits.max_order_size
+            && sz > max
+        {
+            return Err(RiskReject::MaxOrderSize { sz, max });
+        }
+        let open_orders = orders
+            .open_orders(None)
+            .into_iter()
+            .filter(|o| cl_ord_id.is_none_or(|id| id.is_empty() || o.cl_ord_id != id))
+            .collect::<Vec<_>>();
+        if !intent.reduce_only
+            && let Some(max) = limits.max_open_orders
+            && open_orders.len() >= max
+        {
+            return Err(RiskReject::MaxOpenOrders { count: open_orders.len(), max });
+        }
+        // 双向持仓按订单作用的那一边检查：posSide 与方向一致为开仓，相反为平仓
+        let (projected, reducing) = match intent.leg() {
+            leg @ ("long" | "short") => {
+                let position = account.position(&intent.inst_id, leg).map(|p| p.pos.abs()).unwrap_or(0.0);
+                let opening = (leg == "long") == (intent.side == Side::BUY);
+                if opening { (position + sz, false) } else { (position - sz, true) }
+            }
+            _ => {
+                let position = account.position(&intent.inst_id, "net").map(|p| p.pos).unwrap_or(0.0);
+                let projected = position + if intent.side == Side::SELL { -sz } else { sz };
+                // 单向持仓的减仓单只要不反向开仓就放行
+                (projected, intent.reduce_only || projected.abs() <= position.abs())
+            }
+        };
+        if !reducing
+            && let Some(max) = limits.max_position_of(&intent.inst_id)
+            && projected.abs() > max
+        {
+            return Err(RiskReject::MaxPosition { inst_id: intent.inst_id.clone(), projected, max });
+        }
+        let reference = self.reference_price(&intent.inst_id);
+        let px = intent.px.as_deref().map(str_to_f64);
+        if intent.ord_type != OrderType::MARKET
+            && let (Some(band_pct), Some(px)) = (limits.price_band_pct, px)
+        {
+            let Some(reference) = reference else {
+                return Err(RiskReject::NoReferencePrice { inst_id: intent.inst_id.clone() });
+            };
+            // 买单和买一比（没有时用标记价格），卖单和卖一比
+            let base = match intent.side.as_str() {
+                Side::BUY if reference.bid > 0.0 => reference.bid,
+                Side::SELL if reference.ask > 0.0 => reference.ask,
+                _ => reference.mark,
+            };
+            if base <= 0.0 {
+                return Err(RiskReject::NoReferencePrice { inst_id: intent.inst_id.clone() });
+            }
+            if (px - base).abs() / base > band_pct {
+                return Err(RiskReject::PriceBand { px, reference: base, band_pct });
+            }
+        }
+        if !reducing && let Some(max) = limits.max_gross_notional {
+            let mut gross = 0.0;
+            for p in account.positions().iter() {
+                let px = if p.mark_px > 0.0 { p.mark_px } else { p.avg_px };
+                gross += notional_usd(&p.inst_id, p.pos, px);
+            }
+            for o in open_orders.iter() {
+                let remaining = str_to_f64(&o.sz) - str_to_f64(&o.acc_fill_sz);
+                gross += notional_usd(&o.inst_id, remaining, str_to_f64(&o.px));
+            }
+            let order_px = px
+                .or(reference.map(|r| if r.mark > 0.0 { r.mark } else { (r.bid + r.ask) / 2.0 }))
+                .unwrap_or(0.0);
+            let projected = gross + notional_usd(&intent.inst_id, sz, order_px);
+            if projected > max {
+                return Err(RiskReject::MaxGrossNotional { projected, max });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod risk_test {
+    use super::*;
+    use crate::common::order_store::LocalOrder;
+
+    fn intent(side: &str, px: Option<&str>, sz: &str) -> OrderIntent {
+        OrderIntent {
+            inst_id: "TEST-USDT-SWAP".to_string(),
+            side: side.to_string(),
+            td_mode: "cross".to_string(),
+            ord_type: if px.is_some() { OrderType::LIMIT } else { OrderType::MARKET }.to_string(),
+            px: px.map(|p| p.to_string()),
+            sz: sz.to_string(),
+            reduce_only: false,
+            pos_side: "net".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_checks() {
+        let limits = RiskLimits {
+            default_max_position: Some(10.0),
+            max_order_size: Some(5.0),
+            max_daily_loss: Some(100.0),
+            ..RiskLimits::default()
+        };
+        let gate = RiskGate::new(limits);
+        let account = AccountState::new();
+        let orders = OrderStore::new();
+        assert_eq!(
+            gate.check(&intent(Side::BUY, Some("100"), "1"), &account, &orders),
+            Err(RiskReject::NoReferencePrice { inst_id: "TEST-USDT-SWAP".to_string() })
+        );
+        gate.update_bbo("TEST-USDT-SWAP", 100.0, 101.0);
+        assert_eq!(gate.check(&intent(Side::BUY, Some("100"), "1"), &account, &orders), Ok(()));
+        assert!(matches!(gate.check(&intent(Side::BUY, Some("120"), "1"), &account, &orders), Err(RiskReject::PriceBand { .. })));
+        assert!(matches!(gate.check(&intent(Side::BUY, None, "6"), &account, &orders), Err(RiskReject::MaxOrderSize { .. })));
+
+        gate.record_pnl(-60.0);
+        assert!(!gate.is_killed());
+        gate.record_pnl(-50.0);
+        assert!(gate.is_killed());
+        assert_eq!(gate.check(&intent(Side::SELL, None, "1"), &account, &orders), Err(RiskReject::KillSwitch));
+    }
+
+    #[test]
+    fn test_hedge_mode_position() {
+        use crate::common::account::PositionData;
+        let gate = RiskGate::new(RiskLimits { default_max_position: Some(12.0), max_gross_notional: Some(2050.0), ..RiskLimits::default() });
+        let account = AccountState::new();
+        let orders = OrderStore::new();
+        let leg = |pos_side: &str| PositionData {
+            inst_id: "TEST-USDT-SWAP".to_string(),
+            pos_side: pos_side.to_string(),
+            pos: "10".to_string(),
+            avg_px: "100".to_string(),
+            u_time: "1".to_string(),
+            ..PositionData::default()
+        };
+        account.on_positions(&[leg("long"), leg("short")]);
+        gate.update_bbo("TEST-USDT-SWAP", 100.0, 101.0);
+        let hedge = |side: &str, pos_side: &str, sz: &str| OrderIntent { pos_side: pos_side.to_string(), ..intent(side, None, sz) };
+        // 净持仓为 0，但多头一边开到 15 超限
+        assert!(matches!(gate.check(&hedge(Side::BUY, "long", "5"), &account, &orders), Err(RiskReject::MaxPosition { .. })));
+        // 卖出开空是新增敞口，要检查持仓和总名义价值
+        assert!(matches!(gate.check(&hedge(Side::SELL, "short", "1"), &account, &orders), Err(RiskReject::MaxGrossNotional { .. })));
+        // 平多不受限制
+        assert_eq!(gate.check(&hedge(Side::SELL, "long", "5"), &account, &orders), Ok(()));
+    }
+
+    #[test]
+    fn test_ws_orders() {
+        assert!(parse_ws_orders(r#"{"op":"subscribe","args":[{"channel":"orders"}]}"#).is_none());
+        let msg = r#"{"id":"1","op":"batch-orders","args":[
+            {"instId":"TEST-USDT-SWAP","side":"buy","tdMode":"cross","ordType":"market","sz":"1","posSide":"net","clOrdId":"a"},
+            {"instId":"TEST-USDT-SWAP","side":"sell","tdMode":"cross","ordType":"limit","px":"100","sz":"2","posSide":"long","clOrdId":"b"}]}"#;
+        let orders = parse_ws_orders(msg).unwrap().unwrap();
+        assert_eq!(orders.len(), 2);
+        assert!(!orders[0].intent.reduce_only && orders[0].intent.px.is_none());
+        assert!(orders[1].intent.reduce_only);
+        assert!(matches!(parse_ws_orders(r#"{"op":"order","args":[{"sz":1}]}"#), Some(Err(RiskReject::Malformed { .. }))));
+
+        // 已登记的订单检查时不把自己算进挂单数
+        let gate = RiskGate::new(RiskLimits { max_open_orders: Some(1), ..RiskLimits::default() });
+        let account = AccountState::new();
+        let store = OrderStore::new();
+        store.track_submit("a", LocalOrder::pending("a", "TEST-USDT-SWAP", Side::BUY, OrderType::MARKET, None, "1"));
+        assert_eq!(gate.check_submitted(&orders[0], &account, &store), Ok(()));
+        let other = WsOrder { cl_ord_id: "c".to_string(), intent: orders[0].intent.clone() };
+        assert!(matches!(gate.check_submitted(&other, &account, &store), Err(RiskReject::MaxOpenOrders { .. })));
+    }
+
+    #[test]
+    fn test_pause_reasons() {
+        let gate = RiskGate::new(RiskLimits::default());
+        let inst_id = "TEST-USDT-SWAP";
+        gate.pause(inst_id, PauseReason::Maintenance, "");
+        gate.pause(inst_id, PauseReason::State, "suspend");
+        // 维护结束但产品仍处于 suspend，继续暂停
+        gate.resume(inst_id, PauseReason::Maintenance);
+        assert!(gate.is_paused(inst_id));
+        assert_eq!(gate.pause_reasons(inst_id), HashSet::from([PauseReason::State]));
+        // 没有的来源不影响
+        gate.resume(inst_id, PauseReason::BookRebuild);
+        assert!(gate.is_paused(inst_id));
+        gate.resume(inst_id, PauseReason::State);
+        assert!(!gate.is_paused(inst_id));
+        assert!(gate.pause_reasons(inst_id).is_empty());
+    }
+
+    #[test]
+    fn test_record_fill() {
+        let gate = RiskGate::new(RiskLimits::default());
+        let mut fill = Fill {
+            inst_id: "TEST-USDT-SWAP".to_string(),
+            trade_id: "1".to_string(),
+            ord_id: "1".to_string(),
+            cl_ord_id: String::new(),
+            side: Side::SELL.to_string(),
+            pos_side: "net".to_string(),
+            px: "100".to_string(),
+            sz: "1".to_string(),
+            liquidity: crate::common::fills::Liquidity::Taker,
+            fee: "-0.5".to_string(),
+            fee_ccy: "USDT".to_string(),
+            ts: Utc::now().timestamp_millis() as u64,
+            fill_pnl: String::new(),
+        };
+        gate.record_fill(&fill, -10.0);
+        assert_eq!(gate.daily_pnl().1, -10.5);
+
+        // 币本位：盈亏和手续费都是币，按标记价格换算
+        let mut inverse = fill.clone();
+        inverse.inst_id = "TEST-USD-SWAP".to_string();
+        inverse.trade_id = "2".to_string();
+        inverse.fee = "-0.001".to_string();
+        inverse.fee_ccy = "TEST".to_string();
+        gate.record_fill(&inverse, -0.01);
+        assert!((gate.daily_pnl().1 - (-10.5 - 1.1)).abs() < 1e-9);
+        gate.update_mark("TEST-USD-SWAP", 200.0);
+        gate.record_fill(&inverse, -0.01);
+        assert!((gate.daily_pnl().1 - (-11.6 - 2.2)).abs() < 1e-9);
+
+        // 补拉到的前一天成交不计入当日亏损
+        let before = gate.daily_pnl().1;
+        fill.ts -= 86_400_000;
+        gate.record_fill(&fill, -10.0);
+        assert_eq!(gate.daily_pnl().1, before);
+
+        // 重算覆盖快照恢复的值，前一天的成交不计入
+        let mut today = fill.clone();
+        today.ts += 86_400_000;
+        gate.restore_daily_pnl(Utc::now().date_naive(), -1.0);
+        gate.rebuild_daily_pnl(&[(fill, -10.0), (today.clone(), -20.0), (today, 5.0)]);
+        assert_eq!(gate.daily_pnl().1, -16.0);
+    }
+
+    #[test]
+    fn test_load_limits() {
+        let path = std::env::temp_dir().join(format!("okx_risk_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"max_position":{"BTC-USDT-SWAP":3},"max_open_orders":20}"#).unwrap();
+        let limits = RiskLimits::load(&path).unwrap();
+        assert_eq!(limits.max_position_of("BTC-USDT-SWAP"), Some(3.0));
+        assert_eq!(limits.max_open_orders, Some(20));
+        assert_eq!(limits.price_band_pct, Some(0.05));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use chrono::{NaiveDate, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use sonic_rs::{Deserialize, JsonValueTrait, Serialize, Value};
+use crate::common::cl_ord_id::ClOrdIdGenerator;
+use crate::common::config::config;
+use crate::common::order_store::{LocalOrder, OrderStore};
+use crate::common::risk::RiskGate;
+use crate::common::utils::write_atomic;
+
+pub const SNAPSHOT_PATH: &str = "data/snapshot.json";
+/// 快照格式版本，字段有不兼容的变化时递增
+pub const SNAPSHOT_VERSION: u32 = 1;
+/// 定时保存快照的间隔（秒）
+pub const SNAPSHOT_INTERVAL_SECS: u64 = 30;
+
+/// 策略的参数、状态保存和恢复，策略实现后注册到 STRATEGY_STATE
+pub trait StrategyState: Send + Sync {
+    /// 注册时最先调用，params 为配置中的 strategy.params，返回错误时不注册
+    fn configure(&self, _params: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+    /// 保存快照时调用
+    fn save_state(&self) -> Value;
+    /// 注册时用快照中的状态恢复，没有快照时不调用
+    fn restore_state(&self, state: Value);
+}
+
+/// 当前策略的状态入口，快照通过它读写策略状态
+///
+/// 策略注册前从快照恢复的状态先暂存，注册时再交给策略；
+/// 暂存期间保存快照会原样写回，避免策略启动前的快照把状态清空。
+pub struct StrategySlot {
+    strategy: Mutex<Option<Arc<dyn StrategyState>>>,
+    restored: Mutex<Option<Value>>,
+}
+
+impl StrategySlot {
+    pub fn new() -> StrategySlot {
+        StrategySlot { strategy: Mutex::new(None), restored: Mutex::new(None) }
+    }
+
+    /// 注册策略：先传入配置的参数，有暂存的快照状态时再恢复
+    pub fn register(&self, strategy: Arc<dyn StrategyState>) -> Result<(), Box<dyn std::error::Error>> {
+        strategy.configure(&config().strategy.params)?;
+        if let Some(state) = self.restored.lock().unwrap().take() {
+            strategy.restore_state(state);
+        }
+        *self.strategy.lock().unwrap() = Some(strategy);
+        Ok(())
+    }
+
+    /// 快照恢复出的策略状态，策略已注册时直接恢复
+    pub fn restore(&self, state: Value) {
+        if state.is_null() {
+            return;
+        }
+        let strategy = self.strategy.lock().unwrap().clone();
+        match strategy {
+            Some(strategy) => strategy.restore_state(state),
+            None => *self.restored.lock().unwrap() = Some(state),
+        }
+    }
+
+    /// 当前策略状态，策略未注册时返回暂存的快照状态
+    pub fn state(&self) -> Value {
+        let strategy = self.strategy.lock().unwrap().clone();
+        match strategy {
+            Some(strategy) => strategy.save_state(),
+            None => self.restored.lock().unwrap().clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for StrategySlot {
+    fn default() -> Self {
+        StrategySlot::new()
+    }
+}
+
+pub static STRATEGY_STATE: Lazy<StrategySlot> = Lazy::new(StrategySlot::new);
+
+/// 风控计数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskSnapshot {
+    /// UTC 日期 yyyy-mm-dd
+    pub day: String,
+    pub daily_pnl: f64,
+    pub kill_switch: bool,
+}
+
+/// 进程状态快照，重启后用于恢复当日盈亏、订单号序号和未终结订单
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub version: u32,
+    /// 保存时间（毫秒）
+    pub ts: u64,
+    pub cl_ord_id_seq: u64,
+    pub risk: RiskSnapshot,
+    pub open_orders: Vec<LocalOrder>,
+    /// 策略自定义状态，由注册的 StrategyState 序列化
+    pub strategy: Value,
+}
+
+impl Snapshot {
+    pub fn capture(orders: &OrderStore, risk: &RiskGate, ids: &ClOrdIdGenerator, strategy: Value) -> Snapshot {
+        let (day, daily_pnl) = risk.daily_pnl();
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            ts: Utc::now().timestamp_millis() as u64,
+            cl_ord_id_seq: ids.seq(),
+            risk: RiskSnapshot {
+                day: day.format("%Y-%m-%d").to_string(),
+                daily_pnl,
+                kill_switch: risk.is_killed(),
+            },
+            open_orders: orders.open_orders(None),
+            strategy,
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        write_atomic(path, sonic_rs::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// 文件不存在时返回 None，版本比当前程序新时返回错误
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Snapshot>, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let snapshot = sonic_rs::from_slice::<Snapshot>(&std::fs::read(path)?)?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(format!("快照版本 {} 高于程序支持的 {}", snapshot.version, SNAPSHOT_VERSION).into());
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// 恢复到各个组件，返回策略状态
+    pub fn restore(self, orders: &OrderStore, risk: &RiskGate, ids: &ClOrdIdGenerator) -> Value {
+        ids.restore_seq(self.cl_ord_id_seq);
+        match NaiveDate::parse_from_str(&self.risk.day, "%Y-%m-%d") {
+            Ok(day) => risk.restore_daily_pnl(day, self.risk.daily_pnl),
+            Err(e) => warn!("快照日期 {} 无效 {}", self.risk.day, e),
+        }
+        if self.risk.kill_switch {
+            risk.trip_kill_switch("快照中处于停止交易状态");
+        }
+        info!("从快照恢复 {} 个未终结订单，序号 {}", self.open_orders.len(), self.cl_ord_id_seq);
+        orders.restore(self.open_orders);
+        self.strategy
+    }
+}
+
+/// 保存快照，失败只记录日志
+pub fn save_snapshot<P: AsRef<Path>>(path: P, orders: &OrderStore, risk: &RiskGate, ids: &ClOrdIdGenerator, strategy: Value) {
+    if let Err(e) = Snapshot::capture(orders, risk, ids, strategy).save(path) {
+        warn!("保存快照失败 {}", e);
+    }
+}
+
+#[cfg(test)]
+mod snapshot_test {
+    use sonic_rs::json;
+    use crate::common::risk::RiskLimits;
+    use super::*;
+
+    #[test]
+    fn test_save_and_restore() {
+        let path = std::env::temp_dir().join(format!("okx_snapshot_test_{}.json", std::process::id()));
+        let orders = OrderStore::new();
+        orders.track_submit("1", LocalOrder::pending("ox1", "BTC-USDT-SWAP", "buy", "limit", Some("50000"), "1"));
+        let risk = RiskGate::new(RiskLimits::default());
+        risk.record_pnl(-12.5);
+        let ids = ClOrdIdGenerator::with_session("ox", 1_764_000_000_000);
+        ids.next("s1").unwrap();
+        ids.next("s1").unwrap();
+        Snapshot::capture(&orders, &risk, &ids, json!({"grid": 3})).save(&path).unwrap();
+
+        let snapshot = Snapshot::load(&path).unwrap().unwrap();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        let orders2 = OrderStore::new();
+        let risk2 = RiskGate::new(RiskLimits::default());
+        let ids2 = ClOrdIdGenerator::with_session("ox", 1_764_000_000_001);
+        let strategy = snapshot.restore(&orders2, &risk2, &ids2);
+        assert_eq!(strategy, json!({"grid": 3}));
+        assert_eq!(risk2.daily_pnl().1, -12.5);
+        assert_eq!(ids2.seq(), 2);
+        assert!(orders2.get_by_cl_ord_id("ox1").is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    struct Grid(Mutex<Value>);
+
+    impl StrategyState for Grid {
+        fn configure(&self, params: &Value) -> Result<(), Box<dyn std::error::Error>> {
+            if params.is_object() { Ok(()) } else { Err("params 必须是表".into()) }
+        }
+
+        fn save_state(&self) -> Value {
+            self.0.lock().unwrap().clone()
+        }
+
+        fn restore_state(&self, state: Value) {
+            *self.0.lock().unwrap() = state;
+        }
+    }
+
+    #[test]
+    fn test_strategy_slot() {
+        let slot = StrategySlot::new();
+        assert!(slot.state().is_null());
+        // 策略注册前恢复的状态暂存，保存快照时原样写回
+        slot.restore(json!({"grid": 3}));
+        assert_eq!(slot.state(), json!({"grid": 3}));
+        let grid = Arc::new(Grid(Mutex::new(Value::new())));
+        slot.register(grid.clone()).unwrap();
+        assert_eq!(*grid.0.lock().unwrap(), json!({"grid": 3}));
+        *grid.0.lock().unwrap() = json!({"grid": 4});
+        assert_eq!(slot.state(), json!({"grid": 4}));
+    }
+}
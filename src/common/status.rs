@@ -0,0 +1,158 @@
+use dashmap::DashMap;
+use log::{info, warn};
+use sonic_rs::{Deserialize, Serialize};
+
+/// status 频道推送的系统维护信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StatusData {
+    pub title: String,
+    /// scheduled / ongoing / pre_open / completed / canceled
+    pub state: String,
+    /// 计划开始 / 结束时间（毫秒）
+    pub begin: String,
+    pub end: String,
+    pub pre_open_begin: String,
+    pub href: String,
+    /// 受影响的服务，见 affects_trading
+    pub service_type: String,
+    /// unified: 交易账户
+    pub system: String,
+    pub sche_desc: String,
+    /// 1: 计划维护 2: 临时维护 3: 系统故障
+    pub maint_type: String,
+    /// 1: 实盘 2: 模拟盘
+    pub env: String,
+    pub ts: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceState {
+    Scheduled,
+    Ongoing,
+    PreOpen,
+    Completed,
+    Canceled,
+    Unknown,
+}
+
+impl MaintenanceState {
+    pub fn from_okx(state: &str) -> MaintenanceState {
+        match state {
+            "scheduled" => MaintenanceState::Scheduled,
+            "ongoing" => MaintenanceState::Ongoing,
+            "pre_open" => MaintenanceState::PreOpen,
+            "completed" => MaintenanceState::Completed,
+            "canceled" => MaintenanceState::Canceled,
+            _ => MaintenanceState::Unknown,
+        }
+    }
+
+    /// 维护进行中（含开盘前的集合竞价阶段），此时不能正常交易
+    pub fn is_active(&self) -> bool {
+        matches!(self, MaintenanceState::Ongoing | MaintenanceState::PreOpen)
+    }
+
+    /// 已结束或取消
+    pub fn is_finished(&self) -> bool {
+        matches!(self, MaintenanceState::Completed | MaintenanceState::Canceled)
+    }
+}
+
+/// 影响下单的服务类型：0 WebSocket、5 交易服务、8 交易服务（按账户分批）、9 交易服务（按产品分批）
+/// status 推送不带具体产品，交易相关的维护视为影响所有正在交易的产品
+pub fn affects_trading(service_type: &str) -> bool {
+    matches!(service_type, "0" | "5" | "8" | "9")
+}
+
+/// 维护事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceEvent {
+    /// 同一次维护的标识：title + begin
+    pub key: String,
+    pub title: String,
+    pub state: MaintenanceState,
+    pub begin_ms: u64,
+    pub end_ms: u64,
+    pub service_type: String,
+    pub affects_trading: bool,
+}
+
+impl From<&StatusData> for MaintenanceEvent {
+    fn from(data: &StatusData) -> Self {
+        MaintenanceEvent {
+            key: format!("{}-{}", data.title, data.begin),
+            title: data.title.clone(),
+            state: MaintenanceState::from_okx(&data.state),
+            begin_ms: data.begin.parse().unwrap_or(0),
+            end_ms: data.end.parse().unwrap_or(0),
+            service_type: data.service_type.clone(),
+            affects_trading: affects_trading(&data.service_type),
+        }
+    }
+}
+
+/// 当前未结束的维护窗口
+#[derive(Default)]
+pub struct SystemStatus {
+    windows: DashMap<String, MaintenanceEvent>,
+}
+
+impl SystemStatus {
+    pub fn new() -> SystemStatus {
+        SystemStatus::default()
+    }
+
+    /// 处理 status 频道推送，返回维护事件；已结束的窗口移除
+    pub fn on_status(&self, data: &[StatusData]) -> Vec<MaintenanceEvent> {
+        let mut events = Vec::with_capacity(data.len());
+        for d in data.iter() {
+            let event = MaintenanceEvent::from(d);
+            if event.state.is_finished() {
+                self.windows.remove(&event.key);
+                info!("维护结束 {} {:?}", event.title, event.state);
+            } else {
+                if event.state.is_active() && event.affects_trading {
+                    warn!("维护进行中 {} 服务 {} 至 {}", event.title, event.service_type, event.end_ms);
+                } else {
+                    info!("维护计划 {} {:?} {} - {}", event.title, event.state, event.begin_ms, event.end_ms);
+                }
+                self.windows.insert(event.key.clone(), event.clone());
+            }
+            events.push(event);
+        }
+        events
+    }
+
+    /// 是否有影响交易的维护正在进行
+    pub fn trading_halted(&self) -> bool {
+        self.windows.iter().any(|w| w.state.is_active() && w.affects_trading)
+    }
+
+    /// 未结束的维护窗口（含计划中的）
+    pub fn windows(&self) -> Vec<MaintenanceEvent> {
+        self.windows.iter().map(|w| w.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod status_test {
+    use sonic_rs::from_str;
+    use crate::common::ws_api::ChannelData;
+    use super::*;
+
+    #[test]
+    fn test_maintenance_window() {
+        let status = SystemStatus::new();
+        let push = from_str::<ChannelData<StatusData>>(r#"{"arg":{"channel":"status"},"data":[{"begin":"1672823400000","end":"1672825980000","href":"","preOpenBegin":"","scheDesc":"","serviceType":"5","state":"ongoing","system":"unified","maintType":"1","env":"1","title":"Trading account upgrade","ts":"1672826038470"}]}"#).unwrap();
+        let events = status.on_status(&push.data);
+        assert_eq!(events[0].state, MaintenanceState::Ongoing);
+        assert!(status.trading_halted());
+
+        let mut done = push.data[0].clone();
+        done.state = "completed".to_string();
+        status.on_status(&[done]);
+        assert!(!status.trading_halted());
+        assert!(status.windows().is_empty());
+    }
+}
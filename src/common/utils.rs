@@ -1,25 +1,27 @@
-use crate::common::config::{
-    OK_SIMULATION_ACCESS_PASSPHRASE, OKX_SIMULATION_API_KEY, OKX_SIMULATION_SECRET_KEY,
-    REST_SIMULATION_URL, REST_URL,
-};
-use crate::common::rest_api::SwapInstrument;
+use crate::common::config::{config, get_rest_url};
+use crate::common::credentials::{CredentialError, Credentials, CREDENTIAL_STORE};
+use crate::common::clock::SERVER_CLOCK;
+use crate::common::rate_limit::throttle_rest;
+use crate::common::retry::{post_is_retryable, send_with_retry, RestError, RetryPolicy};
+use crate::common::instrument::INSTRUMENT_REGISTRY;
+use crate::common::proxy::PROXY;
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
-use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use chrono::{FixedOffset, TimeZone, Utc};
 use env_logger::Builder;
 use hmac::{Hmac, Mac};
-use log::{LevelFilter, info};
+use log::info;
 use once_cell::sync::Lazy;
 use reqwest::header::HeaderMap;
 use reqwest::{Client, Response};
 use sha2::Sha256;
 use sonic_rs::{JsonValueTrait, Value, json, to_string};
-use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Lines, Write};
 use std::ops::Add;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message::Text;
 use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
@@ -29,55 +31,35 @@ static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
     let mut headers = HeaderMap::new();
     headers.insert("Accept", "application/json".parse().unwrap());
     // 可以自定义配置，例如设置超时、User-Agent 等
-    Client::builder()
-        .timeout(std::time::Duration::from_secs(10)) // 10 秒超时
+    let mut builder = Client::builder()
+        .timeout(std::time::Duration::from_secs(10)); // 10 秒超时
+    if let Some(proxy) = PROXY.as_ref() {
+        builder = builder.proxy(proxy.reqwest_proxy().expect("代理配置错误"));
+    }
+    builder
         .build()
         .expect("Failed to build HTTP client")
 });
 
-pub static INSTRUMENTS_MAP: Lazy<HashMap<String, SwapInstrument>> = Lazy::new(|| {
-    let vec = sonic_rs::from_reader::<BufReader<File>, Vec<SwapInstrument>>(BufReader::new(
-        File::open("data/instruments.json").unwrap(),
-    ))
-    .unwrap();
-    vec.into_iter()
-        .filter(|instrument| instrument.settle_ccy.eq("USDT"))
-        .map(|instrument| (instrument.inst_id.clone(), instrument))
-        .collect::<HashMap<String, SwapInstrument>>()
-});
-
-pub fn get_sz(inst_id: &str) -> Option<&String> {
-    if let Some(instrument) = get_swap_instrument(inst_id) {
-        return Some(&instrument.tick_sz);
-    };
-    None
-}
-pub fn get_min_sz(inst_id:&str) ->Option<&String>{
-    if let Some(instrument) = get_swap_instrument(inst_id) {
-        return Some(&instrument.min_sz);
-    };
-    None
-
+/// 以下按产品取字段的函数都查 INSTRUMENT_REGISTRY，覆盖所有结算币种和产品类型，未知产品返回 None
+pub fn get_sz(inst_id: &str) -> Option<String> {
+    INSTRUMENT_REGISTRY.get(inst_id).map(|instrument| instrument.tick_sz.clone())
 }
-pub fn get_swap_instrument(inst_id: &str) -> Option<&SwapInstrument> {
-    INSTRUMENTS_MAP.get(inst_id)
+pub fn get_min_sz(inst_id:&str) ->Option<String>{
+    INSTRUMENT_REGISTRY.get(inst_id).map(|instrument| instrument.min_sz.clone())
 }
-
-pub fn get_quantity_sz(inst_id: &str,quantity:&str)->String{
-    let min_sz = get_min_sz(inst_id).unwrap();
-    let quantity_to = price_to_tick_int_str(quantity, min_sz);
-    let ct_val = get_ct_val(inst_id);
-    let to_tick_int_str = price_to_tick_int_str("1", ct_val);
-    
-    tick_int_to_price_str(quantity_to*to_tick_int_str,get_min_sz(inst_id).unwrap())
+/// 把数量换算成下单张数，正向合约 quantity 单位为币，反向合约为 USD
+pub fn get_quantity_sz(inst_id: &str,quantity:&str)->Option<String>{
+    let quantity = quantity.parse::<f64>().ok()?;
+    INSTRUMENT_REGISTRY.get(inst_id)?.quantity_to_sz(quantity)
 }
 #[cfg(test)]
 mod utils_test {
     use super::*;
     #[test]
     fn test_get_client() {
-        println!("{}", INSTRUMENTS_MAP.len());
-        // for key in INSTRUMENTS_MAP.keys() {
+        println!("{}", INSTRUMENT_REGISTRY.len());
+        // for key in INSTRUMENT_REGISTRY.all() {
         //     println!("{}", key);
         // }
     }
@@ -87,14 +69,28 @@ pub fn get_client() -> &'static Client {
     &HTTP_CLIENT
 }
 
+/// 从查询参数中取出 instId，用于按产品限速
+fn param_inst_id<'a>(params: Option<&[(&str, &'a str)]>) -> Option<&'a str> {
+    params?.iter().find(|(k, _)| *k == "instId").map(|(_, v)| *v)
+}
+
 pub struct HttpClient;
 impl HttpClient {
+    /// GET 失败时按 RetryPolicy::DEFAULT 重试
     pub async fn get(
         path: &str,
         params: Option<&[(&str, &str)]>,
+    ) -> Result<Response, RestError> {
+        send_with_retry(path, RetryPolicy::DEFAULT, || Self::get_once(path, params)).await
+    }
+
+    async fn get_once(
+        path: &str,
+        params: Option<&[(&str, &str)]>,
     ) -> Result<Response, reqwest::Error> {
+        throttle_rest(path, param_inst_id(params)).await;
         let client = get_client();
-        let url = format!("{REST_URL}{path}");
+        let url = format!("{}{path}", get_rest_url());
         let request_builder = client.get(url.as_str());
         match params {
             None => Ok(request_builder.send().await?),
@@ -102,76 +98,117 @@ impl HttpClient {
         }
     }
 }
-pub struct HttpClientSimulation;
-impl HttpClientSimulation {
+/// 使用指定凭证的私有接口客户端，一个进程可以为多个账户各建一个
+#[derive(Debug, Clone)]
+pub struct PrivateClient {
+    credentials: Arc<Credentials>,
+}
+
+impl PrivateClient {
+    /// 按 profile 从 CREDENTIAL_STORE 取凭证
+    pub fn new(profile: &str) -> Result<PrivateClient, CredentialError> {
+        Ok(PrivateClient { credentials: CREDENTIAL_STORE.get(profile)? })
+    }
+
+    pub fn from_credentials(credentials: Arc<Credentials>) -> PrivateClient {
+        PrivateClient { credentials }
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// GET 失败时按 RetryPolicy::DEFAULT 重试，每次重试重新签名
     pub async fn get(
+        &self,
+        path: &str,
+        params: Option<&[(&str, &str)]>,
+    ) -> Result<Response, RestError> {
+        send_with_retry(path, RetryPolicy::DEFAULT, || self.get_once(path, params)).await
+    }
+
+    /// POST 只有幂等接口才重试，见 post_is_retryable；只读凭证不能 POST
+    pub async fn post(&self, path: &str, json: Value) -> Result<Response, RestError> {
+        if self.credentials.read_only {
+            return Err(CredentialError::ReadOnly(self.credentials.profile.clone()).into());
+        }
+        let policy = if post_is_retryable(path) { RetryPolicy::DEFAULT } else { RetryPolicy::NONE };
+        send_with_retry(path, policy, || self.post_once(path, &json)).await
+    }
+
+    /// 签名以外的鉴权请求头，模拟盘额外带 x-simulated-trading
+    fn with_auth_headers(&self, request_builder: reqwest::RequestBuilder, now_iso: &str) -> reqwest::RequestBuilder {
+        let request_builder = request_builder
+            .header("OK-ACCESS-KEY", self.credentials.api_key.as_str())
+            .header("OK-ACCESS-TIMESTAMP", now_iso)
+            .header("OK-ACCESS-PASSPHRASE", self.credentials.passphrase.expose());
+        if config().is_demo() {
+            return request_builder.header("x-simulated-trading", "1");
+        }
+        request_builder
+    }
+
+    async fn get_once(
+        &self,
         path: &str,
         params: Option<&[(&str, &str)]>,
     ) -> Result<Response, reqwest::Error> {
+        throttle_rest(path, param_inst_id(params)).await;
         let now_iso = utc_now_iso();
         let client = get_client();
-        let url = format!("{REST_SIMULATION_URL}{path}");
+        let url = format!("{}{path}", get_rest_url());
         let request_builder = client.get(url.as_str());
-        let mut request_builder = request_builder
-            .header("OK-ACCESS-KEY", OKX_SIMULATION_API_KEY.as_str())
-            .header("OK-ACCESS-TIMESTAMP", &now_iso)
-            .header(
-                "OK-ACCESS-PASSPHRASE",
-                OK_SIMULATION_ACCESS_PASSPHRASE.as_str(),
-            )
-            .header("x-simulated-trading", "1");
+        let mut request_builder = self.with_auth_headers(request_builder, &now_iso);
         match params {
             None => {
-                let sign = sign(
-                    &now_iso,
-                    "GET",
-                    path,
-                    "",
-                    OKX_SIMULATION_SECRET_KEY.as_str(),
-                );
+                let sign = self.credentials.sign(&now_iso, "GET", path, "");
                 request_builder = request_builder.header("OK-ACCESS-SIGN", sign);
-                Ok(request_builder.send().await?)
+                request_builder.send().await
             }
             Some(params) => {
                 request_builder = request_builder.query(params);
                 let path_and_query = request_builder.try_clone().unwrap().build()?;
                 let query = path_and_query.url().query().unwrap();
                 let path_and_query = format!("{}?{}", path, query);
-                let sign = sign(
-                    &now_iso,
-                    "GET",
-                    path_and_query.as_ref(),
-                    "",
-                    OKX_SIMULATION_SECRET_KEY.as_str(),
-                );
+                let sign = self.credentials.sign(&now_iso, "GET", path_and_query.as_ref(), "");
                 request_builder = request_builder.header("OK-ACCESS-SIGN", sign);
-                Ok(request_builder.send().await?)
+                request_builder.send().await
             }
         }
     }
-    pub async fn post(path: &str, json: Value) -> Result<Response, reqwest::Error> {
+
+    async fn post_once(&self, path: &str, json: &Value) -> Result<Response, reqwest::Error> {
+        throttle_rest(path, json.get("instId").and_then(|v| v.as_str())).await;
         let now_iso = utc_now_iso();
         let client = get_client();
-        let url = format!("{REST_SIMULATION_URL}{path}");
+        let url = format!("{}{path}", get_rest_url());
         let request_builder = client.post(url.as_str());
-        let mut request_builder = request_builder
-            .header("OK-ACCESS-KEY", OKX_SIMULATION_API_KEY.as_str())
-            .header("OK-ACCESS-TIMESTAMP", &now_iso)
-            .header(
-                "OK-ACCESS-PASSPHRASE",
-                OK_SIMULATION_ACCESS_PASSPHRASE.as_str(),
-            )
-            .header("x-simulated-trading", "1");
-        request_builder = request_builder.json(&json);
-        let sign = sign(
-            &now_iso,
-            "POST",
-            path,
-            to_string(&json).unwrap().as_str(),
-            OKX_SIMULATION_SECRET_KEY.as_str(),
-        );
+        let mut request_builder = self.with_auth_headers(request_builder, &now_iso);
+        // 交易类请求可带有效期，超时未被处理的请求交易所直接丢弃
+        if path.starts_with("/api/v5/trade/")
+            && let Some(exp_time) = SERVER_CLOCK.exp_time()
+        {
+            request_builder = request_builder.header("expTime", exp_time);
+        }
+        request_builder = request_builder.json(json);
+        let sign = self.credentials.sign(&now_iso, "POST", path, to_string(json).unwrap().as_str());
         request_builder = request_builder.header("OK-ACCESS-SIGN", sign);
-        Ok(request_builder.send().await?)
+        request_builder.send().await
+    }
+}
+
+/// 使用默认凭证（credentials.profile）的私有接口
+pub struct HttpClientSimulation;
+impl HttpClientSimulation {
+    pub async fn get(
+        path: &str,
+        params: Option<&[(&str, &str)]>,
+    ) -> Result<Response, RestError> {
+        PrivateClient::new(&config().credentials.profile)?.get(path, params).await
+    }
+
+    pub async fn post(path: &str, json: Value) -> Result<Response, RestError> {
+        PrivateClient::new(&config().credentials.profile)?.post(path, json).await
     }
 }
 
@@ -179,27 +216,29 @@ pub fn send_str(value: &str) -> Message {
     Text(Utf8Bytes::from(value))
 }
 
-pub fn get_ct_val(inst_id: &str)->&str{
-    &get_swap_instrument(inst_id).unwrap().ct_val
+pub fn get_ct_val(inst_id: &str)->Option<String>{
+    INSTRUMENT_REGISTRY.get(inst_id).map(|instrument| instrument.ct_val.clone())
 }
 
+/// 日志级别和时区取自配置，需在 load_config 之后调用
 pub fn log_init() {
-    let offset = FixedOffset::east_opt(8 * 3600).unwrap(); // 定义 UTC+8 偏移变量
+    let log = &config().log;
+    let offset = FixedOffset::east_opt(log.utc_offset_hours * 3600).unwrap(); // 定义时区偏移变量
 
     Builder::new()
         .format(move |buf, record| {
             // move 闭包捕获 offset
             let utc_now = Utc::now(); // 每次日志时获取当前 UTC 时间
-            let local_now = offset.from_utc_datetime(&utc_now.naive_utc()); // 应用 +8 偏移
+            let local_now = offset.from_utc_datetime(&utc_now.naive_utc()); // 应用时区偏移
             writeln!(
                 buf,
                 "[{}] {}: {}",
-                local_now.format("%Y-%m-%d %H:%M:%S%.3f"), // 格式化：2025-11-16 15:23:00.123 (UTC+8)
+                local_now.format("%Y-%m-%d %H:%M:%S%.3f"), // 格式化：2025-11-16 15:23:00.123
                 record.level(),
                 record.args()
             )
         })
-        .filter(None, LevelFilter::Info)
+        .filter(None, log.level_filter())
         .init();
 }
 
@@ -245,6 +284,11 @@ pub fn price_to_tick_int_str(price: &str, tick_size: &str) -> u64 {
     result
 }
 
+/// OKX 的数值字段都是字符串，空字符串或解析失败按 0 处理
+pub fn str_to_f64(value: &str) -> f64 {
+    value.parse::<f64>().unwrap_or(0.0)
+}
+
 pub fn tick_int_to_price_str(tick_int: u64, tick_size: &str) -> String {
     // 如果 tick_size 没有小数点，直接返回整数字符串
     if !tick_size.contains(".") {
@@ -278,24 +322,35 @@ mod test_p {
     fn test_price_to_tick_int_str() {
         let inst_id = "BTC-USDT-SWAP";
         let min_sz = get_min_sz(inst_id).unwrap();
-        let int_str = price_to_tick_int_str("0", min_sz);
+        let int_str = price_to_tick_int_str("0", &min_sz);
         println!("{}", int_str);
     }
     #[test]
     fn test_tick_int_to_price_str() {
         let inst_id = "BTC-USDT-SWAP";
         let min_sz = get_min_sz(inst_id).unwrap();
-        let price_str = tick_int_to_price_str(7371, min_sz);
+        let price_str = tick_int_to_price_str(7371, &min_sz);
         println!("{}", price_str);
     }
+
+    #[test]
+    fn test_non_usdt_helpers() {
+        for inst_id in ["BTC-USD-SWAP", "BTC-USDC-SWAP"] {
+            assert!(get_sz(inst_id).is_some(), "{}", inst_id);
+            assert!(get_min_sz(inst_id).is_some(), "{}", inst_id);
+            assert!(get_ct_val(inst_id).is_some(), "{}", inst_id);
+            assert!(get_inst_id_code(inst_id).is_some(), "{}", inst_id);
+        }
+        assert_eq!(get_sz("NOPE-USD-SWAP"), None);
+        assert_eq!(get_quantity_sz("NOPE-USD-SWAP", "1"), None);
+        // 币本位 BTC-USD-SWAP 面值 100 USD，1000 USD 为 10 张
+        assert_eq!(get_quantity_sz("BTC-USD-SWAP", "1000").unwrap().parse::<f64>().unwrap(), 10.0);
+    }
 }
 
-/// 返回类似 "2020-12-08T09:08:57.715Z" 的 UTC 时间字符串
+/// 返回类似 "2020-12-08T09:08:57.715Z" 的 UTC 时间字符串，已按服务器时钟校准
 pub fn utc_now_iso() -> String {
-    // 获取当前 UTC 时间，精度到毫秒
-    let now: DateTime<Utc> = Utc::now();
-    // 格式化：固定使用 3 位毫秒 + Z
-    now.format("%Y-%m-%dT%H:%M:%S.%3fZ").to_string()
+    SERVER_CLOCK.now_iso()
 }
 pub fn sign(timestamp: &str, method: &str, path: &str, body: &str, secret_key: &str) -> String {
     // 拼接：timestamp + method + requestPath + body
@@ -312,12 +367,8 @@ pub fn sign(timestamp: &str, method: &str, path: &str, body: &str, secret_key: &
 }
 
 
-pub fn get_inst_id_code(inst_id: &str) -> String {
-    get_swap_instrument(inst_id).unwrap().inst_id_code.to_string()
-}
-
-pub fn order_id_str(inst_id: &str,side:&str,price:&str,order_type:&str)-> String {
-    format!("{}-{}-{}-{}",inst_id,side,price,order_type)
+pub fn get_inst_id_code(inst_id: &str) -> Option<String> {
+    INSTRUMENT_REGISTRY.get(inst_id).map(|instrument| instrument.inst_id_code.to_string())
 }
 
 pub const WS_FILE_PATH: &str = "data/input.txt";
@@ -328,6 +379,46 @@ pub fn read_ws_file() -> Lines<BufReader<File>> {
     reader.lines()
 }
 
+/// 先写临时文件再 rename，进程在写入中途退出也不会留下半个文件
+pub fn write_atomic<P: AsRef<Path>>(path: P, bytes: &[u8]) -> std::io::Result<()> {
+    write_atomic_with(path.as_ref(), bytes, |tmp| File::create(tmp))
+}
+
+/// 同 write_atomic，但临时文件创建时就只有本人可读写（unix 600），用于保存密钥
+///
+/// 先删掉上次残留的临时文件，保证新建时的权限生效，rename 后目标文件保持该权限
+pub fn write_atomic_private<P: AsRef<Path>>(path: P, bytes: &[u8]) -> std::io::Result<()> {
+    write_atomic_with(path.as_ref(), bytes, |tmp| {
+        match std::fs::remove_file(tmp) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(tmp)
+    })
+}
+
+fn write_atomic_with(path: &Path, bytes: &[u8], create: impl FnOnce(&Path) -> std::io::Result<File>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = Path::new(&tmp);
+    let mut file = create(tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
 #[cfg(test)]
 mod test {
     #[tokio::test]
@@ -343,6 +434,7 @@ mod test {
             println!("{:?}",okx_msg)
         }
     }
+    use std::collections::HashMap;
     use std::io::BufRead;
 use std::fs::{read, write};
     use std::path::Path;
@@ -420,7 +512,7 @@ use std::fs::{read, write};
         let mut map_book_vec:HashMap<(String, u64, u64),Vec<u64>> = HashMap::new();
         let book_json_vec = from_reader::<BufReader<File>, Vec<BookData>>(BufReader::new(File::open("data/books.json").unwrap())).unwrap();
         let book_data = book_json_vec.into_iter().last().unwrap();
-        let vec_asks = book_data.asks.into_iter().map(|vec_str| (price_to_tick_int_str(vec_str.get(0).unwrap(), get_sz(inst_id).unwrap()),price_to_tick_int_str(vec_str.get(1).unwrap(), get_min_sz(inst_id).unwrap()))).collect::<Vec<(u64,u64)>>();
+        let vec_asks = book_data.asks.into_iter().map(|vec_str| (price_to_tick_int_str(vec_str.get(0).unwrap(), &get_sz(inst_id).unwrap()),price_to_tick_int_str(vec_str.get(1).unwrap(), &get_min_sz(inst_id).unwrap()))).collect::<Vec<(u64,u64)>>();
         // let vec_bids = book_data.bids;
         let max_price = vec_asks.iter().map(|(price, _)| { price }).max().unwrap();
         let min_price = vec_asks.iter().map(|(price, _)| { price }).min().unwrap();
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use log::info;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sonic_rs::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use crate::common::config::AppConfig;
+use crate::common::credentials::{check_permissions, encrypted_paths, unlock, CredentialError, Secret, SecretEntry};
+use crate::common::utils::write_atomic_private;
+
+/// 加密文件格式版本
+pub const VAULT_VERSION: u32 = 1;
+/// 非交互环境下提供解锁密码的环境变量
+pub const VAULT_PASSPHRASE_ENV: &str = "OKX_VAULT_PASSPHRASE";
+const KDF_ARGON2ID: &str = "argon2id";
+/// 绑定到密文的附加数据，防止把其他用途的密文当作凭证文件
+const AAD: &[u8] = b"okx-credentials-vault";
+
+/// Argon2id 参数，m_cost 单位为 KiB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// OWASP 推荐的 Argon2id 最低配置：19 MiB，2 次迭代
+    pub const DEFAULT: KdfParams = KdfParams { m_cost: 19 * 1024, t_cost: 2, p_cost: 1 };
+}
+
+/// 加密凭证文件，明文为 profile -> SecretEntry 的 JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: String,
+    #[serde(flatten)]
+    params: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(passphrase: &Secret, salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; 32]>, CredentialError> {
+    let argon_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32)).map_err(|e| CredentialError::Crypto(e.to_string()))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params)
+        .hash_password_into(passphrase.expose().as_bytes(), salt, key.as_mut())
+        .map_err(|e| CredentialError::Crypto(e.to_string()))?;
+    Ok(key)
+}
+
+/// 每次加密使用新的 salt 和 nonce
+pub fn encrypt(entries: &BTreeMap<String, SecretEntry>, passphrase: &Secret, params: KdfParams) -> Result<String, CredentialError> {
+    if passphrase.is_empty() {
+        return Err(CredentialError::Crypto("密码不能为空".to_string()));
+    }
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let key = derive_key(passphrase, &salt, params)?;
+    let plaintext = Zeroizing::new(sonic_rs::to_string(entries).map_err(|e| CredentialError::Crypto(e.to_string()))?);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext.as_bytes(), aad: AAD })
+        .map_err(|e| CredentialError::Crypto(e.to_string()))?;
+    let file = VaultFile {
+        version: VAULT_VERSION,
+        kdf: KDF_ARGON2ID.to_string(),
+        params,
+        salt: BASE64_STANDARD.encode(salt),
+        nonce: BASE64_STANDARD.encode(nonce),
+        ciphertext: BASE64_STANDARD.encode(ciphertext),
+    };
+    sonic_rs::to_string_pretty(&file).map_err(|e| CredentialError::Crypto(e.to_string()))
+}
+
+/// 解密失败（密码错误或被篡改）返回 Decrypt
+pub fn decrypt(path: &Path, text: &str, passphrase: &Secret) -> Result<BTreeMap<String, SecretEntry>, CredentialError> {
+    let parse_error = |message: String| CredentialError::Parse { path: path.to_path_buf(), message };
+    let file = sonic_rs::from_str::<VaultFile>(text).map_err(|e| parse_error(e.to_string()))?;
+    if file.version > VAULT_VERSION || file.kdf != KDF_ARGON2ID {
+        return Err(parse_error(format!("不支持的版本 {} / kdf {}", file.version, file.kdf)));
+    }
+    let decode = |value: &str| BASE64_STANDARD.decode(value).map_err(|e| parse_error(e.to_string()));
+    let (salt, nonce, ciphertext) = (decode(&file.salt)?, decode(&file.nonce)?, decode(&file.ciphertext)?);
+    if nonce.len() != 12 {
+        return Err(parse_error(format!("nonce 长度 {} 不是 12", nonce.len())));
+    }
+    let key = derive_key(passphrase, &salt, file.params)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: AAD })
+        .map(Zeroizing::new)
+        .map_err(|_| CredentialError::Decrypt(path.to_path_buf()))?;
+    sonic_rs::from_slice::<BTreeMap<String, SecretEntry>>(&plaintext).map_err(|e| parse_error(e.to_string()))
+}
+
+pub fn read_vault(path: &Path, passphrase: &Secret) -> Result<BTreeMap<String, SecretEntry>, CredentialError> {
+    check_permissions(path)?;
+    let text = std::fs::read_to_string(path).map_err(|error| CredentialError::Io { path: path.to_path_buf(), error })?;
+    decrypt(path, &text, passphrase)
+}
+
+/// 原子写入，临时文件创建时即为 600，写入过程中也不会被其他用户读到
+pub fn write_vault(path: &Path, entries: &BTreeMap<String, SecretEntry>, passphrase: &Secret, params: KdfParams) -> Result<(), CredentialError> {
+    let text = encrypt(entries, passphrase, params)?;
+    write_atomic_private(path, text.as_bytes()).map_err(|error| CredentialError::Io { path: path.to_path_buf(), error })
+}
+
+/// 读取密码：优先环境变量 OKX_VAULT_PASSPHRASE，否则在终端输入（不回显）
+pub fn read_passphrase(prompt: &str) -> Result<Secret, CredentialError> {
+    if let Ok(passphrase) = std::env::var(VAULT_PASSPHRASE_ENV) {
+        return Ok(Secret::new(passphrase));
+    }
+    prompt_secret(prompt)
+}
+
+fn prompt_secret(prompt: &str) -> Result<Secret, CredentialError> {
+    rpassword::prompt_password(prompt)
+        .map(Secret::new)
+        .map_err(|e| CredentialError::Crypto(format!("读取输入失败: {}", e)))
+}
+
+/// 新密码需要输入两次
+fn prompt_new_passphrase() -> Result<Secret, CredentialError> {
+    let passphrase = prompt_secret("新密码: ")?;
+    if prompt_secret("再次输入新密码: ")? != passphrase {
+        return Err(CredentialError::Crypto("两次输入的密码不一致".to_string()));
+    }
+    Ok(passphrase)
+}
+
+fn prompt_line(prompt: &str) -> Result<String, CredentialError> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush().ok();
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| CredentialError::Crypto(format!("读取输入失败: {}", e)))?;
+    Ok(line.trim().to_string())
+}
+
+/// 启动时解锁配置中所有加密凭证文件
+pub fn unlock_configured(config: &AppConfig) -> Result<(), CredentialError> {
+    for path in encrypted_paths(&config.credentials) {
+        let passphrase = read_passphrase(&format!("解锁凭证文件 {} 的密码: ", path.display()))?;
+        let profiles = unlock(&path, &passphrase)?;
+        info!("已解锁 {} 中的凭证 {:?}", path.display(), profiles);
+    }
+    Ok(())
+}
+
+const CLI_USAGE: &str = "用法:
+  credentials add <path> <profile>   添加或更换 profile 的 API key（文件不存在时创建）
+  credentials remove <path> <profile>
+  credentials rotate <path>          更换文件密码
+  credentials list <path>";
+
+/// cargo run -- credentials <add|remove|rotate|list> ...，args 为 credentials 之后的参数
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let arg = |i: usize| args.get(i).map(|s| s.as_str()).ok_or(CLI_USAGE);
+    let path = Path::new(arg(1)?);
+    match arg(0)? {
+        "add" => {
+            let profile = arg(2)?;
+            let (mut entries, passphrase) = if path.exists() {
+                let passphrase = read_passphrase(&format!("{} 的密码: ", path.display()))?;
+                (read_vault(path, &passphrase)?, passphrase)
+            } else {
+                (BTreeMap::new(), prompt_new_passphrase()?)
+            };
+            let entry = SecretEntry {
+                api_key: prompt_line("API key: ")?,
+                secret_key: prompt_secret("Secret key: ")?,
+                passphrase: prompt_secret("API passphrase: ")?,
+            };
+            if entry.api_key.is_empty() || entry.secret_key.is_empty() || entry.passphrase.is_empty() {
+                return Err("API key / secret key / passphrase 都不能为空".into());
+            }
+            entries.insert(profile.to_string(), entry);
+            write_vault(path, &entries, &passphrase, KdfParams::DEFAULT)?;
+            println!("已保存 {} 到 {}", profile, path.display());
+        }
+        "remove" => {
+            let profile = arg(2)?;
+            let passphrase = read_passphrase(&format!("{} 的密码: ", path.display()))?;
+            let mut entries = read_vault(path, &passphrase)?;
+            if entries.remove(profile).is_none() {
+                return Err(format!("{} 中没有 {}", path.display(), profile).into());
+            }
+            write_vault(path, &entries, &passphrase, KdfParams::DEFAULT)?;
+            println!("已从 {} 删除 {}", path.display(), profile);
+        }
+        "rotate" => {
+            let passphrase = read_passphrase(&format!("{} 的当前密码: ", path.display()))?;
+            let entries = read_vault(path, &passphrase)?;
+            write_vault(path, &entries, &prompt_new_passphrase()?, KdfParams::DEFAULT)?;
+            println!("已更换 {} 的密码", path.display());
+        }
+        "list" => {
+            let passphrase = read_passphrase(&format!("{} 的密码: ", path.display()))?;
+            for (profile, entry) in read_vault(path, &passphrase)? {
+                println!("{} api_key {}", profile, entry.api_key);
+            }
+        }
+        _ => return Err(CLI_USAGE.into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod vault_test {
+    use super::*;
+
+    /// 测试用的低成本参数
+    const FAST: KdfParams = KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 };
+
+    #[test]
+    fn test_round_trip() {
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            "main".to_string(),
+            SecretEntry { api_key: "key".to_string(), secret_key: Secret::new("secret"), passphrase: Secret::new("pass") },
+        );
+        let passphrase = Secret::new("correct horse");
+        let text = encrypt(&entries, &passphrase, FAST).unwrap();
+        assert!(!text.contains("secret") && !text.contains("pass\""));
+        let path = Path::new("vault.json");
+        assert_eq!(decrypt(path, &text, &passphrase).unwrap(), entries);
+        assert!(matches!(decrypt(path, &text, &Secret::new("wrong")), Err(CredentialError::Decrypt(_))));
+        // 同样的明文每次加密结果不同
+        assert_ne!(encrypt(&entries, &passphrase, FAST).unwrap(), text);
+
+        let file = std::env::temp_dir().join(format!("okx_vault_test_{}.json", std::process::id()));
+        write_vault(&file, &entries, &passphrase, FAST).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(unlock(&file, &passphrase).unwrap(), vec!["main".to_string()]);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(format!("{:?}", entries["main"].secret_key), "***");
+    }
+}
@@ -1,15 +1,27 @@
 use crate::common::config::*;
 use log::info;
 use sonic_rs::{json, Deserialize, Serialize};
-use time::OffsetDateTime;
 use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{client_async_tls, connect_async, MaybeTlsStream, WebSocketStream};
 use tokio_tungstenite::tungstenite::protocol::frame::coding::Data;
-use crate::common::utils::sign;
+use crate::common::account::{get_pos_mode, PosMode};
+use crate::common::clock::SERVER_CLOCK;
+use crate::common::credentials::{CredentialError, Credentials, CREDENTIAL_STORE};
+use crate::common::proxy::PROXY;
+use crate::common::algo::{CHANNEL_ALGO_ADVANCE, CHANNEL_ORDERS_ALGO};
+use crate::common::utils::send_str;
+use futures::{SinkExt, StreamExt};
+use futures::stream::{SplitSink, SplitStream};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::error::SendError;
+use tokio_tungstenite::tungstenite::Message;
 
 pub async fn create_ws(url: &str) ->Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn std::error::Error>>{
-    // 创建连接并获取 WebSocket 流
-    let (ws_stream, response) = connect_async(url).await?;
+    // 创建连接并获取 WebSocket 流，配置了代理时先建立隧道再握手
+    let (ws_stream, response) = match PROXY.as_ref() {
+        Some(proxy) => client_async_tls(url, proxy.connect_url(url).await?).await?,
+        None => connect_async(url).await?,
+    };
     // Print the response status
     info!("ws_url:{} Response status: {}",url,response.status().as_str());
     Ok(ws_stream)
@@ -19,13 +31,58 @@ pub async fn create_ws(url: &str) ->Result<WebSocketStream<MaybeTlsStream<TcpStr
 pub struct OkxMessage {
     pub event: Option<String>,
     pub arg: Option<Arg>,
+    /// WS 交易操作（order / cancel-order 等）的回执会带上 op 字段
+    pub op: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_code_as_string")]
+    pub code: Option<String>,
+    pub msg: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Arg {
     pub channel: String,
-    #[serde(rename = "instId")]
+    /// 私有频道（orders 等）按 instType 订阅时没有 instId
+    #[serde(rename = "instId", default)]
     pub inst_id: String,
+    #[serde(rename = "instType", default)]
+    pub inst_type: String,
+}
+
+/// 频道推送的通用结构：arg + data 数组
+#[derive(Debug, Deserialize)]
+pub struct ChannelData<T> {
+    pub arg: Arg,
+    pub data: Vec<T>,
+}
+
+/// WS 交易操作的回执，例如
+/// {"id":"1512","op":"order","data":[{"clOrdId":"","ordId":"12345689","tag":"","sCode":"0","sMsg":""}],"code":"0","msg":""}
+#[derive(Debug, Deserialize)]
+pub struct OpResponse {
+    #[serde(default)]
+    pub id: String,
+    pub op: String,
+    #[serde(default, deserialize_with = "deserialize_code_as_string")]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub msg: String,
+    #[serde(default)]
+    pub data: Vec<OpAckData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpAckData {
+    #[serde(default)]
+    pub cl_ord_id: String,
+    #[serde(default)]
+    pub ord_id: String,
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub s_code: String,
+    #[serde(default)]
+    pub s_msg: String,
 }
 #[derive(Debug, Deserialize)]
 pub struct Ticker {
@@ -149,6 +206,16 @@ pub struct TickerData {
     pub ts: String,         // 时间戳
 }
 
+/// mark-price 频道推送
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MarkPriceData {
+    pub inst_type: String,
+    pub inst_id: String,
+    pub mark_px: String,
+    pub ts: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ChannelBboTbt {
     pub arg: Arg,
@@ -164,6 +231,30 @@ pub const CHANNEL_TICKERS: &str = "tickers";
 pub const CHANNEL_BOOKS: &str = "books";
 pub const CHANNEL_BOOKS5: &str = "books5";
 pub const CHANNEL_BBO_TBT: &str = "bbo-tbt";
+/// 公共频道：产品信息，订阅后先推送全量，之后只推送有变化的产品
+pub const CHANNEL_INSTRUMENTS: &str = "instruments";
+/// 公共频道：系统维护状态
+pub const CHANNEL_STATUS: &str = "status";
+/// 公共频道：标记价格，现货没有
+pub const CHANNEL_MARK_PRICE: &str = "mark-price";
+/// 私有频道：订单
+pub const CHANNEL_ORDERS: &str = "orders";
+/// 私有频道：持仓
+pub const CHANNEL_POSITIONS: &str = "positions";
+/// 私有频道：账户余额
+pub const CHANNEL_ACCOUNT: &str = "account";
+/// 私有频道：账户余额和持仓（成交、交割等事件触发）
+pub const CHANNEL_BALANCE_AND_POSITION: &str = "balance_and_position";
+
+pub struct InstType;
+impl InstType {
+    pub const SPOT: &'static str = "SPOT";
+    pub const MARGIN: &'static str = "MARGIN";
+    pub const SWAP: &'static str = "SWAP";
+    pub const FUTURES: &'static str = "FUTURES";
+    pub const OPTION: &'static str = "OPTION";
+    pub const ANY: &'static str = "ANY";
+}
 
 pub fn subscribe(channel: &str,inst_id: &str)->String{
     json!({
@@ -175,18 +266,204 @@ pub fn subscribe(channel: &str,inst_id: &str)->String{
     }).to_string()
 }
 
+/// 一条消息订阅多个产品，inst_ids 可以直接用 InstrumentRegistry::query_ids 的结果
+pub fn subscribe_many<S: AsRef<str>>(channel: &str, inst_ids: &[S]) -> String {
+    let args = inst_ids
+        .iter()
+        .map(|inst_id| json!({"channel": channel, "instId": inst_id.as_ref()}))
+        .collect::<Vec<_>>();
+    json!({
+        "op": "subscribe",
+        "args": args
+    }).to_string()
+}
+
+pub fn unsubscribe(channel: &str, inst_id: &str) -> String {
+    json!({
+        "op": "unsubscribe",
+        "args": [{
+            "channel": channel,
+            "instId": inst_id
+        }]
+    }).to_string()
+}
+
+/// 订阅按产品类型推送的公共频道，instruments 需要 instType，status 不需要
+pub fn subscribe_inst_type(channel: &str, inst_type: Option<&str>) -> String {
+    subscribe_private(channel, inst_type, None)
+}
+
+/// 订阅私有频道，orders / positions 需要 instType，account / balance_and_position 不需要
+pub fn subscribe_private(channel: &str, inst_type: Option<&str>, inst_id: Option<&str>) -> String {
+    let mut arg = json!({
+        "channel": channel
+    });
+    if let Some(inst_type) = inst_type {
+        arg["instType"] = json!(inst_type);
+    }
+    if let Some(inst_id) = inst_id {
+        arg["instId"] = json!(inst_id);
+    }
+    json!({
+        "op": "subscribe",
+        "args": [arg]
+    }).to_string()
+}
+
+
+/// K 线频道的周期，频道名为 candle{bar}、mark-price-candle{bar}、index-candle{bar}
+pub const CANDLE_BARS: [&str; 21] = [
+    "1s", "1m", "3m", "5m", "15m", "30m", "1H", "2H", "4H",
+    "6H", "12H", "1D", "2D", "3D", "1W", "1M", "3M",
+    "6Hutc", "12Hutc", "1Dutc", "1Wutc",
+];
+/// 业务频道：全部成交
+pub const CHANNEL_TRADES_ALL: &str = "trades-all";
+/// 业务频道：充值、提币信息
+pub const CHANNEL_DEPOSIT_INFO: &str = "deposit-info";
+pub const CHANNEL_WITHDRAWAL_INFO: &str = "withdrawal-info";
+
+/// 是否为 K 线频道（candle1m、mark-price-candle1H、index-candle1D 等）
+pub fn is_candle_channel(channel: &str) -> bool {
+    ["mark-price-candle", "index-candle", "candle"]
+        .iter()
+        .find_map(|prefix| channel.strip_prefix(prefix))
+        .is_some_and(|bar| CANDLE_BARS.contains(&bar))
+}
+
+/// 三类 WebSocket 连接，频道只能在所属的连接上订阅
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WsEndpoint {
+    /// /ws/v5/public：行情、产品、系统状态
+    Public,
+    /// /ws/v5/private：订单、持仓、账户，需要登录
+    Private,
+    /// /ws/v5/business：K 线、全部成交、策略委托等，策略委托需要登录
+    Business,
+}
+
+impl WsEndpoint {
+    /// 频道所在的连接，未列出的频道按公共频道处理
+    pub fn of_channel(channel: &str) -> WsEndpoint {
+        match channel {
+            CHANNEL_ORDERS | CHANNEL_POSITIONS | CHANNEL_ACCOUNT | CHANNEL_BALANCE_AND_POSITION
+            | "fills" | "liquidation-warning" | "account-greeks" => WsEndpoint::Private,
+            CHANNEL_ORDERS_ALGO | CHANNEL_ALGO_ADVANCE | CHANNEL_TRADES_ALL
+            | CHANNEL_DEPOSIT_INFO | CHANNEL_WITHDRAWAL_INFO => WsEndpoint::Business,
+            _ if is_candle_channel(channel) || channel.starts_with("grid-") || channel.starts_with("sprd-") => WsEndpoint::Business,
+            _ => WsEndpoint::Public,
+        }
+    }
+
+    /// 当前配置下的地址
+    pub fn url(&self) -> &'static str {
+        match self {
+            WsEndpoint::Public => get_ws_public(),
+            WsEndpoint::Private => get_ws_private(),
+            WsEndpoint::Business => get_ws_business(),
+        }
+    }
+
+    /// WsSession::connect 是否在连接后登录
+    pub fn needs_login(&self) -> bool {
+        !matches!(self, WsEndpoint::Public)
+    }
+}
+
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// 一条 WebSocket 连接，私有和业务连接建立后先发送登录
+///
+/// 登录结果以 event = login 推送，需要登录的频道应在收到登录成功后再订阅
+pub struct WsSession {
+    pub endpoint: WsEndpoint,
+    stream: WsStream,
+}
+
+impl WsSession {
+    /// 连接配置中的地址，需要登录的连接使用默认凭证
+    pub async fn connect(endpoint: WsEndpoint) -> Result<WsSession, Box<dyn std::error::Error>> {
+        let credentials = match endpoint.needs_login() {
+            true => Some(CREDENTIAL_STORE.default_credentials()?),
+            false => None,
+        };
+        WsSession::connect_url(endpoint, endpoint.url(), credentials.as_deref()).await
+    }
+
+    /// 使用指定凭证连接，credentials 为 None 时不登录（业务连接只订阅 K 线时可以不登录）
+    pub async fn connect_with(endpoint: WsEndpoint, credentials: Option<&Credentials>) -> Result<WsSession, Box<dyn std::error::Error>> {
+        WsSession::connect_url(endpoint, endpoint.url(), credentials).await
+    }
+
+    pub async fn connect_url(endpoint: WsEndpoint, url: &str, credentials: Option<&Credentials>) -> Result<WsSession, Box<dyn std::error::Error>> {
+        let mut stream = create_ws(url).await?;
+        if let Some(credentials) = credentials {
+            stream.send(send_str(&login_with(credentials))).await?;
+        }
+        Ok(WsSession { endpoint, stream })
+    }
+
+    pub fn split(self) -> (SplitSink<WsStream, Message>, SplitStream<WsStream>) {
+        self.stream.split()
+    }
+}
+
+/// 按频道把订阅发到对应连接的发送通道
+///
+/// 私有连接和业务连接上需要登录的频道，要等登录成功后再经由路由发送
+#[derive(Clone)]
+pub struct WsRouter {
+    public: Sender<String>,
+    private: Sender<String>,
+    business: Sender<String>,
+}
+
+impl WsRouter {
+    pub fn new(public: Sender<String>, private: Sender<String>, business: Sender<String>) -> WsRouter {
+        WsRouter { public, private, business }
+    }
+
+    pub fn sender(&self, endpoint: WsEndpoint) -> &Sender<String> {
+        match endpoint {
+            WsEndpoint::Public => &self.public,
+            WsEndpoint::Private => &self.private,
+            WsEndpoint::Business => &self.business,
+        }
+    }
+
+    /// 发送已拼好的消息，channel 决定走哪条连接
+    pub async fn send(&self, channel: &str, message: String) -> Result<(), SendError<String>> {
+        self.sender(WsEndpoint::of_channel(channel)).send(message).await
+    }
+
+    pub async fn subscribe_many<S: AsRef<str>>(&self, channel: &str, inst_ids: &[S]) -> Result<(), SendError<String>> {
+        self.send(channel, subscribe_many(channel, inst_ids)).await
+    }
+
+    pub async fn subscribe_private(&self, channel: &str, inst_type: Option<&str>, inst_id: Option<&str>) -> Result<(), SendError<String>> {
+        self.send(channel, subscribe_private(channel, inst_type, inst_id)).await
+    }
+}
+
+/// 使用默认凭证（credentials.profile）登录
+pub fn login()->Result<String, CredentialError>{
+    let credentials = CREDENTIAL_STORE.default_credentials()?;
+    Ok(login_with(&credentials))
+}
 
-pub fn login()->String{
-    let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+/// 使用指定凭证登录，多账户时每个私有连接各用一组凭证
+pub fn login_with(credentials: &Credentials)->String{
+    // 用校准后的服务器时间，避免本地时钟漂移导致 60004 / 50102
+    let timestamp = SERVER_CLOCK.now_secs();
 
-    let sign  = sign(timestamp.to_string().as_str(), "GET", "/users/self/verify", "",get_secret_key());
+    let sign  = credentials.sign(timestamp.to_string().as_str(), "GET", "/users/self/verify", "");
         json!({
  "op": "login",
  "args":
   [
      {
-       "apiKey": get_api_key(),
-       "passphrase": get_passphrase(),
+       "apiKey": credentials.api_key.as_str(),
+       "passphrase": credentials.passphrase.expose(),
        "timestamp": timestamp,
        "sign":sign
       }
@@ -210,25 +487,49 @@ pub fn login()->String{
 // ).to_string()
 // }
 
+/// 下单（开仓），id 同时作为 WS 请求 id 和 clOrdId，需由 ClOrdIdGenerator 生成
+/// posSide 按当前账户的持仓模式填写
 pub fn order(id: &str, side: &str, inst_id: &str, td_mode: &str, ord_type: &str, px: Option<&str>, sz: &str) ->String{
+    let arg = order_arg(side, inst_id, td_mode, ord_type, px, sz, false);
+    json!({
+        "id": id,
+        "op": "order",
+        "args": [with_cl_ord_id(arg, id)]
+    }).to_string()
+}
+
+/// 下单（平仓），双向持仓下 posSide 取被平的方向，单向持仓下设置 reduceOnly
+pub fn order_close(id: &str, side: &str, inst_id: &str, td_mode: &str, ord_type: &str, px: Option<&str>, sz: &str) ->String{
+    let arg = order_arg(side, inst_id, td_mode, ord_type, px, sz, true);
+    json!({
+        "id": id,
+        "op": "order",
+        "args": [with_cl_ord_id(arg, id)]
+    }).to_string()
+}
+
+fn with_cl_ord_id(mut arg: sonic_rs::Value, cl_ord_id: &str) -> sonic_rs::Value {
+    arg["clOrdId"] = json!(cl_ord_id);
+    arg
+}
+
+fn order_arg(side: &str, inst_id: &str, td_mode: &str, ord_type: &str, px: Option<&str>, sz: &str, close: bool) -> sonic_rs::Value {
+    let pos_mode = get_pos_mode();
     let mut arg = json!({
         "side": side,
         "instId": inst_id,
         "tdMode": td_mode,
         "ordType": ord_type,
         "sz": sz,
-        "posSide": "net"
+        "posSide": pos_mode.pos_side(side, close)
     });
-
+    if close && pos_mode == PosMode::Net {
+        arg["reduceOnly"] = json!(true);
+    }
     if let Some(price) = px {
         arg["px"] = json!(price);
     }
-
-    json!({
-        "id": id,
-        "op": "order",
-        "args": [arg]
-    }).to_string()
+    arg
 }
 
 pub struct TdMode;
@@ -248,13 +549,15 @@ impl Side {
     pub const BUY: &'static str = "buy";
     pub const SELL: &'static str = "sell";
 }
+/// 市价开仓，posSide 按当前账户的持仓模式填写
 pub fn order_market(id: &str, side: &str, inst_id: &str,sz: &str)->String{
-    let pos_side = if side == Side::BUY { "long" } else { "short" };
-    order_market_with_pos(id, side, inst_id, sz, pos_side)
+    order_market_with_pos(id, side, inst_id, sz, get_pos_mode().pos_side(side, false))
 }
 
+/// 市价下单，id 同时作为 WS 请求 id 和 clOrdId
 pub fn order_market_with_pos(id: &str, side: &str, inst_id: &str, sz: &str, pos_side: &str)->String{
     let arg = json!({
+        "clOrdId": id,
         "side": side,
         "instId": inst_id,
         "tdMode": TdMode::CROSS,
@@ -327,9 +630,7 @@ use tokio_tungstenite::tungstenite::Message::Text;
 
     use std::fs::File;
     use std::path::Path;
-    use crate::common::config::{get_ws_private, get_ws_public, OK_SIMULATION_ACCESS_PASSPHRASE};
-    use crate::common::config::OKX_SIMULATION_SECRET_KEY;
-    use crate::common::config::OKX_SIMULATION_API_KEY;
+    use crate::common::config::{get_ws_private, get_ws_public};
     use futures::{SinkExt, StreamExt};
     use sonic_rs::{json, to_string};
     use time::OffsetDateTime;
@@ -338,6 +639,60 @@ use tokio_tungstenite::tungstenite::Message::Text;
     use crate::common::config::WS_SIMULATION_URL_PRIVATE;
     use crate::common::utils::{log_init, send_str, sign, WS_FILE_PATH};
     use crate::common::ws_api::{create_ws, login, order, subscribe, CHANNEL_BBO_TBT, CHANNEL_BOOKS, CHANNEL_BOOKS5, CHANNEL_TICKERS};
+    use crate::common::ws_api::{WsEndpoint, WsRouter, WsSession, CHANNEL_ORDERS};
+    use crate::common::algo::CHANNEL_ORDERS_ALGO;
+    use crate::common::credentials::{Credentials, Secret};
+
+    #[tokio::test]
+    async fn test_routing() {
+        assert_eq!(WsEndpoint::of_channel(CHANNEL_BOOKS5), WsEndpoint::Public);
+        assert_eq!(WsEndpoint::of_channel(CHANNEL_ORDERS), WsEndpoint::Private);
+        assert_eq!(WsEndpoint::of_channel(CHANNEL_ORDERS_ALGO), WsEndpoint::Business);
+        assert_eq!(WsEndpoint::of_channel("candle1H"), WsEndpoint::Business);
+        assert_eq!(WsEndpoint::of_channel("mark-price-candle1Dutc"), WsEndpoint::Business);
+        assert_eq!(WsEndpoint::of_channel("candle7m"), WsEndpoint::Public);
+        assert!(WsEndpoint::Business.url().ends_with("/ws/v5/business"));
+
+        let (public, mut public_rx) = tokio::sync::mpsc::channel(8);
+        let (private, mut private_rx) = tokio::sync::mpsc::channel(8);
+        let (business, mut business_rx) = tokio::sync::mpsc::channel(8);
+        let router = WsRouter::new(public, private, business);
+        router.subscribe_many("candle1m", &["BTC-USDT-SWAP"]).await.unwrap();
+        router.subscribe_many(CHANNEL_TICKERS, &["BTC-USDT-SWAP"]).await.unwrap();
+        router.subscribe_private(CHANNEL_ORDERS_ALGO, Some("ANY"), None).await.unwrap();
+        router.subscribe_private(CHANNEL_ORDERS, Some("ANY"), None).await.unwrap();
+        assert!(business_rx.recv().await.unwrap().contains("candle1m"));
+        assert!(business_rx.recv().await.unwrap().contains(CHANNEL_ORDERS_ALGO));
+        assert!(public_rx.recv().await.unwrap().contains(CHANNEL_TICKERS));
+        assert!(private_rx.recv().await.unwrap().contains("\"orders\""));
+        assert!(business_rx.try_recv().is_err() && public_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_login() {
+        // 本地 ws:// 服务，把收到的第一条消息转交给测试
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://127.0.0.1:{}/ws/v5/business", listener.local_addr().unwrap().port());
+        let (first_tx, mut first_rx) = tokio::sync::mpsc::channel::<String>(1);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            if let Some(Ok(Message::Text(text))) = ws.next().await {
+                first_tx.send(text.to_string()).await.unwrap();
+            }
+        });
+        let credentials = Credentials {
+            profile: "test".to_string(),
+            api_key: "key".to_string(),
+            secret_key: Secret::new("secret"),
+            passphrase: Secret::new("pass"),
+            read_only: false,
+        };
+        let session = WsSession::connect_url(WsEndpoint::Business, &url, Some(&credentials)).await.unwrap();
+        assert_eq!(session.endpoint, WsEndpoint::Business);
+        let first = first_rx.recv().await.unwrap();
+        assert!(first.contains("\"op\":\"login\"") && first.contains("\"apiKey\":\"key\""), "{}", first);
+    }
 
 //     #[tokio::test]
 //     async fn test_login(){
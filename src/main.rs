@@ -1,41 +1,81 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error;
 use std::fs::File;
-use std::sync::RwLock;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use chrono::Utc;
 use dashmap::DashMap;
 use futures::{SinkExt, StreamExt};
 use futures::stream::{SplitSink, SplitStream};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use once_cell::sync::Lazy;
 use serde::de::Unexpected::Option;
 use sonic_rs::{from_str, JsonValueTrait};
 use sonic_rs::writer::BufferedWriter;
 use tokio::net::TcpStream;
 use tokio::spawn;
-use tokio::sync::mpsc::{channel, unbounded_channel, Receiver};
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender};
 use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 use tokio_tungstenite::tungstenite::Message::Text;
 use tokio_tungstenite::tungstenite::{Bytes, Error, Message, Utf8Bytes};
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use okx::common::config::{get_ws_private, get_ws_public};
+use okx::common::config::{config, get_ws_public, load_config, positional_args};
+use okx::common::cl_ord_id::ClOrdIdGenerator;
+use okx::common::credentials::CREDENTIAL_STORE;
+use okx::common::vault::{run_cli, unlock_configured};
+use okx::common::proxy::Proxy;
+use okx::common::account::{get_pos_mode, load_pos_mode, AccountData, AccountState, BalanceAndPositionData, PositionData};
+use okx::common::fills::{Fill, FillJournal, FILL_JOURNAL_PATH};
+use okx::common::algo::{AlgoOrderData, AlgoOrderStore, CHANNEL_ALGO_ADVANCE, CHANNEL_ORDERS_ALGO};
+use okx::common::clock::SERVER_CLOCK;
+use okx::common::flatten::flatten_all;
+use okx::common::instrument::{Instrument, InstrumentEvent, INSTRUMENT_REGISTRY, REFRESH_INTERVAL_SECS};
+use okx::common::leverage::LeveragePlan;
+use okx::common::order_store::{LocalOrder, OrderData, OrderStore};
+use okx::common::risk::{parse_ws_orders, OrderIntent, PauseReason, RiskGate, RiskLimits, RISK_LIMITS_PATH};
+use okx::common::reconcile::reconcile;
+use okx::common::status::{StatusData, SystemStatus};
+use okx::common::snapshot::{save_snapshot, Snapshot, SNAPSHOT_INTERVAL_SECS, SNAPSHOT_PATH, STRATEGY_STATE};
+use okx::common::rate_limit::{ws_op, WsOp, RATE_LIMITER};
 use okx::common::rest_api::instruments;
-use okx::common::utils::{get_inst_id_code, get_min_sz, get_sz, log_init, order_id_str, price_to_tick_int_str, send_str, tick_int_to_price_str};
-use okx::common::ws_api::{create_ws, login, order, order_market, subscribe, BookData, Books, Books5, OkxMessage, OrderType, Side, Ticker, TickerData, ChannelBboTbt, CHANNEL_BOOKS, CHANNEL_BOOKS5, CHANNEL_TICKERS, CHANNEL_BBO_TBT};
+use okx::common::utils::{get_inst_id_code, get_min_sz, get_sz, log_init, price_to_tick_int_str, str_to_f64, send_str, tick_int_to_price_str};
+use okx::common::ws_api::{create_ws, is_candle_channel, WsEndpoint, WsRouter, WsSession, order, order_close, order_market, subscribe, subscribe_inst_type, subscribe_many, subscribe_private, unsubscribe, BookData, Books, Books5, OkxMessage, OpResponse, ChannelData, InstType, OrderType, Side, Ticker, TickerData, ChannelBboTbt, CHANNEL_BOOKS, CHANNEL_BOOKS5, CHANNEL_TICKERS, CHANNEL_BBO_TBT, CHANNEL_ORDERS, CHANNEL_POSITIONS, CHANNEL_ACCOUNT, CHANNEL_BALANCE_AND_POSITION, CHANNEL_INSTRUMENTS, CHANNEL_STATUS, CHANNEL_MARK_PRICE, MarkPriceData};
 
-static ORDER_COUNTER: AtomicU64 = AtomicU64::new(1);
+static CL_ORD_ID: Lazy<ClOrdIdGenerator> = Lazy::new(ClOrdIdGenerator::default);
 static ASKS: Lazy<DashMap<(String, u64, u64), Vec<u64>>> = Lazy::new(|| {
     DashMap::new()
 });
 static BIDS: Lazy<DashMap<(String, u64, u64), Vec<u64>>> = Lazy::new(|| {
     DashMap::new()
 });
+static ORDER_STORE: Lazy<OrderStore> = Lazy::new(OrderStore::new);
+static ACCOUNT_STATE: Lazy<AccountState> = Lazy::new(AccountState::new);
+static ALGO_ORDER_STORE: Lazy<AlgoOrderStore> = Lazy::new(AlgoOrderStore::new);
+static SYSTEM_STATUS: Lazy<SystemStatus> = Lazy::new(SystemStatus::new);
+static RISK_GATE: Lazy<RiskGate> = Lazy::new(|| {
+    // 配置文件中没有 [risk] 时沿用单独的风控配置文件
+    let limits = config().risk.clone().unwrap_or_else(|| RiskLimits::load_or_default(RISK_LIMITS_PATH));
+    RiskGate::new(limits)
+});
+static FILL_JOURNAL: Lazy<FillJournal> = Lazy::new(|| {
+    FillJournal::open(FILL_JOURNAL_PATH).expect("Failed to open fill journal")
+});
+static LEVERAGE_PLAN: Lazy<LeveragePlan> = Lazy::new(LeveragePlan::new);
+/// 连接的写端，重连后替换为新连接的写端
+type WsSink = Arc<tokio::sync::Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>;
+/// 重连间隔从 1 秒开始翻倍，最长间隔（秒）
+const RECONNECT_MAX_SECS: u64 = 30;
 pub struct TaskFn;
 impl TaskFn {
 
     pub fn print_order(inst_id:&str) {
-        let sz = get_sz(inst_id).unwrap();
-        let min_sz = get_min_sz(inst_id).unwrap();
+        let (Some(sz), Some(min_sz)) = (get_sz(inst_id), get_min_sz(inst_id)) else {
+            warn!("未知产品 {}", inst_id);
+            return;
+        };
+        let (sz, min_sz) = (sz.as_str(), min_sz.as_str());
         let mut asks_key = ASKS.iter().map(|entry| { entry.key().clone() }).collect::<Vec<(String, u64, u64)>>();
         asks_key.sort_by(|(_, p1, _), (_, p2, _)| { p1.cmp(p2) });
         for (i,p1,p2) in asks_key {
@@ -79,10 +119,17 @@ impl TaskFn {
     }
     pub async fn rx_books(mut rx: Receiver<(Utf8Bytes,String,u8)>){
         let mut init_map = HashMap::<String,bool>::new();
+        // tickSz 变化后等待新快照的产品，收到快照后恢复交易
+        let mut rebuilding = HashSet::<String>::new();
         loop {
             match rx.recv().await {
                 Some((b,inst_id,task_id)) => {
-                    let sz = get_sz(&inst_id).unwrap();
+                    // 价格按 tickSz 转为整数索引，tickSz 可能在运行中变化，从注册表读取最新值
+                    let Some(instrument) = INSTRUMENT_REGISTRY.get(&inst_id) else {
+                        error!("未知产品 {}", inst_id);
+                        continue;
+                    };
+                    let sz = &instrument.tick_sz;
                     match task_id {
                         0 => {
                             let b = from_str::<Books>(&b).unwrap();
@@ -113,6 +160,10 @@ impl TaskFn {
                                         BIDS.insert((inst_id.clone(),min_price.clone(),max_price.clone()),vec_price);
                                         break
                                     }
+                                    if rebuilding.remove(&inst_id) {
+                                        info!("{} 盘口已按 tickSz {} 重建", inst_id, sz);
+                                        RISK_GATE.resume(&inst_id, PauseReason::BookRebuild);
+                                    }
                                     init_map.insert(inst_id,true);
                                 }
                                 "update" =>{
@@ -140,6 +191,13 @@ impl TaskFn {
                                 // }
                             };
                         },
+                        3 => {
+                            // tickSz 变化：旧的价格索引失效，清空后等待重新订阅的快照
+                            ASKS.retain(|(id, _, _), _| *id != inst_id);
+                            BIDS.retain(|(id, _, _), _| *id != inst_id);
+                            init_map.remove(&inst_id);
+                            rebuilding.insert(inst_id);
+                        },
                         _ => {}
                     }
 
@@ -152,10 +210,11 @@ impl TaskFn {
     }
 
     fn books_update(inst_id: &String, sz: &String,asks: Vec<Vec<String>>, bids: Vec<Vec<String>>) {
+        let min_sz = get_min_sz(inst_id).unwrap_or_default();
         let mut asks_p_v = asks.into_iter().map(|vec_str| as_bs_to_pv(vec_str, sz)).collect::<Vec<(u64, u64)>>();
         asks_p_v.sort_by(|a,b| a.1.cmp(&b.1));
         if asks_p_v.len() > 0 {
-            info!("asks 价格：{} 数量：{}", tick_int_to_price_str(asks_p_v[0].0,sz),tick_int_to_price_str(asks_p_v[0].1,get_min_sz(inst_id).unwrap()));
+            info!("asks 价格：{} 数量：{}", tick_int_to_price_str(asks_p_v[0].0,sz),tick_int_to_price_str(asks_p_v[0].1,&min_sz));
         }
         // let mut keys = ASKS.iter().map(|entry| entry.key().clone()).collect::<Vec<(String, u64, u64)>>();
 
@@ -215,7 +274,7 @@ impl TaskFn {
         let mut bids_p_v = bids.into_iter().map(|vec_str| as_bs_to_pv(vec_str, sz)).collect::<Vec<(u64, u64)>>();
         bids_p_v.sort_by(|(a1,b1), (a2,b2)| a1.cmp(&a2).reverse());
         if bids_p_v.len()>0 {
-            info!("bids 价格：{} 数量：{}", tick_int_to_price_str(bids_p_v[0].0,sz),tick_int_to_price_str(bids_p_v[0].1,get_min_sz(inst_id).unwrap()));
+            info!("bids 价格：{} 数量：{}", tick_int_to_price_str(bids_p_v[0].0,sz),tick_int_to_price_str(bids_p_v[0].1,&min_sz));
         }
 
         // let mut keys = BIDS.iter().map(|entry| entry.key().clone()).collect::<Vec<(String, u64, u64)>>();
@@ -273,18 +332,356 @@ impl TaskFn {
         // }
     }
 
-    pub async fn rx_order(mut rx:Receiver<String>, mut tx_ws: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>){
+    /// 连接的发送队列：交易类 op 按 instId 分到各自的限速队列，一个产品被限速不会阻塞其他产品的撤单
+    ///
+    /// login / subscribe 等不在限速表中的消息直接写入连接
+    pub async fn rx_order(mut rx:Receiver<String>, tx_ws: WsSink){
+        let (tx_send, rx_send) = channel::<String>(512);
+        spawn(Self::ws_writer(rx_send, tx_ws));
+        let mut lanes: HashMap<String, Sender<(String, WsOp, u64)>> = HashMap::new();
         while let Some(b) = rx.recv().await {
-            if let Err( e) = tx_ws.send(send_str(&b)).await{
+            // 所有下单消息都在这里过风控，不经过 submit_order 直接发到通道的也一样
+            if let Some(parsed) = parse_ws_orders(&b) {
+                let checked = match &parsed {
+                    Ok(orders) => orders.iter().try_for_each(|o| RISK_GATE.check_submitted(o, &ACCOUNT_STATE, &ORDER_STORE)),
+                    Err(reject) => Err(reject.clone()),
+                };
+                if let Err(reject) = checked {
+                    // 批量下单任何一笔不通过整批不发
+                    for o in parsed.iter().flatten() {
+                        ORDER_STORE.reject_local(&o.cl_ord_id, &reject.to_string());
+                    }
+                    error!("风控拒绝发送 {} {}", b, reject);
+                    continue;
+                }
+            }
+            let Some(op) = ws_op(&b).filter(|op| RATE_LIMITER.is_limited(&op.op)) else {
+                if tx_send.send(b).await.is_err() {
+                    error!("ws writer closed");
+                    return;
+                }
+                continue;
+            };
+            // 改单在入队时登记，排在后面的同一订单改单会让前面还在等待的那次放弃
+            let generation = op.coalesce().map(|key| RATE_LIMITER.supersede(&key)).unwrap_or(0);
+            let lane = lanes.entry(op.inst_id.clone().unwrap_or_default()).or_insert_with(|| {
+                let (tx_lane, rx_lane) = channel::<(String, WsOp, u64)>(256);
+                spawn(Self::throttle_lane(rx_lane, tx_send.clone()));
+                tx_lane
+            });
+            if lane.send((b, op, generation)).await.is_err() {
+                error!("throttle lane closed");
+            }
+        }
+    }
+
+    /// 单个产品的限速队列，保证同一产品的操作按顺序发送
+    async fn throttle_lane(mut rx: Receiver<(String, WsOp, u64)>, tx_send: Sender<String>) {
+        while let Some((b, op, generation)) = rx.recv().await {
+            if let Err(e) = RATE_LIMITER.acquire_ws(&op, generation).await {
+                error!("限速 {} {}", b, e);
+                if let Some(Ok(orders)) = parse_ws_orders(&b) {
+                    for o in orders.iter() {
+                        ORDER_STORE.reject_local(&o.cl_ord_id, &e.to_string());
+                    }
+                }
+                continue;
+            }
+            if tx_send.send(b).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn ws_writer(mut rx: Receiver<String>, tx_ws: WsSink) {
+        while let Some(b) = rx.recv().await {
+            if let Err( e) = tx_ws.lock().await.send(send_str(&b)).await{
                 error!("发送失败 {} {}",b,e);
             }
         }
     }
-    pub async fn rx_ws_order(mut rx_order_ws: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>){
+    /// 处理产品变化事件：非 live 或下线的产品暂停交易，tickSz 变化时重建盘口
+    pub async fn instrument_events(mut rx: broadcast::Receiver<InstrumentEvent>, tx_public: Sender<String>, book_tx: Sender<(Utf8Bytes,String,u8)>) {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("产品事件积压，丢失 {} 条", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            match event {
+                InstrumentEvent::StateChanged { inst_id, new, .. } => {
+                    if new == "live" {
+                        RISK_GATE.resume(&inst_id, PauseReason::State);
+                    } else {
+                        RISK_GATE.pause(&inst_id, PauseReason::State, &new);
+                    }
+                }
+                InstrumentEvent::Listed(instrument) => RISK_GATE.resume(&instrument.inst_id, PauseReason::Delisted),
+                InstrumentEvent::Delisted(instrument) => RISK_GATE.pause(&instrument.inst_id, PauseReason::Delisted, ""),
+                InstrumentEvent::TickSizeChanged { inst_id, old, new } => {
+                    // 只处理本地有盘口的产品
+                    if !ASKS.iter().any(|e| e.key().0 == inst_id) && !BIDS.iter().any(|e| e.key().0 == inst_id) {
+                        continue;
+                    }
+                    RISK_GATE.pause(&inst_id, PauseReason::BookRebuild, &format!("tickSz {} -> {}", old, new));
+                    let resubscribe = [unsubscribe(CHANNEL_BOOKS, &inst_id), subscribe(CHANNEL_BOOKS, &inst_id)];
+                    if book_tx.send((Utf8Bytes::from_static(""), inst_id, 3)).await.is_err() {
+                        error!("book channel closed");
+                        break;
+                    }
+                    for msg in resubscribe {
+                        if tx_public.send(msg).await.is_err() {
+                            error!("public channel closed");
+                            return;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// status 频道：影响交易的维护进行中时暂停交易的产品，全部结束后恢复
+    fn on_status(text: &str, inst_ids: &[String]) {
+        let status = match from_str::<ChannelData<StatusData>>(text) {
+            Ok(status) => status,
+            Err(e) => {
+                error!("解析维护推送失败 {} {}", e, text);
+                return;
+            }
+        };
+        for event in SYSTEM_STATUS.on_status(&status.data) {
+            if !event.affects_trading {
+                continue;
+            }
+            for inst_id in inst_ids.iter() {
+                if event.state.is_active() {
+                    RISK_GATE.pause(inst_id, PauseReason::Maintenance, &event.title);
+                } else if event.state.is_finished() && !SYSTEM_STATUS.trading_halted() {
+                    RISK_GATE.resume(inst_id, PauseReason::Maintenance);
+                }
+            }
+        }
+    }
+
+    /// 策略下单入口：风控预检通过后生成 clOrdId、登记到订单存储并放入下单通道，返回 clOrdId
+    ///
+    /// 预检用于立即返回拒单原因，发送前 rx_order 还会再检查一次
+    pub async fn submit_order(tx_order: &Sender<String>, tag: &str, intent: OrderIntent) -> Result<String, Box<dyn error::Error>> {
+        RISK_GATE.check(&intent, &ACCOUNT_STATE, &ORDER_STORE)?;
+        let cl_ord_id = CL_ORD_ID.next(tag)?;
+        // reduce_only 的意图按平仓下单，posSide / reduceOnly 由持仓模式决定
+        let build = if intent.reduce_only { order_close } else { order };
+        let msg = build(&cl_ord_id, &intent.side, &intent.inst_id, &intent.td_mode, &intent.ord_type, intent.px.as_deref(), &intent.sz);
+        ORDER_STORE.track_submit(&cl_ord_id, LocalOrder::pending(&cl_ord_id, &intent.inst_id, &intent.side, &intent.ord_type, intent.px.as_deref(), &intent.sz));
+        tx_order.send(msg).await?;
+        Ok(cl_ord_id)
+    }
+
+    /// 启动时从快照恢复当日盈亏、订单号序号、未终结订单和策略状态，当日盈亏再按成交日志重算
+    pub fn restore_snapshot() {
+        match Snapshot::load(SNAPSHOT_PATH) {
+            Ok(Some(snapshot)) => {
+                STRATEGY_STATE.restore(snapshot.restore(&ORDER_STORE, &RISK_GATE, &CL_ORD_ID));
+            }
+            Ok(None) => info!("快照 {} 不存在", SNAPSHOT_PATH),
+            Err(e) => warn!("读取快照失败 {}", e),
+        }
+        // 快照之后记入日志的成交不在快照的当日盈亏里，日志有今天的成交时以日志重算为准
+        let today_start = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp_millis() as u64;
+        let fills = FILL_JOURNAL.query_pnl(today_start, u64::MAX);
+        if !fills.is_empty() {
+            RISK_GATE.rebuild_daily_pnl(&fills);
+        }
+    }
+
+    pub fn save_snapshot() {
+        save_snapshot(SNAPSHOT_PATH, &ORDER_STORE, &RISK_GATE, &CL_ORD_ID, STRATEGY_STATE.state());
+    }
+
+    pub async fn snapshot_loop() {
+        let mut interval = tokio::time::interval(Duration::from_secs(SNAPSHOT_INTERVAL_SECS));
+        // 第一次 tick 立即返回，跳过
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            Self::save_snapshot();
+            // 快照之后清理已终结的订单和策略委托，避免订单表在进程生命周期内无限增长
+            let purged = ORDER_STORE.purge_terminal();
+            if purged > 0 {
+                info!("清理已终结订单 {} 条", purged);
+            }
+            let purged = ALGO_ORDER_STORE.purge_terminal();
+            if purged > 0 {
+                info!("清理已终结策略委托 {} 条", purged);
+            }
+        }
+    }
+
+    pub async fn rx_ws_order(mut rx_order_ws: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>, tx_order: Sender<String>){
         while let Some(b) = rx_order_ws.next().await {
-            match b {
-                Ok(Text(s)) => {
-                    info!("{}",s.as_str());
+            if let Ok(Text(s)) = b {
+                Self::private_message(&s, &tx_order).await;
+            }
+        }
+    }
+
+    pub async fn rx_ws_business(mut rx_business_ws: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>, tx_business: Sender<String>){
+        while let Some(b) = rx_business_ws.next().await {
+            if let Ok(Text(s)) = b {
+                Self::business_message(&s, &tx_business).await;
+            }
+        }
+    }
+
+    /// 私有 / 业务连接断开后重连：新连接带登录，登录成功后由消息处理重新订阅并对账，
+    /// 不需要登录的订阅（业务连接上的 K 线）在重连后按 resubscribe 重新发送
+    pub async fn ws_session(endpoint: WsEndpoint, mut rx_ws: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>, tx_ws: WsSink, tx_channel: Sender<String>, resubscribe: Vec<String>) {
+        loop {
+            match endpoint {
+                WsEndpoint::Business => Self::rx_ws_business(rx_ws, tx_channel.clone()).await,
+                _ => Self::rx_ws_order(rx_ws, tx_channel.clone()).await,
+            }
+            let mut delay = 1;
+            rx_ws = loop {
+                warn!("{:?} 连接断开，{} 秒后重连", endpoint, delay);
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+                // 错误不是 Send，先转成字符串再 await
+                let session = WsSession::connect(endpoint).await.map_err(|e| e.to_string());
+                match session {
+                    Ok(session) => break Self::replace_sink(session, &tx_ws).await,
+                    Err(e) => error!("{:?} 重连失败 {}", endpoint, e),
+                }
+                delay = (delay * 2).min(RECONNECT_MAX_SECS);
+            };
+            info!("{:?} 已重连", endpoint);
+            for sub in resubscribe.iter() {
+                if tx_channel.send(sub.clone()).await.is_err() {
+                    error!("{:?} channel closed", endpoint);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn replace_sink(session: WsSession, tx_ws: &WsSink) -> SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+        let (sink, stream) = session.split();
+        *tx_ws.lock().await = sink;
+        stream
+    }
+
+    /// 处理业务连接上的消息：登录后订阅策略委托频道，推送写入 ALGO_ORDER_STORE
+    async fn business_message(text: &str, tx_business: &Sender<String>) {
+        let Ok(msg) = from_str::<OkxMessage>(text) else {
+            info!("{}", text);
+            return;
+        };
+        if let Some(event) = msg.event {
+            info!("{}", text);
+            if event == "login" && msg.code.as_deref() == Some("0") {
+                for channel in [CHANNEL_ORDERS_ALGO, CHANNEL_ALGO_ADVANCE] {
+                    if tx_business.send(subscribe_private(channel, Some(InstType::ANY), None)).await.is_err() {
+                        error!("business channel closed");
+                        return;
+                    }
+                }
+            }
+            return;
+        }
+        if let Some(arg) = msg.arg {
+            match arg.channel.as_str() {
+                CHANNEL_ORDERS_ALGO | CHANNEL_ALGO_ADVANCE => match from_str::<ChannelData<AlgoOrderData>>(text) {
+                    Ok(algos) => {
+                        for algo in algos.data.iter() {
+                            info!("策略委托 {} {} {} {}", algo.inst_id, algo.algo_id, algo.ord_type, algo.state);
+                        }
+                        ALGO_ORDER_STORE.on_update(&algos.data);
+                    }
+                    Err(e) => error!("解析策略委托推送失败 {} {}", e, text),
+                },
+                channel if is_candle_channel(channel) => debug!("{}", text),
+                _ => {}
+            }
+        }
+    }
+
+    /// 处理私有连接上的消息：登录事件、交易操作回执、orders 频道推送
+    async fn private_message(text: &str, tx_order: &Sender<String>) {
+        let Ok(msg) = from_str::<OkxMessage>(text) else {
+            info!("{}", text);
+            return;
+        };
+        if let Some(event) = msg.event {
+            info!("{}", text);
+            if event == "login" && msg.code.as_deref() == Some("0") {
+                // 登录成功后订阅订单、持仓和账户频道
+                let subscribes = [
+                    subscribe_private(CHANNEL_ORDERS, Some(InstType::ANY), None),
+                    subscribe_private(CHANNEL_POSITIONS, Some(InstType::ANY), None),
+                    subscribe_private(CHANNEL_ACCOUNT, None, None),
+                    subscribe_private(CHANNEL_BALANCE_AND_POSITION, None, None),
+                ];
+                for sub in subscribes {
+                    if tx_order.send(sub).await.is_err() {
+                        error!("order channel closed");
+                        return;
+                    }
+                }
+                // 启动和重连后都会重新登录，登录成功后对账，补上断线期间漏掉的推送
+                spawn(async {
+                    reconcile(&ORDER_STORE, &ALGO_ORDER_STORE, &ACCOUNT_STATE, &CL_ORD_ID, &FILL_JOURNAL, &RISK_GATE).await;
+                });
+            }
+            return;
+        }
+        if msg.op.is_some() {
+            match from_str::<OpResponse>(text) {
+                Ok(resp) => ORDER_STORE.on_op_response(&resp),
+                Err(e) => error!("解析回执失败 {} {}", e, text),
+            }
+            return;
+        }
+        if let Some(arg) = msg.arg {
+            match arg.channel.as_str() {
+                CHANNEL_ORDERS => match from_str::<ChannelData<OrderData>>(text) {
+                    Ok(orders) => {
+                        for order in orders.data.iter() {
+                            info!("订单 {} {} {} {} accFillSz {} avgPx {}", order.inst_id, order.ord_id, order.cl_ord_id, order.state, order.acc_fill_sz, order.avg_px);
+                            ORDER_STORE.on_order_update(order);
+                            if !CL_ORD_ID.is_ours(&order.cl_ord_id) {
+                                ORDER_STORE.mark_foreign(&order.cl_ord_id, &order.ord_id);
+                            }
+                            if let Some(fill) = Fill::from_order_data(order) {
+                                match FILL_JOURNAL.record(fill.clone()) {
+                                    Ok(Some(pnl)) => RISK_GATE.record_fill(&fill, pnl),
+                                    Ok(None) => {}
+                                    Err(e) => error!("写入成交日志失败 {}", e),
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => error!("解析订单推送失败 {} {}", e, text),
+                },
+                CHANNEL_POSITIONS => match from_str::<ChannelData<PositionData>>(text) {
+                    Ok(positions) => {
+                        ACCOUNT_STATE.on_positions(&positions.data);
+                        // 持仓推送带标记价格，补充没有订阅 mark-price 的产品
+                        for p in positions.data.iter().filter(|p| str_to_f64(&p.mark_px) > 0.0) {
+                            RISK_GATE.update_mark(&p.inst_id, str_to_f64(&p.mark_px));
+                        }
+                    }
+                    Err(e) => error!("解析持仓推送失败 {} {}", e, text),
+                },
+                CHANNEL_ACCOUNT => match from_str::<ChannelData<AccountData>>(text) {
+                    Ok(account) => ACCOUNT_STATE.on_account(&account.data),
+                    Err(e) => error!("解析账户推送失败 {} {}", e, text),
+                },
+                CHANNEL_BALANCE_AND_POSITION => match from_str::<ChannelData<BalanceAndPositionData>>(text) {
+                    Ok(bp) => ACCOUNT_STATE.on_balance_and_position(&bp.data),
+                    Err(e) => error!("解析余额持仓推送失败 {} {}", e, text),
                 },
                 _ => {}
             }
@@ -294,23 +691,122 @@ impl TaskFn {
 
 #[tokio::main]
 async fn main() ->Result<(), Box<dyn error::Error>>{
+    // 配置错误时列出所有问题后退出，此时日志还未初始化
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    // 管理加密凭证文件：cargo run -- credentials <add|remove|rotate|list> ...
+    let args = positional_args(std::env::args().skip(1));
+    if args.first().map(|s| s.as_str()) == Some("credentials") {
+        if let Err(e) = run_cli(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
     log_init();
+    info!("环境 {} 站点 {} REST {} 产品 {:?}", config.environment, config.endpoints.region, config.rest_url(), config.instruments);
+    // 代理地址或密码环境变量有误时直接退出，之后 PROXY 不会再失败
+    if let Err(e) = Proxy::from_config(&config.proxy) {
+        error!("{}", e);
+        std::process::exit(2);
+    }
+    // 凭证缺失时在启动阶段直接退出，不等到第一次签名；加密凭证先解锁
+    if let Err(e) = unlock_configured(config).and_then(|_| CREDENTIAL_STORE.default_credentials()) {
+        error!("{}", e);
+        std::process::exit(2);
+    }
+    // 签名和登录使用服务器时间，先校准一次再定时刷新
+    if let Err(e) = SERVER_CLOCK.sync().await {
+        warn!("时间同步失败，使用本地时钟: {}", e);
+    }
+    spawn(SERVER_CLOCK.sync_loop());
+    SERVER_CLOCK.set_request_expiry(config.request_expiry_ms);
+    // 紧急清仓：cargo run -- flatten
+    if args.first().map(|s| s.as_str()) == Some("flatten") {
+        let report = flatten_all().await;
+        println!("{}", report);
+        return Ok(());
+    }
+    // 下单的 posSide 依赖持仓模式，获取失败时按单向持仓处理
+    if let Err(e) = load_pos_mode().await {
+        warn!("获取持仓模式失败，按 {} 处理: {}", get_pos_mode(), e);
+    }
+    TaskFn::restore_snapshot();
+    spawn(TaskFn::snapshot_loop());
+    spawn(async {
+        // Ctrl-C 退出前保存快照
+        if tokio::signal::ctrl_c().await.is_ok() {
+            TaskFn::save_snapshot();
+            std::process::exit(0);
+        }
+    });
+    // 启动时立即刷新一次产品列表，之后定时刷新并写回缓存
+    spawn(INSTRUMENT_REGISTRY.refresh_loop(
+        vec![(InstType::SPOT, None), (InstType::SWAP, None), (InstType::FUTURES, None)],
+        REFRESH_INTERVAL_SECS,
+    ));
+    let inst_ids = &config.instruments;
+    // 交易开始前按计划设置杠杆
+    for inst_id in inst_ids {
+        LEVERAGE_PLAN.declare(inst_id, &config.strategy.mgn_mode, config.strategy.lever)?;
+    }
+    // 杠杆没有设置成功的产品暂停交易，不按错误的杠杆开仓
+    for (inst_id, result) in LEVERAGE_PLAN.apply().await {
+        if let Err(e) = result {
+            RISK_GATE.pause(&inst_id, PauseReason::Leverage, &e);
+        }
+    }
     let ws = create_ws(get_ws_public()).await?;
-
-    let ws_order = create_ws(get_ws_private()).await?;
-    let (mut tx_order_ws, rx_order_ws) = ws_order.split();
-    tx_order_ws.send(send_str(&login())).await.unwrap();
-    let inst_id = "ETH-USDT-SWAP";
-    let (mut tx, mut rx) = ws.split();
-    tx.send(send_str(subscribe(CHANNEL_BOOKS,inst_id ).as_str())).await?;
-    tx.send(send_str(subscribe(CHANNEL_TICKERS,inst_id).as_str())).await?;
-    tx.send(send_str(subscribe(CHANNEL_BOOKS5,inst_id).as_str())).await?;
+    // 私有和业务连接建立后先用默认凭证登录，登录成功后再订阅各自的频道
+    let (tx_order_ws, rx_order_ws) = WsSession::connect(WsEndpoint::Private).await?.split();
+    let (tx_business_ws, rx_business_ws) = WsSession::connect(WsEndpoint::Business).await?.split();
+    let (tx, mut rx) = ws.split();
+    // 公共连接的发送也走通道，产品事件处理需要重新订阅盘口
+    let (tx_public_channel, rx_public_channel) = channel::<String>(64);
+    let (tx_order_channel,rx_order_channel) = channel::<String>(512);
+    let (tx_business_channel, rx_business_channel) = channel::<String>(64);
+    let tx_order_ws: WsSink = Arc::new(tokio::sync::Mutex::new(tx_order_ws));
+    let tx_business_ws: WsSink = Arc::new(tokio::sync::Mutex::new(tx_business_ws));
+    spawn(TaskFn::rx_order(rx_public_channel, Arc::new(tokio::sync::Mutex::new(tx))));
+    spawn(TaskFn::rx_order(rx_order_channel, tx_order_ws.clone()));
+    spawn(TaskFn::rx_order(rx_business_channel, tx_business_ws.clone()));
+    // 配置的行情频道按所属连接订阅，K 线走业务连接
+    let router = WsRouter::new(tx_public_channel.clone(), tx_order_channel.clone(), tx_business_channel.clone());
+    for channel in &config.channels {
+        router.subscribe_many(channel, inst_ids).await?;
+    }
+    // 标记价格作为风控价格检查的参考价，不依赖 channels 中是否有 tickers / books5
+    let marked = inst_ids
+        .iter()
+        .filter(|inst_id| match INSTRUMENT_REGISTRY.get(inst_id) {
+            Some(instrument) => instrument.inst_type != InstType::SPOT,
+            // 不在注册表中时按 instId 判断，BTC-USDT 为现货
+            None => inst_id.matches('-').count() >= 2,
+        })
+        .collect::<Vec<_>>();
+    if !marked.is_empty() {
+        router.subscribe_many(CHANNEL_MARK_PRICE, &marked).await?;
+    }
+    tx_public_channel.send(subscribe_inst_type(CHANNEL_INSTRUMENTS, Some(InstType::SWAP))).await?;
+    tx_public_channel.send(subscribe_inst_type(CHANNEL_STATUS, None)).await?;
     // tx.send(send_str(subscribe(CHANNEL_BBO_TBT,inst_id).as_str())).await?;
     let (book_channel_tx,book_channel_rx) = channel::<(Utf8Bytes,String,u8)>(512);
-    let (tx_order_channel,rx_order_channel) = channel::<String>(512);
     spawn(TaskFn::rx_books(book_channel_rx));
-    spawn(TaskFn::rx_order(rx_order_channel,tx_order_ws));
-    spawn(TaskFn::rx_ws_order(rx_order_ws));
+    spawn(TaskFn::instrument_events(INSTRUMENT_REGISTRY.subscribe(), tx_public_channel.clone(), book_channel_tx.clone()));
+    // 业务连接上的 K 线订阅不依赖登录，重连后直接重发
+    let business_subscribes = config
+        .channels
+        .iter()
+        .filter(|channel| WsEndpoint::of_channel(channel) == WsEndpoint::Business)
+        .map(|channel| subscribe_many(channel, inst_ids))
+        .collect::<Vec<_>>();
+    spawn(TaskFn::ws_session(WsEndpoint::Private, rx_order_ws, tx_order_ws, tx_order_channel.clone(), Vec::new()));
+    spawn(TaskFn::ws_session(WsEndpoint::Business, rx_business_ws, tx_business_ws, tx_business_channel.clone(), business_subscribes));
 
     // let mut is_send_order = false;
     loop {
@@ -346,11 +842,36 @@ async fn main() ->Result<(), Box<dyn error::Error>>{
                                                 };
                                             }
                                             CHANNEL_TICKERS=>{
+                                                // 买一卖一作为风控价格检查的参考价
+                                                if let Ok(tickers) = from_str::<Ticker>(&text) {
+                                                    for t in tickers.data.iter() {
+                                                        RISK_GATE.update_bbo(&t.inst_id, str_to_f64(&t.bid_px), str_to_f64(&t.ask_px));
+                                                    }
+                                                }
                                                 // let tickers = from_str::<Ticker>(&text).unwrap();
                                                 // info!("{}",tickers.data.first().unwrap().last);
                                                 // TaskFn::print_order(inst_id);
 
                                             }
+                                            CHANNEL_INSTRUMENTS=>{
+                                                match from_str::<ChannelData<Instrument>>(&text) {
+                                                    Ok(instruments) => {
+                                                        INSTRUMENT_REGISTRY.apply_updates(instruments.data);
+                                                    }
+                                                    Err(e) => error!("解析产品推送失败 {}", e),
+                                                }
+                                            }
+                                            CHANNEL_STATUS=>{
+                                                TaskFn::on_status(&text, inst_ids);
+                                            }
+                                            CHANNEL_MARK_PRICE=>{
+                                                // 没有买一卖一时风控用标记价格做价格检查
+                                                if let Ok(marks) = from_str::<ChannelData<MarkPriceData>>(&text) {
+                                                    for m in marks.data.iter() {
+                                                        RISK_GATE.update_mark(&m.inst_id, str_to_f64(&m.mark_px));
+                                                    }
+                                                }
+                                            }
                                             CHANNEL_BBO_TBT=>{
                                                 if book_channel_tx.send((text,args.inst_id.clone(),2)).await.is_err(){
                                                     error!("book channel closed");
@@ -372,6 +893,7 @@ async fn main() ->Result<(), Box<dyn error::Error>>{
             }
         }
     }
+    TaskFn::save_snapshot();
     Ok(())
 }
 
@@ -392,7 +914,9 @@ fn as_bs_to_pv( vec_str: Vec<String>,sz:&str) -> (u64, u64) {
 mod test {
     use std::io::{BufRead, BufReader};
     use std::path::Path;
+    use okx::common::config::get_ws_private;
     use okx::common::utils::{get_quantity_sz, WS_FILE_PATH};
+    use okx::common::ws_api::login;
     use super::*;
     #[tokio::test]
     async fn read_test() {
@@ -449,7 +973,7 @@ mod test {
     #[tokio::test]
     async fn quantity_test() {
         let inst_id = "BTC-USDT-SWAP";
-        println!("{}", get_quantity_sz(inst_id, "1.0"));
+        println!("{:?}", get_quantity_sz(inst_id, "1.0"));
     }
 
 
@@ -459,10 +983,10 @@ mod test {
         let ws_order = create_ws(get_ws_private()).await?;
         let (mut tx, mut rx) = ws_order.split();
         let inst_id = "BTC-USDT-SWAP";
-        let order_id = ORDER_COUNTER.fetch_add(1, Ordering::Relaxed).to_string();
+        let order_id = CL_ORD_ID.next("test")?;
 
-        let market_order = order_market(&order_id, Side::BUY, inst_id, &get_quantity_sz(inst_id, "1.0"));
-        tx.send(send_str(login().as_str())).await?;
+        let market_order = order_market(&order_id, Side::BUY, inst_id, &get_quantity_sz(inst_id, "1.0").unwrap());
+        tx.send(send_str(login()?.as_str())).await?;
         let mut is_send_order = false;
         loop {
             let result = rx.next().await.unwrap();